@@ -0,0 +1,300 @@
+//! Async client of the filebox http api.
+//!
+//! ```no_run
+//! # async fn demo() -> Result<(), filebox_client::Error> {
+//! let client = filebox_client::Client::new("http://127.0.0.1:8888");
+//! let created = client
+//!     .upload("notes.txt", "hello", &filebox_client::UploadOptions::default())
+//!     .await?;
+//! let info = client.info(&created.code).await?;
+//! let content = client.take(&info.code).await?.bytes().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::Path;
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use reqwest::{header, RequestBuilder, Response, StatusCode};
+
+pub use filebox_types::*;
+pub use reqwest::Body;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("{0}")]
+    Api(ApiError),
+}
+
+/// A failure reported by the server.
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{} ({}){}", .response.message, .response.error, remaining_hint(*.visit_error_remaining))]
+pub struct ApiError {
+    pub kind: ErrorKind,
+    pub status: StatusCode,
+    /// The `ErrorResponse` body, or one made up from the status when the
+    /// body is not json.
+    pub response: ErrorResponse,
+    /// Date of an `X-IP-*-LIMIT` header, to be sent back until it expires.
+    pub limit_flag: Option<String>,
+    /// Value of `X-IP-VISIT-ERROR-REMAINING`.
+    pub visit_error_remaining: Option<u32>,
+}
+
+fn remaining_hint(remaining: Option<u32>) -> String {
+    match remaining {
+        Some(remaining) => format!(", {remaining} wrong codes left today"),
+        None => String::new(),
+    }
+}
+
+/// The `error` name of an `ErrorResponse`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    NotFound,
+    AlreadyTaken,
+    Expired,
+    InvalidCode,
+    InvalidFileType,
+    InvalidInput,
+    IpVisitErrorLimit,
+    IpUploadLimit,
+    IpDenied,
+    Unauthorized,
+    RateLimited,
+    Unavailable,
+    Internal,
+    /// A name unknown to this client.
+    Other(String),
+}
+
+impl ErrorKind {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "NOT_FOUND" => ErrorKind::NotFound,
+            "ALREADY_TAKEN" => ErrorKind::AlreadyTaken,
+            "EXPIRED" => ErrorKind::Expired,
+            "INVALID_CODE" => ErrorKind::InvalidCode,
+            "INVALID_FILE_TYPE" => ErrorKind::InvalidFileType,
+            "INPUT_VALIDATE_ERROR" | "VALIDATE_ARGS_ERROR" => ErrorKind::InvalidInput,
+            "IP_VISIT_ERROR_LIMIT" => ErrorKind::IpVisitErrorLimit,
+            "IP_UPLOAD_LIMIT" => ErrorKind::IpUploadLimit,
+            "IP_DENIED" => ErrorKind::IpDenied,
+            "UNAUTHORIZED" => ErrorKind::Unauthorized,
+            "LIMITER_UNAVAILABLE" => ErrorKind::Unavailable,
+            "IO_ERROR"
+            | "DB_ERROR"
+            | "UNKNOWN"
+            | "MULTIPART_ERROR"
+            | "REDIS_ERROR"
+            | "DESERIALIZE_JSON_ERROR"
+            | "PARSE_GET_REDIS_VALUE"
+            | "REDIS_SEND_COMMAND_ERROR"
+            | "ACTIX_WEB_ERROR" => ErrorKind::Internal,
+            name => ErrorKind::Other(name.to_string()),
+        }
+    }
+
+    /// The kind of a response without an `ErrorResponse` body.
+    fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::NOT_FOUND => ErrorKind::NotFound,
+            StatusCode::CONFLICT => ErrorKind::AlreadyTaken,
+            StatusCode::GONE => ErrorKind::Expired,
+            StatusCode::TOO_MANY_REQUESTS => ErrorKind::RateLimited,
+            StatusCode::UNAUTHORIZED => ErrorKind::Unauthorized,
+            StatusCode::SERVICE_UNAVAILABLE => ErrorKind::Unavailable,
+            status if status.is_server_error() => ErrorKind::Internal,
+            status => ErrorKind::Other(format!("HTTP_{}", status.as_u16())),
+        }
+    }
+}
+
+/// Options of a streamed upload.
+#[derive(Debug, Clone, Default)]
+pub struct UploadOptions {
+    /// Days until the box expires, the server's minimum when unset.
+    pub duration_day: Option<u8>,
+}
+
+/// The content of a taken box, streamed from the response.
+pub struct Download {
+    /// From `Content-Disposition`, without any directory part.
+    pub file_name: Option<String>,
+    pub content_length: Option<u64>,
+    response: Response,
+}
+
+impl Download {
+    pub fn bytes_stream(self) -> impl Stream<Item = Result<Bytes, Error>> {
+        self.response
+            .bytes_stream()
+            .map(|chunk| chunk.map_err(Error::from))
+    }
+
+    pub async fn bytes(self) -> Result<Bytes, Error> {
+        Ok(self.response.bytes().await?)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    server: String,
+}
+
+impl Client {
+    pub fn new(server: impl Into<String>) -> Self {
+        Self::with_http_client(server, reqwest::Client::new())
+    }
+
+    pub fn with_http_client(server: impl Into<String>, http: reqwest::Client) -> Self {
+        Self {
+            http,
+            server: server.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub async fn create_text(
+        &self,
+        req: &CreateTextFileboxRequest,
+    ) -> Result<CreateFileboxResponse, Error> {
+        let url = format!("{}/v1/filebox/text", self.server);
+        let resp = send(self.http.post(url).json(req)).await?;
+        Ok(resp.json().await?)
+    }
+
+    /// Stream `body` into a new file box named `name`.
+    pub async fn upload(
+        &self,
+        name: &str,
+        body: impl Into<Body>,
+        options: &UploadOptions,
+    ) -> Result<CreateFileboxResponse, Error> {
+        let url = format!(
+            "{}/v1/filebox/raw/{}",
+            self.server,
+            utf8_percent_encode(name, NON_ALPHANUMERIC)
+        );
+        let mut req = self
+            .http
+            .put(url)
+            .header(header::ACCEPT, "application/json")
+            .body(body);
+        if let Some(day) = options.duration_day {
+            req = req.header(DURATION_DAY_HEADER, day.to_string());
+        }
+        let resp = send(req).await?;
+        Ok(resp.json().await?)
+    }
+
+    /// Look a box up without taking it.
+    pub async fn info(&self, code: &str) -> Result<GetFileboxResponse, Error> {
+        let url = format!("{}/v1/filebox/{}", self.server, code);
+        let resp = send(self.http.get(url)).await?;
+        Ok(resp.json().await?)
+    }
+
+    /// Take a box, its content is streamed from the returned `Download`.
+    pub async fn take(&self, code: &str) -> Result<Download, Error> {
+        let url = format!("{}/v1/filebox/{}", self.server, code);
+        let resp = send(self.http.post(url)).await?;
+        Ok(Download {
+            file_name: attachment_name(&resp),
+            content_length: resp.content_length(),
+            response: resp,
+        })
+    }
+}
+
+async fn send(req: RequestBuilder) -> Result<Response, Error> {
+    let resp = req.send().await?;
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+
+    let header = |name: &str| {
+        resp.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let limit_flag = header(IP_UPLOAD_LIMIT_HEADER).or_else(|| header(IP_VISIT_ERROR_LIMIT_HEADER));
+    let visit_error_remaining =
+        header(IP_VISIT_ERROR_REMAINING_HEADER).and_then(|value| value.parse().ok());
+
+    let text = resp.text().await?;
+    let (kind, response) = match serde_json::from_str::<ErrorResponse>(&text) {
+        Ok(response) => (ErrorKind::from_name(&response.error), response),
+        Err(_) => {
+            let kind = ErrorKind::from_status(status);
+            let response = ErrorResponse {
+                code: status.as_u16(),
+                error: format!("HTTP_{}", status.as_u16()),
+                message: text,
+            };
+            (kind, response)
+        }
+    };
+    Err(Error::Api(ApiError {
+        kind,
+        status,
+        response,
+        limit_flag,
+        visit_error_remaining,
+    }))
+}
+
+/// The file name of a `Content-Disposition: attachment; filename*=...`
+/// response, without any directory part.
+fn attachment_name(resp: &Response) -> Option<String> {
+    let disposition = resp
+        .headers()
+        .get(header::CONTENT_DISPOSITION)?
+        .to_str()
+        .ok()?;
+    let encoded = disposition
+        .split(';')
+        .find_map(|param| param.trim().strip_prefix("filename*="))?;
+    let (_charset, value) = encoded.split_once("''")?;
+    let name = percent_decode_str(value).decode_utf8_lossy();
+    let name = Path::new(name.as_ref()).file_name()?;
+    Some(name.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_kind_should_map_names() {
+        assert_eq!(ErrorKind::from_name("NOT_FOUND"), ErrorKind::NotFound);
+        assert_eq!(
+            ErrorKind::from_name("ALREADY_TAKEN"),
+            ErrorKind::AlreadyTaken
+        );
+        assert_eq!(
+            ErrorKind::from_name("INPUT_VALIDATE_ERROR"),
+            ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            ErrorKind::from_name("IP_UPLOAD_LIMIT"),
+            ErrorKind::IpUploadLimit
+        );
+        assert_eq!(ErrorKind::from_name("DB_ERROR"), ErrorKind::Internal);
+        assert_eq!(
+            ErrorKind::from_name("SOMETHING_NEW"),
+            ErrorKind::Other("SOMETHING_NEW".to_string())
+        );
+        assert_eq!(
+            ErrorKind::from_status(StatusCode::TOO_MANY_REQUESTS),
+            ErrorKind::RateLimited
+        );
+    }
+}
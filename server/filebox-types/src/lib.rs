@@ -0,0 +1,149 @@
+//! Wire types of the filebox http api, shared by the server and its clients.
+
+use serde::{
+    de::{self, Unexpected},
+    Deserialize, Serialize,
+};
+
+#[cfg(feature = "openapi")]
+use utoipa::ToSchema;
+
+pub const IP_UPLOAD_LIMIT_HEADER: &str = "X-IP-UPLOAD-LIMIT";
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+pub const DURATION_DAY_HEADER: &str = "X-Filebox-Duration-Day";
+pub const MAX_DOWNLOADS_HEADER: &str = "X-Filebox-Max-Downloads";
+pub const IP_VISIT_ERROR_LIMIT_HEADER: &str = "X-IP-VISIT-ERROR-LIMIT";
+pub const IP_VISIT_ERROR_REMAINING_HEADER: &str = "X-IP-VISIT-ERROR-REMAINING";
+/// Format of the dates in the `X-IP-*-LIMIT` headers.
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Json body of `POST /v1/filebox/text`, the same text box as the multipart
+/// form with `file_type` 2.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct CreateTextFileboxRequest {
+    /// At most 50 characters.
+    pub name: String,
+    /// 1 to 2000 characters.
+    pub text: String,
+    /// Days until the box expires.
+    pub duration_day: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct GetFileboxResponse {
+    pub id: i64,
+    pub code: String,
+    pub name: String,
+    pub file_type: FileboxFileType,
+    /// Unix timestamp in seconds.
+    pub created_at: i64,
+    pub expired_at: i64,
+    pub used_at: Option<i64>,
+    /// The same times in RFC 3339, in UTC.
+    pub created_at_rfc3339: String,
+    pub expired_at_rfc3339: String,
+    pub used_at_rfc3339: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct CreateFileboxResponse {
+    pub id: i64,
+    pub code: String,
+    pub name: String,
+    pub file_type: FileboxFileType,
+    /// Unix timestamp in seconds.
+    pub created_at: i64,
+    pub expired_at: i64,
+    /// The same times in RFC 3339, in UTC.
+    pub created_at_rfc3339: String,
+    pub expired_at_rfc3339: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(ToSchema))]
+pub struct ErrorResponse {
+    pub code: u16,
+    pub error: String,
+    pub message: String,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum FileboxFileType {
+    File = 1,
+    Text = 2,
+}
+
+// 序列化为数字, derive 生成的是字符串枚举, 所以手写
+#[cfg(feature = "openapi")]
+impl<'s> ToSchema<'s> for FileboxFileType {
+    fn schema() -> (
+        &'s str,
+        utoipa::openapi::RefOr<utoipa::openapi::schema::Schema>,
+    ) {
+        use utoipa::openapi::{ObjectBuilder, SchemaType};
+
+        let schema = ObjectBuilder::new()
+            .schema_type(SchemaType::Integer)
+            .enum_values(Some([1, 2]))
+            .description(Some("1 = file, 2 = text"))
+            .build();
+        ("FileboxFileType", schema.into())
+    }
+}
+
+impl From<&FileboxFileType> for u8 {
+    fn from(v: &FileboxFileType) -> Self {
+        match v {
+            FileboxFileType::File => 1,
+            FileboxFileType::Text => 2,
+        }
+    }
+}
+
+// https://serde.rs/impl-serializer.html
+impl Serialize for FileboxFileType {
+    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        s.serialize_u8(self.into())
+    }
+}
+
+// https://damad.be/joost/blog/rust-serde-deserialization-of-an-enum-variant.html
+impl<'de> Deserialize<'de> for FileboxFileType {
+    fn deserialize<D>(d: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let num = u8::deserialize(d)?;
+        match num {
+            1 => Ok(FileboxFileType::File),
+            2 => Ok(FileboxFileType::Text),
+            // TODO: 修改为指定的json序列化错误
+            // 目前提示为：Json deserialize error: invalid value: integer `3`, expected 1 or 2 at line 3 column 1
+            _ => Err(de::Error::invalid_value(
+                Unexpected::Unsigned(num as u64),
+                &"1 or 2",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filebox_file_type_should_be_a_number() {
+        assert_eq!(serde_json::to_string(&FileboxFileType::File).unwrap(), "1");
+        assert_eq!(
+            serde_json::from_str::<FileboxFileType>("2").unwrap(),
+            FileboxFileType::Text
+        );
+        assert!(serde_json::from_str::<FileboxFileType>("3").is_err());
+    }
+}
@@ -0,0 +1,3 @@
+DROP TABLE filebox_event CASCADE;
+
+DROP TYPE filebox_event_type;
\ No newline at end of file
@@ -0,0 +1,27 @@
+CREATE TYPE filebox_event_type AS ENUM (
+    'created',
+    'viewed',
+    'taken',
+    'expired',
+    'revoked',
+    'lookup_failed'
+);
+
+-- append only, rows are kept when the filebox itself is deleted
+CREATE TABLE
+    IF NOT EXISTS filebox_event (
+        id BIGSERIAL NOT NULL,
+        event_type filebox_event_type NOT NULL,
+        filebox_id BIGINT DEFAULT NULL,
+        code VARCHAR(10) NOT NULL,
+        ip VARCHAR(64) NOT NULL DEFAULT '',
+        user_agent VARCHAR(512) NOT NULL DEFAULT '',
+        created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        CONSTRAINT filebox_event_pkey PRIMARY KEY (id)
+    );
+
+CREATE INDEX filebox_event_code_idx ON filebox_event (code);
+
+CREATE INDEX filebox_event_ip_idx ON filebox_event (ip);
+
+CREATE INDEX filebox_event_created_at_idx ON filebox_event (created_at);
@@ -0,0 +1,5 @@
+DROP TABLE daily_stats;
+
+DROP INDEX filebox_event_filebox_id_idx;
+
+ALTER TABLE filebox_event DROP COLUMN file_type, DROP COLUMN size;
\ No newline at end of file
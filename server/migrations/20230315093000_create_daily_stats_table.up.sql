@@ -0,0 +1,21 @@
+-- what the daily aggregation needs to know once the filebox is deleted
+ALTER TABLE filebox_event
+    ADD COLUMN file_type file_type DEFAULT NULL,
+    ADD COLUMN size BIGINT NOT NULL DEFAULT 0;
+
+CREATE INDEX filebox_event_filebox_id_idx ON filebox_event (filebox_id);
+
+-- one row per day the fileboxes were created on, rebuilt by the aggregation job
+CREATE TABLE
+    IF NOT EXISTS daily_stats (
+        day DATE NOT NULL,
+        created_count BIGINT NOT NULL DEFAULT 0,
+        text_count BIGINT NOT NULL DEFAULT 0,
+        file_count BIGINT NOT NULL DEFAULT 0,
+        total_bytes BIGINT NOT NULL DEFAULT 0,
+        taken_count BIGINT NOT NULL DEFAULT 0,
+        median_pickup_secs DOUBLE PRECISION DEFAULT NULL,
+        expired_count BIGINT NOT NULL DEFAULT 0,
+        updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        CONSTRAINT daily_stats_pkey PRIMARY KEY (day)
+    );
@@ -0,0 +1,8 @@
+ALTER TABLE daily_stats ALTER COLUMN updated_at TYPE TIMESTAMP;
+
+ALTER TABLE filebox_event ALTER COLUMN created_at TYPE TIMESTAMP;
+
+ALTER TABLE filebox
+    ALTER COLUMN created_at TYPE TIMESTAMP,
+    ALTER COLUMN expired_at TYPE TIMESTAMP,
+    ALTER COLUMN used_at TYPE TIMESTAMP;
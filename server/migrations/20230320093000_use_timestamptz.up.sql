@@ -0,0 +1,10 @@
+-- 旧数据是服务端本地时间, 按迁移会话的 TimeZone 解释;
+-- 与服务端写入时的时区不一致时, 先 ALTER DATABASE filebox SET timezone TO '...' 再启动
+ALTER TABLE filebox
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ,
+    ALTER COLUMN expired_at TYPE TIMESTAMPTZ,
+    ALTER COLUMN used_at TYPE TIMESTAMPTZ;
+
+ALTER TABLE filebox_event ALTER COLUMN created_at TYPE TIMESTAMPTZ;
+
+ALTER TABLE daily_stats ALTER COLUMN updated_at TYPE TIMESTAMPTZ;
@@ -0,0 +1,3 @@
+ALTER TABLE filebox_event
+    ALTER COLUMN code TYPE VARCHAR(10) USING left(code, 10),
+    ALTER COLUMN ip TYPE VARCHAR(64) USING left(ip, 64);
@@ -0,0 +1,4 @@
+-- code 与 ip 来自请求, 过长的取件码不能让审计记录写入失败
+ALTER TABLE filebox_event
+    ALTER COLUMN code TYPE TEXT,
+    ALTER COLUMN ip TYPE TEXT;
@@ -0,0 +1,2 @@
+ALTER TABLE filebox
+    ALTER COLUMN name TYPE VARCHAR(30) USING left(name, 30);
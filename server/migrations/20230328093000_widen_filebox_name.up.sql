@@ -0,0 +1,3 @@
+-- 接口允许 50 字节的名称
+ALTER TABLE filebox
+    ALTER COLUMN name TYPE VARCHAR(50);
@@ -0,0 +1,16 @@
+-- file_type 取值 'text' / 'file', 与 postgres 的 file_type 枚举一致
+CREATE TABLE
+    IF NOT EXISTS filebox (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        code VARCHAR(10) NOT NULL,
+        name VARCHAR(30) NOT NULL,
+        size BIGINT NOT NULL DEFAULT 0,
+        file_type TEXT NOT NULL DEFAULT 'file' CHECK (file_type IN ('text', 'file')),
+        file_path VARCHAR(250) NOT NULL DEFAULT '',
+        text VARCHAR(2000) NOT NULL DEFAULT '',
+        created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        expired_at TIMESTAMP NOT NULL,
+        used_at TIMESTAMP DEFAULT NULL
+    );
+
+CREATE INDEX filebox_code_idx ON filebox (code);
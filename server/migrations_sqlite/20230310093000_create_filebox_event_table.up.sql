@@ -0,0 +1,30 @@
+-- append only, rows are kept when the filebox itself is deleted
+CREATE TABLE
+    IF NOT EXISTS filebox_event (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        event_type TEXT NOT NULL CHECK (
+            event_type IN (
+                'created',
+                'viewed',
+                'taken',
+                'expired',
+                'revoked',
+                'lookup_failed'
+            )
+        ),
+        filebox_id BIGINT DEFAULT NULL,
+        code VARCHAR(10) NOT NULL,
+        file_type TEXT DEFAULT NULL CHECK (file_type IN ('text', 'file')),
+        size BIGINT NOT NULL DEFAULT 0,
+        ip VARCHAR(64) NOT NULL DEFAULT '',
+        user_agent VARCHAR(512) NOT NULL DEFAULT '',
+        created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+    );
+
+CREATE INDEX filebox_event_code_idx ON filebox_event (code);
+
+CREATE INDEX filebox_event_ip_idx ON filebox_event (ip);
+
+CREATE INDEX filebox_event_created_at_idx ON filebox_event (created_at);
+
+CREATE INDEX filebox_event_filebox_id_idx ON filebox_event (filebox_id);
@@ -0,0 +1,8 @@
+-- 未配置 redis 时, 通过 /admin 添加的 ip 规则保存在这里
+CREATE TABLE
+    IF NOT EXISTS ip_rule (
+        kind TEXT NOT NULL CHECK (kind IN ('allow', 'deny')),
+        cidr VARCHAR(64) NOT NULL,
+        created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        PRIMARY KEY (kind, cidr)
+    );
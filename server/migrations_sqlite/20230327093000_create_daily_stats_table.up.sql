@@ -0,0 +1,13 @@
+-- one row per day the fileboxes were created on, rebuilt by the aggregation job
+CREATE TABLE
+    IF NOT EXISTS daily_stats (
+        day DATE NOT NULL PRIMARY KEY,
+        created_count BIGINT NOT NULL DEFAULT 0,
+        text_count BIGINT NOT NULL DEFAULT 0,
+        file_count BIGINT NOT NULL DEFAULT 0,
+        total_bytes BIGINT NOT NULL DEFAULT 0,
+        taken_count BIGINT NOT NULL DEFAULT 0,
+        median_pickup_secs DOUBLE PRECISION DEFAULT NULL,
+        expired_count BIGINT NOT NULL DEFAULT 0,
+        updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+    );
@@ -0,0 +1,24 @@
+-- 接口允许 50 字节的名称, SQLite 只能重建表来修改列
+CREATE TABLE
+    filebox_new (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        code VARCHAR(10) NOT NULL,
+        name VARCHAR(50) NOT NULL,
+        size BIGINT NOT NULL DEFAULT 0,
+        file_type TEXT NOT NULL DEFAULT 'file' CHECK (file_type IN ('text', 'file')),
+        file_path VARCHAR(250) NOT NULL DEFAULT '',
+        text VARCHAR(2000) NOT NULL DEFAULT '',
+        created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+        expired_at TIMESTAMP NOT NULL,
+        used_at TIMESTAMP DEFAULT NULL
+    );
+
+INSERT INTO filebox_new
+SELECT id, code, name, size, file_type, file_path, text, created_at, expired_at, used_at
+FROM filebox;
+
+DROP TABLE filebox;
+
+ALTER TABLE filebox_new RENAME TO filebox;
+
+CREATE INDEX filebox_code_idx ON filebox (code);
@@ -1,78 +1,278 @@
+use std::{
+    collections::BTreeMap,
+    future::{ready, Ready},
+    net::IpAddr,
+    str::FromStr,
+};
+
 use actix::Addr;
-use actix_easy_multipart::{tempfile::Tempfile, text::Text, MultipartForm};
+use actix_easy_multipart::{tempfile::Tempfile, text::Text, MultipartForm, MultipartFormTrait};
 use actix_redis::RedisActor;
-use serde::{
-    de::{self, Unexpected},
-    Deserialize, Serialize,
+use actix_web::{dev::Payload, error::PayloadError, http::header, web, FromRequest, HttpRequest};
+use chrono::{DateTime, NaiveDate, SecondsFormat, TimeZone, Utc};
+use futures_util::{future::LocalBoxFuture, FutureExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use validator::{ValidationError, ValidationErrors};
+
+pub use filebox_types::{
+    CreateFileboxResponse, CreateTextFileboxRequest, ErrorResponse, FileboxFileType,
+    GetFileboxResponse, DATE_FORMAT, DURATION_DAY_HEADER, IP_UPLOAD_LIMIT_HEADER,
+    IP_VISIT_ERROR_LIMIT_HEADER, IP_VISIT_ERROR_REMAINING_HEADER, MAX_DOWNLOADS_HEADER,
+    REQUEST_ID_HEADER,
 };
-use validator::{Validate, ValidationError, ValidationErrors};
 
-use crate::models::filebox::{FileType, Filebox};
+use crate::{
+    errors::Error,
+    models::{
+        daily_stats::DailyStats,
+        filebox::{FileType, Filebox, FileboxFilter},
+        filebox_event::{FileboxEvent, FileboxEventFilter, FileboxEventType},
+    },
+    state::AppState,
+};
 
-#[derive(Debug, MultipartForm)]
+#[derive(Debug, MultipartForm, ToSchema)]
 pub struct CreateFileboxRequest {
+    /// At most 50 characters.
+    #[schema(value_type = String)]
     pub name: Text<String>,
+    /// Content of a text box, 1 to 2000 characters.
+    #[schema(value_type = Option<String>)]
     pub text: Option<Text<String>>,
+    /// Days until the box expires.
+    #[schema(value_type = u8)]
     pub duration_day: Text<u8>,
+    #[schema(value_type = FileboxFileType)]
     pub file_type: Text<FileboxFileType>,
+    /// Content of a file box.
+    #[schema(value_type = Option<String>, format = Binary)]
     pub file: Option<Tempfile>,
 }
 
-impl Validate for CreateFileboxRequest {
-    fn validate(&self) -> Result<(), validator::ValidationErrors> {
-        let mut errors = ValidationErrors::new();
+/// Upload bounds that can be changed by a config reload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadLimits {
+    pub max_upload_size: usize,
+    pub min_duration_day: u8,
+    pub max_duration_day: u8,
+}
 
-        if self.name.len() > 50 {
-            errors.add("name", ValidationError::new("name more than 50 characters"));
+impl Default for UploadLimits {
+    fn default() -> Self {
+        Self {
+            max_upload_size: 50 * 1024 * 1024,
+            min_duration_day: 1,
+            max_duration_day: 29,
         }
+    }
+}
 
-        if *self.duration_day == 0 || *self.duration_day >= 30 {
-            errors.add(
-                "duration_day",
-                ValidationError::new("duration_day over scope"),
-            );
-        }
+/// The multipart form is never read past this size, `max_upload_size` can be
+/// reloaded up to it.
+pub const MAX_UPLOAD_SIZE_CEILING: usize = 1024 * 1024 * 1024;
 
-        match *self.file_type {
-            FileboxFileType::Text => {
-                if let Some(text) = &self.text {
-                    if text.len() == 0 || text.len() > 2000 {
-                        errors.add("text", ValidationError::new("text over scope"));
-                    }
-                } else {
-                    errors.add("text", ValidationError::new("text empty"));
-                }
+/// Room for the boundaries and the text fields of the multipart form on top of
+/// `max_upload_size`, the name and the text are at most a few KiB.
+pub const MULTIPART_FIELDS_ALLOWANCE: usize = 64 * 1024;
+
+/// `MultipartForm` read no further than the live `max_upload_size`, the
+/// `MultipartFormConfig` limit is fixed when the app is built and so only
+/// knows `MAX_UPLOAD_SIZE_CEILING`.
+pub struct UploadForm<T>(pub T);
+
+impl<T> UploadForm<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: MultipartFormTrait + 'static> FromRequest for UploadForm<T> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let limit = req
+            .app_data::<web::Data<AppState>>()
+            .map(|app_state| app_state.upload_limits.load().max_upload_size)
+            .unwrap_or(MAX_UPLOAD_SIZE_CEILING)
+            .saturating_add(MULTIPART_FIELDS_ALLOWANCE);
+        let mut size = 0;
+        let limited = payload.take().map(move |chunk| {
+            let chunk = chunk?;
+            size += chunk.len();
+            if size > limit {
+                return Err(PayloadError::Overflow);
+            }
+            Ok(chunk)
+        });
+        let mut payload = Payload::from(limited.boxed_local());
+        let form = MultipartForm::<T>::from_request(req, &mut payload);
+        async move { Ok(UploadForm(form.await?.into_inner())) }.boxed_local()
+    }
+}
 
-                if !errors.is_empty() {
-                    return Err(errors);
-                }
+impl CreateFileboxRequest {
+    pub fn validate(&self, limits: &UploadLimits) -> Result<(), validator::ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        validate_name_and_duration(&self.name, *self.duration_day, limits, &mut errors);
 
-                Ok(())
+        match *self.file_type {
+            FileboxFileType::Text => {
+                validate_text(self.text.as_ref().map(|text| text.as_str()), &mut errors)
             }
             FileboxFileType::File => {
-                if self.file.is_none() {
-                    errors.add("file", ValidationError::new("file empty"));
+                match &self.file {
+                    Some(file) if file.size > limits.max_upload_size => {
+                        errors.add("file", ValidationError::new("file too large"));
+                    }
+                    Some(_) => {}
+                    None => errors.add("file", ValidationError::new("file empty")),
                 };
+            }
+        }
 
-                if !errors.is_empty() {
-                    return Err(errors);
-                }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
 
-                Ok(())
-            }
+        Ok(())
+    }
+}
+
+/// The json counterpart of the multipart text box.
+pub fn validate_text_filebox_request(
+    req: &CreateTextFileboxRequest,
+    limits: &UploadLimits,
+) -> Result<(), validator::ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+    validate_name_and_duration(&req.name, req.duration_day, limits, &mut errors);
+    validate_text(Some(&req.text), &mut errors);
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(())
+}
+
+/// Options of `PUT /v1/filebox/raw/{filename}`, each one is read from the
+/// query or else from its `X-Filebox-*` header.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RawUploadQuery {
+    /// Days until the box expires, `min_duration_day` when unset.
+    pub duration_day: Option<u8>,
+    /// A box is taken once, so only 1 is accepted.
+    pub max_downloads: Option<u32>,
+}
+
+impl RawUploadQuery {
+    /// Fill the options missing from the query with the request headers.
+    pub fn or_headers(self, req: &HttpRequest) -> Result<Self, Error> {
+        fn header<T: FromStr>(req: &HttpRequest, name: &str) -> Result<Option<T>, Error> {
+            req.headers()
+                .get(name)
+                .map(|value| {
+                    value
+                        .to_str()
+                        .ok()
+                        .and_then(|value| value.trim().parse().ok())
+                        .ok_or_else(|| Error::ValidateArgsError(format!("invalid header {name}")))
+                })
+                .transpose()
         }
+
+        Ok(Self {
+            duration_day: match self.duration_day {
+                Some(day) => Some(day),
+                None => header(req, DURATION_DAY_HEADER)?,
+            },
+            max_downloads: match self.max_downloads {
+                Some(max) => Some(max),
+                None => header(req, MAX_DOWNLOADS_HEADER)?,
+            },
+        })
+    }
+
+    /// Check the options along with the file name before the body is read,
+    /// and return the days until the box expires.
+    pub fn validate(
+        &self,
+        file_name: &str,
+        limits: &UploadLimits,
+    ) -> Result<u8, validator::ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        let day = self.duration_day.unwrap_or(limits.min_duration_day);
+        validate_name_and_duration(file_name, day, limits, &mut errors);
+
+        if file_name.is_empty()
+            || file_name == "."
+            || file_name == ".."
+            || file_name.contains(['/', '\\'])
+        {
+            errors.add("filename", ValidationError::new("invalid file name"));
+        }
+        if !matches!(self.max_downloads, None | Some(1)) {
+            errors.add(
+                "max_downloads",
+                ValidationError::new("a filebox can only be taken once"),
+            );
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(day)
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GetFileboxResponse {
-    pub id: i64,
-    pub code: String,
-    pub name: String,
-    pub file_type: FileboxFileType,
-    pub created_at: i64,
-    pub expired_at: i64,
-    pub used_at: Option<i64>,
+/// Check the size of a streamed upload, which is only known while reading it.
+pub fn validate_upload_size(
+    size: usize,
+    limits: &UploadLimits,
+) -> Result<(), validator::ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+    if size == 0 {
+        errors.add("file", ValidationError::new("file empty"));
+    } else if size > limits.max_upload_size {
+        errors.add("file", ValidationError::new("file too large"));
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(())
+}
+
+fn validate_name_and_duration(
+    name: &str,
+    duration_day: u8,
+    limits: &UploadLimits,
+    errors: &mut ValidationErrors,
+) {
+    if name.len() > 50 {
+        errors.add("name", ValidationError::new("name more than 50 characters"));
+    }
+
+    if duration_day < limits.min_duration_day || duration_day > limits.max_duration_day {
+        errors.add(
+            "duration_day",
+            ValidationError::new("duration_day over scope"),
+        );
+    }
+}
+
+fn validate_text(text: Option<&str>, errors: &mut ValidationErrors) {
+    match text {
+        Some(text) if text.is_empty() || text.len() > 2000 => {
+            errors.add("text", ValidationError::new("text over scope"));
+        }
+        Some(_) => {}
+        None => errors.add("text", ValidationError::new("text empty")),
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -85,67 +285,39 @@ pub struct TakeTextResponse {
     pub created_at: i64,
     pub expired_at: i64,
     pub used_at: i64,
+    pub created_at_rfc3339: String,
+    pub expired_at_rfc3339: String,
+    pub used_at_rfc3339: String,
 }
 
-#[derive(Debug, Clone, Serialize)]
-pub struct CreateFileboxResponse {
-    pub id: i64,
-    pub code: String,
-    pub name: String,
-    pub file_type: FileboxFileType,
-    pub created_at: i64,
-    pub expired_at: i64,
-}
-
-#[derive(PartialEq, Eq, Clone, Copy, Debug)]
-pub enum FileboxFileType {
-    File = 1,
-    Text = 2,
-}
-
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct HealthCheckResponse {
     pub message: String,
     pub health_check_count: u64,
+    pub limiter_fallback: bool,
 }
 
-impl From<&FileboxFileType> for u8 {
-    fn from(v: &FileboxFileType) -> Self {
-        match v {
-            FileboxFileType::File => 1,
-            FileboxFileType::Text => 2,
-        }
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentStatus {
+    Up,
+    Down,
 }
 
-// https://serde.rs/impl-serializer.html
-impl Serialize for FileboxFileType {
-    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        s.serialize_u8(self.into())
-    }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComponentHealth {
+    pub status: ComponentStatus,
+    /// A required component that is down makes the server not ready.
+    pub required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
 }
 
-// https://damad.be/joost/blog/rust-serde-deserialization-of-an-enum-variant.html
-impl<'de> Deserialize<'de> for FileboxFileType {
-    fn deserialize<D>(d: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let num = u8::deserialize(d)?;
-        match num {
-            1 => Ok(FileboxFileType::File),
-            2 => Ok(FileboxFileType::Text),
-            // TODO: 修改为指定的json序列化错误
-            // 目前提示为：Json deserialize error: invalid value: integer `3`, expected 1 or 2 at line 3 column 1
-            _ => Err(de::Error::invalid_value(
-                Unexpected::Unsigned(num as u64),
-                &"1 or 2",
-            )),
-        }
-    }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProbeResponse {
+    pub status: ComponentStatus,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub components: BTreeMap<String, ComponentHealth>,
 }
 
 impl From<FileboxFileType> for FileType {
@@ -176,6 +348,60 @@ impl From<Filebox> for GetFileboxResponse {
             created_at: v.created_at.timestamp(),
             expired_at: v.expired_at.timestamp(),
             used_at: v.used_at.map(|used_at| used_at.timestamp()),
+            created_at_rfc3339: rfc3339(&v.created_at),
+            expired_at_rfc3339: rfc3339(&v.expired_at),
+            used_at_rfc3339: v.used_at.as_ref().map(rfc3339),
+        }
+    }
+}
+
+impl From<Filebox> for AdminFileboxResponse {
+    fn from(v: Filebox) -> Self {
+        Self {
+            id: v.id,
+            code: v.code,
+            name: v.name,
+            size: v.size,
+            file_type: v.file_type.into(),
+            text: v.text,
+            file_path: v.file_path,
+            created_at: v.created_at.timestamp(),
+            expired_at: v.expired_at.timestamp(),
+            used_at: v.used_at.map(|used_at| used_at.timestamp()),
+            created_at_rfc3339: rfc3339(&v.created_at),
+            expired_at_rfc3339: rfc3339(&v.expired_at),
+            used_at_rfc3339: v.used_at.as_ref().map(rfc3339),
+        }
+    }
+}
+
+impl From<FileboxEvent> for FileboxEventResponse {
+    fn from(v: FileboxEvent) -> Self {
+        Self {
+            id: v.id,
+            event_type: v.event_type.into(),
+            filebox_id: v.filebox_id,
+            code: v.code,
+            ip: v.ip,
+            user_agent: v.user_agent,
+            created_at: v.created_at.timestamp(),
+            created_at_rfc3339: rfc3339(&v.created_at),
+        }
+    }
+}
+
+impl From<DailyStats> for DailyStatsResponse {
+    fn from(v: DailyStats) -> Self {
+        Self {
+            day: v.day.format("%Y-%m-%d").to_string(),
+            pickup_ratio: v.pickup_ratio(),
+            created_count: v.created_count,
+            text_count: v.text_count,
+            file_count: v.file_count,
+            total_bytes: v.total_bytes,
+            taken_count: v.taken_count,
+            median_pickup_secs: v.median_pickup_secs,
+            expired_count: v.expired_count,
         }
     }
 }
@@ -191,6 +417,9 @@ impl From<Filebox> for TakeTextResponse {
             created_at: v.created_at.timestamp(),
             expired_at: v.expired_at.timestamp(),
             used_at: v.used_at.unwrap().timestamp(),
+            created_at_rfc3339: rfc3339(&v.created_at),
+            expired_at_rfc3339: rfc3339(&v.expired_at),
+            used_at_rfc3339: rfc3339(&v.used_at.unwrap()),
         }
     }
 }
@@ -204,18 +433,19 @@ impl From<Filebox> for CreateFileboxResponse {
             file_type: v.file_type.into(),
             created_at: v.created_at.timestamp(),
             expired_at: v.expired_at.timestamp(),
+            created_at_rfc3339: rfc3339(&v.created_at),
+            expired_at_rfc3339: rfc3339(&v.expired_at),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
-pub struct ErrorResponse {
-    pub code: u16,
-    pub error: String,
-    pub message: String,
+/// `2023-03-01T08:00:00Z`, the RFC 3339 form next to every unix timestamp of
+/// the responses.
+pub fn rfc3339(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct IpInfo {
     pub visit_error_limit_of_per_day: i32,
     pub upload_limit_of_per_day: i32,
@@ -227,8 +457,308 @@ impl IpInfo {
     }
 }
 
-pub type RedisActorAddr = Addr<RedisActor>;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpRuleKind {
+    Allow,
+    Deny,
+}
+
+impl IpRuleKind {
+    pub const ALL: [IpRuleKind; 2] = [IpRuleKind::Allow, IpRuleKind::Deny];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IpRuleKind::Allow => "allow",
+            IpRuleKind::Deny => "deny",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpRuleRequest {
+    pub kind: IpRuleKind,
+    pub cidr: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpRulesResponse {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+/// Query of `GET /admin/fileboxes`, times are unix timestamps in seconds.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListFileboxQuery {
+    /// Exact code or part of the name.
+    pub q: Option<String>,
+    pub file_type: Option<FileboxFileType>,
+    pub created_from: Option<i64>,
+    pub created_to: Option<i64>,
+    pub taken: Option<bool>,
+    pub min_size: Option<i64>,
+    pub max_size: Option<i64>,
+    #[serde(default = "default_page")]
+    pub page: i64,
+    #[serde(default = "default_page_size")]
+    pub page_size: i64,
+}
+
+fn default_page() -> i64 {
+    1
+}
+
+fn default_page_size() -> i64 {
+    20
+}
+
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+fn check_page(page: i64, page_size: i64) -> Result<(), Error> {
+    if page < 1 || !(1..=MAX_PAGE_SIZE).contains(&page_size) {
+        return Err(Error::ValidateArgsError(format!(
+            "page should be at least 1 and page_size between 1 and {MAX_PAGE_SIZE}"
+        )));
+    }
+    Ok(())
+}
+
+fn timestamp_to_datetime(ts: i64) -> Result<DateTime<Utc>, Error> {
+    Utc.timestamp_opt(ts, 0)
+        .single()
+        .ok_or_else(|| Error::ValidateArgsError(format!("invalid timestamp: {ts}")))
+}
+
+impl ListFileboxQuery {
+    pub fn to_filter(&self) -> Result<FileboxFilter, Error> {
+        check_page(self.page, self.page_size)?;
+
+        Ok(FileboxFilter {
+            keyword: self.q.clone().filter(|q| !q.is_empty()),
+            file_type: self.file_type.map(Into::into),
+            created_from: self.created_from.map(timestamp_to_datetime).transpose()?,
+            created_to: self.created_to.map(timestamp_to_datetime).transpose()?,
+            taken: self.taken,
+            min_size: self.min_size,
+            max_size: self.max_size,
+        })
+    }
+
+    pub fn offset(&self) -> i64 {
+        (self.page - 1) * self.page_size
+    }
+}
+
+/// Everything stored of a filebox, only returned by the admin api.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminFileboxResponse {
+    pub id: i64,
+    pub code: String,
+    pub name: String,
+    pub size: i64,
+    pub file_type: FileboxFileType,
+    pub text: String,
+    pub file_path: String,
+    pub created_at: i64,
+    pub expired_at: i64,
+    pub used_at: Option<i64>,
+    pub created_at_rfc3339: String,
+    pub expired_at_rfc3339: String,
+    pub used_at_rfc3339: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListFileboxResponse {
+    pub items: Vec<AdminFileboxResponse>,
+    pub total: i64,
+    pub page: i64,
+    pub page_size: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevokeFileboxRequest {
+    pub ids: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevokeFileboxResponse {
+    /// Ids that were deleted, unknown ids are left out.
+    pub revoked: Vec<i64>,
+}
+
+/// Who sent the request, as recorded in the audit log.
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub ip: String,
+    pub user_agent: String,
+}
+
+impl ClientInfo {
+    pub fn from_http_request(req: &HttpRequest) -> Self {
+        let ip = client_ip(req);
+        let user_agent = req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .chars()
+            .take(512)
+            .collect();
+
+        Self { ip, user_agent }
+    }
+}
+
+/// The ip the limits are counted on: `X-REAL-IP` when the peer is one of the
+/// `trusted_proxies`, the peer address otherwise, so a client can not pick
+/// its own ip by sending the header.
+pub fn client_ip(req: &HttpRequest) -> String {
+    let peer = req.peer_addr().map(|addr| addr.ip());
+    let trusted = match (peer, req.app_data::<web::Data<AppState>>()) {
+        (Some(peer), Some(app_state)) => app_state
+            .trusted_proxies
+            .iter()
+            .any(|net| net.contains(&peer)),
+        _ => false,
+    };
+    let real_ip = req
+        .headers()
+        .get("X-REAL-IP")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| IpAddr::from_str(value.trim()).ok());
 
-pub const IP_UPLOAD_LIMIT_HEADER: &str = "X-IP-UPLOAD-LIMIT";
-pub const IP_VISIT_ERROR_LIMIT_HEADER: &str = "X-IP-VISIT-ERROR-LIMIT";
-pub const DATE_FORMAT: &str = "%Y-%m-%d";
+    match (real_ip, peer) {
+        (Some(ip), _) if trusted => ip.to_string(),
+        (_, Some(peer)) => peer.to_string(),
+        _ => String::new(),
+    }
+}
+
+impl FromRequest for ClientInfo {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(ClientInfo::from_http_request(req)))
+    }
+}
+
+/// Query of `GET /admin/events`, times are unix timestamps in seconds.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListFileboxEventQuery {
+    pub code: Option<String>,
+    pub ip: Option<String>,
+    pub event_type: Option<String>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    #[serde(default = "default_page")]
+    pub page: i64,
+    #[serde(default = "default_page_size")]
+    pub page_size: i64,
+}
+
+impl ListFileboxEventQuery {
+    pub fn to_filter(&self) -> Result<FileboxEventFilter, Error> {
+        check_page(self.page, self.page_size)?;
+
+        let event_type = self
+            .event_type
+            .as_deref()
+            .map(FileboxEventType::from_str)
+            .transpose()
+            .map_err(Error::ValidateArgsError)?;
+        Ok(FileboxEventFilter {
+            code: self.code.clone().filter(|code| !code.is_empty()),
+            ip: self.ip.clone().filter(|ip| !ip.is_empty()),
+            event_type,
+            created_from: self.from.map(timestamp_to_datetime).transpose()?,
+            created_to: self.to.map(timestamp_to_datetime).transpose()?,
+        })
+    }
+
+    pub fn offset(&self) -> i64 {
+        (self.page - 1) * self.page_size
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileboxEventResponse {
+    pub id: i64,
+    pub event_type: String,
+    pub filebox_id: Option<i64>,
+    pub code: String,
+    pub ip: String,
+    pub user_agent: String,
+    pub created_at: i64,
+    pub created_at_rfc3339: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListFileboxEventResponse {
+    pub items: Vec<FileboxEventResponse>,
+    pub total: i64,
+    pub page: i64,
+    pub page_size: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+/// Query of `GET /admin/stats`, days are written as `YYYY-MM-DD` and both
+/// ends are included. Defaults to the last 30 days.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DailyStatsQuery {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    #[serde(default)]
+    pub format: StatsFormat,
+}
+
+pub const MAX_STATS_DAYS: i64 = 366;
+
+impl DailyStatsQuery {
+    pub fn range(&self, today: NaiveDate) -> Result<(NaiveDate, NaiveDate), Error> {
+        let parse = |day: &str| {
+            NaiveDate::parse_from_str(day, "%Y-%m-%d")
+                .map_err(|_| Error::ValidateArgsError(format!("invalid day: {day}")))
+        };
+        let to = self.to.as_deref().map(parse).transpose()?.unwrap_or(today);
+        let from = match self.from.as_deref() {
+            Some(from) => parse(from)?,
+            None => to - chrono::Duration::days(29),
+        };
+
+        let days = (to - from).num_days() + 1;
+        if !(1..=MAX_STATS_DAYS).contains(&days) {
+            return Err(Error::ValidateArgsError(format!(
+                "from should not be after to and cover at most {MAX_STATS_DAYS} days"
+            )));
+        }
+        Ok((from, to))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyStatsResponse {
+    pub day: String,
+    pub created_count: i64,
+    pub text_count: i64,
+    pub file_count: i64,
+    pub total_bytes: i64,
+    pub taken_count: i64,
+    pub pickup_ratio: f64,
+    pub median_pickup_secs: Option<f64>,
+    pub expired_count: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListDailyStatsResponse {
+    pub items: Vec<DailyStatsResponse>,
+}
+
+pub type RedisActorAddr = Addr<RedisActor>;
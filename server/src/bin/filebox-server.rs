@@ -1,115 +1,182 @@
 use std::cell::RefCell;
 use std::env;
-use std::io::Write;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 
 use actix_cors::Cors;
-use actix_extensible_rate_limit::backend::memory::InMemoryBackend;
-use actix_extensible_rate_limit::backend::SimpleInputFunctionBuilder;
 use actix_extensible_rate_limit::RateLimiter;
 use actix_http::header::HeaderName;
 use actix_redis::RedisActor;
-use actix_web::middleware::Logger;
 use actix_web::{http, web, App, HttpServer};
 use actix_web_lab::middleware::from_fn;
-use chrono::Local;
-use server::api::{IP_UPLOAD_LIMIT_HEADER, IP_VISIT_ERROR_LIMIT_HEADER};
-use server::data::redis::IpAllower;
+use arc_swap::ArcSwap;
+use server::api::{
+    UploadLimits, DURATION_DAY_HEADER, IP_UPLOAD_LIMIT_HEADER, IP_VISIT_ERROR_LIMIT_HEADER,
+    IP_VISIT_ERROR_REMAINING_HEADER, MAX_DOWNLOADS_HEADER, REQUEST_ID_HEADER,
+};
+use server::config::{CliArgs, Config, ConfigError};
+use server::data::limiter::{IpLimiter, LookupGuard};
+use server::data::redis::{IpAllower, IpRules};
+use server::data::repository::connect_repository;
+use server::errors::configure_extractors;
+use server::handlers::admin::{
+    add_ip_rule, delete_filebox, delete_ip_rule_by_cidr, get_filebox_detail, list_daily_stats,
+    list_filebox_events, list_fileboxes, list_ip_rules, reload_config, revoke_fileboxes,
+};
 use server::handlers::filebox::add_new_filebox;
+use server::handlers::filebox::add_new_raw_filebox;
+use server::handlers::filebox::add_new_text_filebox;
 use server::handlers::filebox::get_filebox_by_code;
 use server::handlers::filebox::take_filebox_by_code;
-use server::handlers::general::health_check_handler;
-use server::middlewares::{ip_upload_limit_of_day_mw, ip_visit_error_limit_of_day_mw};
-use server::scheduler::start_clean_expired_filebox;
-use server::state::{AppState, CacheState};
-use sqlx::postgres::PgPoolOptions;
+use server::handlers::general::{
+    health_check_handler, liveness_handler, metrics_handler, openapi_handler, readiness_handler,
+};
+use server::metrics::Metrics;
+use server::middlewares::{
+    admin_token_mw, ip_upload_limit_of_day_mw, ip_visit_error_limit_of_day_mw, localize_error_mw,
+    lookup_failure_delay_mw, rate_limit_metrics_mw, request_id_mw,
+};
+use server::rate_limit::{rate_limit_input, RateLimitBackend, RateLimitConfig};
+use server::scheduler::{
+    start_aggregate_daily_stats, start_clean_expired_filebox, start_refresh_usage_metrics,
+    start_snapshot_ip_limits,
+};
+use server::state::{load_added_ip_rules, reload_state, AppState, CacheState};
+use server::telemetry::init_tracing;
 use tiny_id::ShortCodeGenerator;
+use tokio::signal::unix::{signal, SignalKind};
 
 #[actix_rt::main]
 async fn main() -> anyhow::Result<()> {
     dotenvy::dotenv().ok();
-    let env = env_logger::Env::new().default_filter_or("info");
-    env_logger::Builder::from_env(env)
-        .format(|buf, record| {
-            let level = { buf.default_styled_level(record.level()) };
-            writeln!(
-                buf,
-                "[{}] {} [{}:{}] {}",
-                Local::now().format("%Y-%m-%d %H:%M:%S"),
-                level,
-                record.module_path().unwrap_or("<unnamed>"),
-                record.line().unwrap_or(0),
-                &record.args(),
-            )
-        })
-        .init();
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL is required");
-    let http_server_addr = env::var("HTTP_SERVER_ADDR").expect("HTTP_SERVER_ADDR is required");
-    let upload_path = env::var("UPLOAD_FILE_PATH").expect("UPLOAD_FILE_PATH is required");
+    let cli = CliArgs::parse(env::args().skip(1)).unwrap_or_else(|err| exit_with_config_error(err));
+    if cli.help {
+        print!("{}", CliArgs::usage());
+        return Ok(());
+    }
+    let config = Config::load(&cli).unwrap_or_else(|err| exit_with_config_error(err));
+    if cli.print_config {
+        print!("{}", config.to_toml());
+        return Ok(());
+    }
+    init_tracing(config.log_format);
+
+    let upload_path = config.upload_file_path.clone();
     std::fs::create_dir_all(upload_path.clone())?;
 
-    let graceful_shutdown_timeout_sec = env::var("GRACEFUL_SHUTDOWN_TIMEOUT_SEC")
-        .expect("GRACEFUL_SHUTDOWN_TIMEOUT_SEC is required");
-    let graceful_shutdown_timeout_sec: u64 = graceful_shutdown_timeout_sec.parse()
-        .unwrap_or_else(|_| panic!("GRACEFUL_SHUTDOWN_TIMEOUT_SEC should be a u64 type but got {graceful_shutdown_timeout_sec}"));
-    let db_pool = PgPoolOptions::new().connect(&database_url).await?;
-    sqlx::migrate!("./migrations").run(&db_pool).await?;
+    let repo = connect_repository(&config.database_url)
+        .await
+        .map_err(|err| anyhow::anyhow!("connect to the database failed: {err}"))?;
+    tracing::info!("filebox storage: {}", repo.backend());
+
+    let generator = ShortCodeGenerator::new_lowercase_alphanumeric(config.code_len);
 
-    let code_len = env::var("CODE_LEN").expect("CODE_LEN is required");
-    let code_len: usize = code_len
-        .parse()
-        .unwrap_or_else(|_| panic!("CODE_LEN should be a u8 type but got {code_len}"));
-    let generator = ShortCodeGenerator::new_lowercase_alphanumeric(code_len);
+    if config.admin_token.is_none() {
+        tracing::warn!("admin_token is not set, the admin api and /metrics are disabled");
+    }
 
+    let metrics = Arc::new(Metrics::new());
     let shared_data = web::Data::new(AppState {
         health_check_response: "I'm OK.".to_string(),
         visit_count: std::sync::Mutex::new(0),
         upload_path: upload_path.clone(),
-        db: db_pool.clone(),
+        storage_min_free_bytes: config.storage_min_free_bytes,
+        repo: repo.clone(),
         code_gen: tokio::sync::Mutex::new(RefCell::new(generator)),
+        admin_token: config.admin_token.clone(),
+        trusted_proxies: config.trusted_proxies.clone(),
+        upload_limits: ArcSwap::from_pointee(UploadLimits::from(&config)),
+        metrics: metrics.clone(),
     });
 
-    let ip_visit_error_limit =
-        env::var("IP_VISIT_ERROR_LIMIT").expect("IP_VISIT_ERROR_LIMIT is required");
-    let ip_visit_error_limit: i32 = ip_visit_error_limit.parse().unwrap_or_else(|_| {
-        panic!("IP_VISIT_ERROR_LIMIT should be a i32 type but got {ip_visit_error_limit}")
-    });
-    let ip_upload_limit = env::var("IP_UPLOAD_LIMIT").expect("IP_UPLOAD_LIMIT is required");
-    let ip_upload_limit: i32 = ip_upload_limit.parse().unwrap_or_else(|_| {
-        panic!("IP_UPLOAD_LIMIT should be a i32 type but got {ip_visit_error_limit}")
-    });
-    let ip_visit_error_duration_day =
-        env::var("IP_VISIT_ERROR_DURATION_DAY").expect("IP_VISIT_ERROR_DURATION_DAY is required");
-    let ip_visit_error_duration_day: i64 = ip_visit_error_duration_day.parse().unwrap_or_else(|_| {
-        panic!("IP_VISIT_ERROR_DURATION_DAY should be a i64 type but got {ip_visit_error_duration_day}")
-    });
+    let mut ip_rules = IpRules::new(config.ip_allow_list.clone(), config.ip_deny_list.clone());
+    let redis_actor = config
+        .redis_conn_addr
+        .as_ref()
+        .map(|addr| Arc::new(RedisActor::start(addr.clone())));
+    if redis_actor.is_none() {
+        tracing::info!("redis_conn_addr is not set, ip limits are kept in the process");
+    }
+    if let Err(err) =
+        load_added_ip_rules(repo.as_ref(), redis_actor.as_deref(), &mut ip_rules).await
+    {
+        tracing::warn!("load the added ip rules failed: {err}");
+    }
+
+    let rate_limit_config = Arc::new(RateLimitConfig::new(
+        std::time::Duration::from_secs(config.rate_limit_window_sec),
+        config.rate_limit_max_requests,
+        config.rate_limit_routes.clone(),
+    ));
+    let rate_limit_backend = config.rate_limit_backend;
+
+    let ip_limiter = Arc::new(IpLimiter::new(
+        redis_actor.clone(),
+        config.redis_failure_policy,
+        metrics.clone(),
+    ));
+    // 仅在未配置 redis 时落盘, 否则内存中只是 redis 故障期间的临时计数
+    let snapshot_path = config
+        .ip_limit_snapshot_path
+        .as_ref()
+        .filter(|_| redis_actor.is_none())
+        .map(PathBuf::from);
+    if let Some(path) = &snapshot_path {
+        if let Err(err) = ip_limiter.memory_store().load_snapshot(path) {
+            tracing::warn!("load ip limit snapshot from {path:?} failed: {err}");
+        }
+    }
+    let snapshot_handle = tokio::spawn(start_snapshot_ip_limits(
+        ip_limiter.memory_store(),
+        snapshot_path.clone(),
+    ));
 
-    let redis_conn_addr = env::var("REDIS_CONN_ADDR").expect("REDIS_CONN_ADDR is required");
     let cache_state = web::Data::new(CacheState {
-        ip_allower: Arc::new(IpAllower::new(
-            ip_visit_error_limit,
-            ip_upload_limit,
-            ip_visit_error_duration_day,
+        ip_allower: ArcSwap::from_pointee(IpAllower::from(&config)),
+        ip_rules: ArcSwap::from_pointee(ip_rules),
+        ip_rules_lock: tokio::sync::Mutex::new(()),
+        ip_limiter: ip_limiter.clone(),
+        lookup_guard: Arc::new(LookupGuard::new(
+            config.lookup_failure_threshold,
+            std::time::Duration::from_millis(config.lookup_failure_delay_ms),
+            std::time::Duration::from_millis(config.lookup_failure_max_delay_ms),
         )),
-        redis_actor: Arc::new(RedisActor::start(redis_conn_addr)),
+        redis_actor,
     });
 
-    let pool = db_pool.clone();
-    let scheduler_handle =
-        tokio::spawn(async move { start_clean_expired_filebox(&pool, upload_path.clone()).await });
+    let cli = web::Data::new(cli);
+    let reload_handle = tokio::spawn(reload_on_sighup(
+        cli.clone(),
+        shared_data.clone(),
+        cache_state.clone(),
+    ));
+
+    let usage_handle = tokio::spawn(start_refresh_usage_metrics(repo.clone(), metrics.clone()));
+    let cleanup_repo = repo.clone();
+    let scheduler_handle = tokio::spawn(async move {
+        start_clean_expired_filebox(cleanup_repo, upload_path.clone(), &metrics).await
+    });
+    // a box can be taken or expire until its longest duration has passed
+    let lookback_days = i64::from(config.max_duration_day) + 1;
+    let stats_handle = tokio::spawn(start_aggregate_daily_stats(repo, lookback_days));
 
-    let allowed_origin = env::var("ALLOWED_ORIGIN").expect("ALLOWED_ORIGIN is required");
+    let allowed_origin = config.allowed_origin.clone();
     let app = move || {
         let cors = Cors::default()
             .allowed_origin(&allowed_origin)
             .allowed_origin_fn(|origin, _req_head| {
                 origin.as_bytes().starts_with(b"http://localhost")
             })
-            .allowed_methods(vec!["GET", "POST"])
+            .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
             // 允许后端自定义响应 HTTP Response header 给前端
-            .expose_headers(vec![IP_UPLOAD_LIMIT_HEADER, IP_VISIT_ERROR_LIMIT_HEADER])
+            .expose_headers(vec![
+                IP_UPLOAD_LIMIT_HEADER,
+                IP_VISIT_ERROR_LIMIT_HEADER,
+                IP_VISIT_ERROR_REMAINING_HEADER,
+                REQUEST_ID_HEADER,
+            ])
             // 允许前端跨域传过来的 HTTP Request header
             .allowed_headers(vec![
                 http::header::AUTHORIZATION,
@@ -117,26 +184,61 @@ async fn main() -> anyhow::Result<()> {
                 http::header::CONTENT_TYPE,
                 HeaderName::from_str(IP_UPLOAD_LIMIT_HEADER).unwrap(),
                 HeaderName::from_str(IP_VISIT_ERROR_LIMIT_HEADER).unwrap(),
+                HeaderName::from_str(REQUEST_ID_HEADER).unwrap(),
+                HeaderName::from_str(DURATION_DAY_HEADER).unwrap(),
+                HeaderName::from_str(MAX_DOWNLOADS_HEADER).unwrap(),
             ])
             .supports_credentials()
             .max_age(3600);
 
         // A backend is responsible for storing rate limit data, and choosing whether to allow/deny requests
-        let backend = InMemoryBackend::builder().build();
+        let backend = RateLimitBackend::new(rate_limit_backend, cache_state.ip_limiter.clone());
 
-        // Assign a limit of 5 requests per minute per client ip address
-        let input = SimpleInputFunctionBuilder::new(std::time::Duration::from_secs(1), 60)
-            .real_ip_key()
-            .build();
+        // Assign RATE_LIMIT_MAX_REQUESTS per RATE_LIMIT_WINDOW_SEC per client ip address,
+        // unless a RATE_LIMIT_ROUTES override matches the path
+        let input = rate_limit_input(rate_limit_config.clone());
 
         let limit_mw = RateLimiter::builder(backend, input).add_headers().build();
         App::new()
             .app_data(shared_data.clone())
             .app_data(cache_state.clone())
+            .app_data(cli.clone())
+            .configure(configure_extractors)
             .wrap(limit_mw)
+            .wrap(from_fn(rate_limit_metrics_mw))
             .wrap(cors)
-            .wrap(Logger::default())
+            .wrap(from_fn(localize_error_mw))
+            .wrap(from_fn(request_id_mw))
             .route("/health", web::get().to(health_check_handler))
+            .route("/health/live", web::get().to(liveness_handler))
+            .route("/health/ready", web::get().to(readiness_handler))
+            .service(
+                web::resource("/metrics")
+                    .wrap(from_fn(admin_token_mw))
+                    .route(web::get().to(metrics_handler)),
+            )
+            .route("/openapi.json", web::get().to(openapi_handler))
+            .service(
+                web::scope("/admin")
+                    .wrap(from_fn(admin_token_mw))
+                    .service(
+                        web::resource("/ip-rules")
+                            .route(web::get().to(list_ip_rules))
+                            .route(web::post().to(add_ip_rule))
+                            .route(web::delete().to(delete_ip_rule_by_cidr)),
+                    )
+                    .route("/reload", web::post().to(reload_config))
+                    .route("/fileboxes", web::get().to(list_fileboxes))
+                    .route("/events", web::get().to(list_filebox_events))
+                    .route("/stats", web::get().to(list_daily_stats))
+                    // 需在 /fileboxes/{id} 之前注册, 否则会被其匹配
+                    .route("/fileboxes/revoke", web::post().to(revoke_fileboxes))
+                    .service(
+                        web::resource("/fileboxes/{id}")
+                            .route(web::get().to(get_filebox_detail))
+                            .route(web::delete().to(delete_filebox)),
+                    ),
+            )
             .service(
                 web::scope("/v1/filebox")
                     .wrap(from_fn(ip_visit_error_limit_of_day_mw))
@@ -146,19 +248,33 @@ async fn main() -> anyhow::Result<()> {
                             .to(add_new_filebox)
                             .wrap(from_fn(ip_upload_limit_of_day_mw)),
                     )
+                    // 需在 /{code} 之前注册, 否则会被其匹配
+                    .route(
+                        "/text",
+                        web::post()
+                            .to(add_new_text_filebox)
+                            .wrap(from_fn(ip_upload_limit_of_day_mw)),
+                    )
+                    .route(
+                        "/raw/{filename}",
+                        web::put()
+                            .to(add_new_raw_filebox)
+                            .wrap(from_fn(ip_upload_limit_of_day_mw)),
+                    )
                     .service(
                         web::resource("/{code}")
+                            .wrap(from_fn(lookup_failure_delay_mw))
                             .route(web::get().to(get_filebox_by_code))
                             .route(web::post().to(take_filebox_by_code)),
                     ),
             )
     };
 
-    log::info!("Filebox server run on: {http_server_addr}");
+    tracing::info!("Filebox server run on: {}", config.http_server_addr);
     let server = HttpServer::new(app)
-        .bind(http_server_addr)?
+        .bind(&config.http_server_addr)?
         .disable_signals()
-        .shutdown_timeout(graceful_shutdown_timeout_sec) // Modified shutdown timeout, less than 30 seconds.
+        .shutdown_timeout(config.graceful_shutdown_timeout_sec) // Modified shutdown timeout, less than 30 seconds.
         .run();
 
     let server_handle = server.handle();
@@ -167,18 +283,59 @@ async fn main() -> anyhow::Result<()> {
     tokio::pin!(server);
     tokio::select! {
         r = signal => {
-            log::info!("received interrupt signal");
+            tracing::info!("received interrupt signal");
             r.unwrap();
             let ((), r) = tokio::join!(server_handle.stop(true), server);
             r.unwrap();
             scheduler_handle.abort();
+            stats_handle.abort();
+            reload_handle.abort();
+            snapshot_handle.abort();
+            usage_handle.abort();
         }
         r = &mut server => {
-            log::info!("server finished");
+            tracing::info!("server finished");
             r.unwrap();
             scheduler_handle.abort();
+            stats_handle.abort();
+            reload_handle.abort();
+            snapshot_handle.abort();
+            usage_handle.abort();
+        }
+    }
+
+    if let Some(path) = &snapshot_path {
+        if let Err(err) = ip_limiter.memory_store().save_snapshot(path) {
+            tracing::error!("save ip limit snapshot to {path:?} failed: {err}");
         }
     }
 
     Ok(())
 }
+
+/// Reload the configuration every time the process receives SIGHUP.
+async fn reload_on_sighup(
+    cli: web::Data<CliArgs>,
+    app_state: web::Data<AppState>,
+    cache_state: web::Data<CacheState>,
+) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(err) => {
+            tracing::warn!("listen to SIGHUP failed, reload is only available over http: {err}");
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        tracing::info!("received hangup signal, reload configuration");
+        if let Err(err) = reload_state(&cli, &app_state, &cache_state).await {
+            tracing::error!("reload configuration failed, keep the current one: {err}");
+        }
+    }
+}
+
+fn exit_with_config_error(err: ConfigError) -> ! {
+    eprint!("{err}");
+    eprintln!("run with --help to list the options");
+    std::process::exit(2);
+}
@@ -0,0 +1,20 @@
+use std::{env, process};
+
+use server::cli::{run, ClientArgs, ClientError};
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let args = ClientArgs::parse(env::args().skip(1)).unwrap_or_else(|err| {
+        eprint!("filebox: {err}\n\n{}", ClientArgs::usage());
+        process::exit(2);
+    });
+
+    if let Err(err) = run(args).await {
+        eprintln!("filebox: {err}");
+        let code = match err {
+            ClientError::Usage(_) => 2,
+            _ => 1,
+        };
+        process::exit(code);
+    }
+}
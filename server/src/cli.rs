@@ -0,0 +1,414 @@
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{Local, TimeZone};
+use filebox_client::{
+    Body, Client, CreateFileboxResponse, ErrorKind, FileboxFileType, GetFileboxResponse,
+    UploadOptions, DATE_FORMAT, IP_UPLOAD_LIMIT_HEADER, IP_VISIT_ERROR_LIMIT_HEADER,
+};
+use futures_util::StreamExt;
+use indicatif::{ProgressBar, ProgressStyle};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+
+pub const SERVER_ENV: &str = "FILEBOX_SERVER";
+pub const LIMITS_FILE_ENV: &str = "FILEBOX_LIMITS_FILE";
+pub const DEFAULT_SERVER: &str = "http://127.0.0.1:8888";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("{0}")]
+    Usage(String),
+
+    #[error(transparent)]
+    Client(#[from] filebox_client::Error),
+
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("the {0} limit of today has been reached, try again tomorrow")]
+    LimitReached(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Upload a file, or the standard input for `-`.
+    Send {
+        source: String,
+        name: Option<String>,
+        duration_day: Option<u8>,
+    },
+    Info {
+        code: String,
+    },
+    /// Take a box into `output`, or a file of its own name in the current
+    /// directory, `-` writes to the standard output.
+    Get {
+        code: String,
+        output: Option<PathBuf>,
+    },
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientArgs {
+    pub server: Option<String>,
+    pub command: Command,
+}
+
+impl ClientArgs {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, ClientError> {
+        let mut server = None;
+        let mut name = None;
+        let mut duration_day = None;
+        let mut output = None;
+        let mut positional = vec![];
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| ClientError::Usage(format!("{flag} requires a value")))
+            };
+
+            match flag.as_str() {
+                "-h" | "--help" => {
+                    return Ok(Self {
+                        server,
+                        command: Command::Help,
+                    })
+                }
+                "-s" | "--server" => server = Some(value()?),
+                "-n" | "--name" => name = Some(value()?),
+                "-d" | "--duration-day" => {
+                    let day = value()?;
+                    let day = day
+                        .parse()
+                        .map_err(|_| ClientError::Usage(format!("invalid {flag}: {day}")))?;
+                    duration_day = Some(day);
+                }
+                "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+                _ if flag.starts_with('-') && flag != "-" => {
+                    return Err(ClientError::Usage(format!("unknown flag {flag}")));
+                }
+                _ => positional.push(arg),
+            }
+        }
+
+        let mut positional = positional.into_iter();
+        let command = match (positional.next().as_deref(), positional.next()) {
+            (None, _) => Command::Help,
+            (Some("send"), Some(source)) => Command::Send {
+                source,
+                name,
+                duration_day,
+            },
+            (Some("info"), Some(code)) => Command::Info { code },
+            (Some("get"), Some(code)) => Command::Get { code, output },
+            _ => {
+                return Err(ClientError::Usage(
+                    "expected send <path|->, info <code> or get <code>".to_string(),
+                ))
+            }
+        };
+        if let Some(extra) = positional.next() {
+            return Err(ClientError::Usage(format!("unexpected argument {extra}")));
+        }
+
+        Ok(Self { server, command })
+    }
+
+    pub fn usage() -> String {
+        format!(
+            "Usage: filebox [--server <url>] <command>
+
+Commands:
+  send <path|->   upload a file, - reads the standard input
+      -n, --name <name>          name of the box, the file name by default
+      -d, --duration-day <days>  days until the box expires
+  info <code>     show a box without taking it
+  get <code>      take a box
+      -o, --output <path>        where to write it, - for the standard output
+
+The server is --server, {SERVER_ENV} or {DEFAULT_SERVER}. Limits reported by
+the server are remembered in {LIMITS_FILE_ENV} or ~/.config/filebox/limits.json.
+"
+        )
+    }
+}
+
+/// The `filebox_client::Client` of the command line, with progress bars.
+///
+/// Like the web client it remembers the `X-IP-*-LIMIT` dates the server
+/// answers with, and refuses to send what would be rejected until the next day.
+pub struct CliClient {
+    client: Client,
+    limits_file: Option<PathBuf>,
+}
+
+impl CliClient {
+    pub fn new(server: impl Into<String>, limits_file: Option<PathBuf>) -> Self {
+        Self {
+            client: Client::new(server),
+            limits_file,
+        }
+    }
+
+    /// Stream `source`, a path or `-` for the standard input, into a new box.
+    pub async fn send(
+        &self,
+        source: &str,
+        name: Option<&str>,
+        duration_day: Option<u8>,
+    ) -> Result<CreateFileboxResponse, ClientError> {
+        self.check_limit_flag(IP_UPLOAD_LIMIT_HEADER)?;
+        let (reader, size, default_name): (Box<dyn AsyncRead + Send + Unpin>, _, _) =
+            if source == "-" {
+                (Box::new(tokio::io::stdin()), None, "stdin".to_string())
+            } else {
+                let file = tokio::fs::File::open(source).await?;
+                let size = file.metadata().await?.len();
+                let file_name = Path::new(source)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "file".to_string());
+                (Box::new(file), Some(size), file_name)
+            };
+        let name = name.map(str::to_string).unwrap_or(default_name);
+
+        let bar = progress_bar(size);
+        let progress = bar.clone();
+        let stream = ReaderStream::new(reader).inspect(move |chunk| {
+            if let Ok(chunk) = chunk {
+                progress.inc(chunk.len() as u64);
+            }
+        });
+        let options = UploadOptions { duration_day };
+        let created = self
+            .client
+            .upload(&name, Body::wrap_stream(stream), &options)
+            .await;
+        bar.finish_and_clear();
+
+        self.remember_limit_flag(created)
+    }
+
+    pub async fn info(&self, code: &str) -> Result<GetFileboxResponse, ClientError> {
+        self.check_limit_flag(IP_VISIT_ERROR_LIMIT_HEADER)?;
+        let filebox = self.client.info(code).await;
+        self.remember_limit_flag(filebox)
+    }
+
+    /// Take a box and return the path it was written to, `None` for the
+    /// standard output.
+    pub async fn get(
+        &self,
+        code: &str,
+        output: Option<&Path>,
+    ) -> Result<Option<PathBuf>, ClientError> {
+        self.check_limit_flag(IP_VISIT_ERROR_LIMIT_HEADER)?;
+        let download = self.client.take(code).await;
+        let download = self.remember_limit_flag(download)?;
+
+        let path = match output {
+            Some(path) if path == Path::new("-") => None,
+            Some(path) => Some(path.to_path_buf()),
+            None => Some(PathBuf::from(
+                download
+                    .file_name
+                    .clone()
+                    .unwrap_or_else(|| code.to_string()),
+            )),
+        };
+        let mut writer: Box<dyn AsyncWrite + Unpin> = match &path {
+            Some(path) => Box::new(tokio::fs::File::create(path).await?),
+            None => Box::new(tokio::io::stdout()),
+        };
+
+        let bar = progress_bar(download.content_length);
+        let mut stream = Box::pin(download.bytes_stream());
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            writer.write_all(&chunk).await?;
+            bar.inc(chunk.len() as u64);
+        }
+        writer.flush().await?;
+        bar.finish_and_clear();
+
+        Ok(path)
+    }
+
+    /// Fail fast when the server flagged this client today, a flag of an
+    /// earlier day is out of date.
+    fn check_limit_flag(&self, header_name: &'static str) -> Result<(), ClientError> {
+        let today = Local::now().format(DATE_FORMAT).to_string();
+        if self.load_limit_flags().get(header_name) == Some(&today) {
+            let kind = match header_name {
+                IP_UPLOAD_LIMIT_HEADER => "upload",
+                _ => "wrong code",
+            };
+            return Err(ClientError::LimitReached(kind));
+        }
+        Ok(())
+    }
+
+    fn remember_limit_flag<T>(
+        &self,
+        result: Result<T, filebox_client::Error>,
+    ) -> Result<T, ClientError> {
+        if let Err(filebox_client::Error::Api(err)) = &result {
+            let header_name = match err.kind {
+                ErrorKind::IpUploadLimit => Some(IP_UPLOAD_LIMIT_HEADER),
+                ErrorKind::IpVisitErrorLimit => Some(IP_VISIT_ERROR_LIMIT_HEADER),
+                _ => None,
+            };
+            if let (Some(header_name), Some(date)) = (header_name, &err.limit_flag) {
+                self.save_limit_flag(header_name, date)?;
+            }
+        }
+        Ok(result?)
+    }
+
+    fn load_limit_flags(&self) -> BTreeMap<String, String> {
+        self.limits_file
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    fn save_limit_flag(&self, header_name: &str, date: &str) -> io::Result<()> {
+        let path = match &self.limits_file {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut flags = self.load_limit_flags();
+        flags.insert(header_name.to_string(), date.to_string());
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // SAFTEY: a map of strings always serializes
+        fs::write(path, serde_json::to_string_pretty(&flags).unwrap())
+    }
+}
+
+/// A bar of `len` bytes, or a spinner when the size is unknown. Nothing is
+/// drawn when stderr is not a terminal.
+fn progress_bar(len: Option<u64>) -> ProgressBar {
+    // SAFTEY: the templates are valid
+    match len {
+        Some(len) => ProgressBar::new(len).with_style(
+            ProgressStyle::with_template("{bar:40} {bytes}/{total_bytes} {bytes_per_sec}").unwrap(),
+        ),
+        None => ProgressBar::new_spinner()
+            .with_style(ProgressStyle::with_template("{spinner} {bytes} {bytes_per_sec}").unwrap()),
+    }
+}
+
+fn default_limits_file() -> Option<PathBuf> {
+    env::var_os(LIMITS_FILE_ENV).map(PathBuf::from).or_else(|| {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(".config/filebox/limits.json"))
+    })
+}
+
+/// In the local time zone of the client, with its offset.
+fn format_timestamp(ts: i64) -> String {
+    Local
+        .timestamp_opt(ts, 0)
+        .single()
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S %:z").to_string())
+        .unwrap_or_else(|| ts.to_string())
+}
+
+/// Run a parsed command line, printing the results.
+pub async fn run(args: ClientArgs) -> Result<(), ClientError> {
+    let server = args
+        .server
+        .or_else(|| env::var(SERVER_ENV).ok())
+        .unwrap_or_else(|| DEFAULT_SERVER.to_string());
+    let client = CliClient::new(server, default_limits_file());
+
+    match args.command {
+        Command::Help => print!("{}", ClientArgs::usage()),
+        Command::Send {
+            source,
+            name,
+            duration_day,
+        } => {
+            let created = client.send(&source, name.as_deref(), duration_day).await?;
+            println!("{}", created.code);
+            eprintln!(
+                "{} expires at {}",
+                created.name,
+                format_timestamp(created.expired_at)
+            );
+        }
+        Command::Info { code } => {
+            let filebox = client.info(&code).await?;
+            let file_type = match filebox.file_type {
+                FileboxFileType::File => "file",
+                FileboxFileType::Text => "text",
+            };
+            println!("code:       {}", filebox.code);
+            println!("name:       {}", filebox.name);
+            println!("type:       {file_type}");
+            println!("created at: {}", format_timestamp(filebox.created_at));
+            println!("expires at: {}", format_timestamp(filebox.expired_at));
+        }
+        Command::Get { code, output } => {
+            if let Some(path) = client.get(&code, output.as_deref()).await? {
+                eprintln!("saved to {}", path.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<ClientArgs, ClientError> {
+        ClientArgs::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn client_args_should_parse() {
+        let args = parse(&["--server=http://box", "send", "-", "-n", "log", "-d", "3"]).unwrap();
+        assert_eq!(args.server.as_deref(), Some("http://box"));
+        assert_eq!(
+            args.command,
+            Command::Send {
+                source: "-".to_string(),
+                name: Some("log".to_string()),
+                duration_day: Some(3),
+            }
+        );
+
+        let args = parse(&["get", "abcde", "--output", "out.txt"]).unwrap();
+        assert_eq!(
+            args.command,
+            Command::Get {
+                code: "abcde".to_string(),
+                output: Some(PathBuf::from("out.txt")),
+            }
+        );
+
+        assert_eq!(parse(&[]).unwrap().command, Command::Help);
+        assert!(parse(&["send"]).is_err());
+        assert!(parse(&["info", "a", "b"]).is_err());
+        assert!(parse(&["get", "a", "--bogus"]).is_err());
+        assert!(parse(&["send", "f", "-d", "many"]).is_err());
+    }
+}
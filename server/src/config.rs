@@ -0,0 +1,697 @@
+use std::{collections::BTreeMap, fmt, fs, path::PathBuf, str::FromStr};
+
+use ipnet::IpNet;
+use serde::{Serialize, Serializer};
+
+use crate::{
+    api::MAX_UPLOAD_SIZE_CEILING,
+    data::{limiter::LimiterFailurePolicy, redis::parse_ip_net_list},
+    rate_limit::{parse_rate_limit_routes, RateLimitBackendKind, RateLimitRule},
+    telemetry::LogFormat,
+};
+
+pub const DEFAULT_CONFIG_FILE: &str = "filebox.toml";
+pub const CONFIG_FILE_ENV: &str = "FILEBOX_CONFIG";
+
+pub struct ConfigKey {
+    pub name: &'static str,
+    pub default: Option<&'static str>,
+    pub help: &'static str,
+}
+
+/// Every configuration key, named as in the TOML file. The environment
+/// variable is the upper case name and the command line flag is the name in
+/// kebab case, e.g. `code_len`, `CODE_LEN` and `--code-len`.
+pub const CONFIG_KEYS: &[ConfigKey] = &[
+    ConfigKey {
+        name: "database_url",
+        default: None,
+        help: "postgres://... or sqlite:path/to/filebox.db",
+    },
+    ConfigKey {
+        name: "http_server_addr",
+        default: Some("0.0.0.0:8888"),
+        help: "address the http server binds to",
+    },
+    ConfigKey {
+        name: "upload_file_path",
+        default: Some("uploaded"),
+        help: "directory the uploaded files are stored in",
+    },
+    ConfigKey {
+        name: "storage_min_free_bytes",
+        default: Some("104857600"),
+        help: "the server is not ready when the upload directory has less free space",
+    },
+    ConfigKey {
+        name: "log_format",
+        default: Some("text"),
+        help: "text or json, how log lines are written",
+    },
+    ConfigKey {
+        name: "graceful_shutdown_timeout_sec",
+        default: Some("5"),
+        help: "seconds to wait for in-flight requests on shutdown",
+    },
+    ConfigKey {
+        name: "code_len",
+        default: Some("5"),
+        help: "length of the generated filebox codes, 1 to 10",
+    },
+    ConfigKey {
+        name: "max_upload_size",
+        default: Some("52428800"),
+        help: "largest accepted file in bytes, reloadable",
+    },
+    ConfigKey {
+        name: "min_duration_day",
+        default: Some("1"),
+        help: "shortest expiry of a filebox in days, reloadable",
+    },
+    ConfigKey {
+        name: "max_duration_day",
+        default: Some("29"),
+        help: "longest expiry of a filebox in days, reloadable",
+    },
+    ConfigKey {
+        name: "allowed_origin",
+        default: Some("http://127.0.0.1:5173"),
+        help: "origin allowed by CORS",
+    },
+    ConfigKey {
+        name: "admin_token",
+        default: Some(""),
+        help: "bearer token of the /admin api and /metrics, empty disables them",
+    },
+    ConfigKey {
+        name: "redis_conn_addr",
+        default: Some(""),
+        help: "redis address as host:port, empty keeps the ip limits in the process",
+    },
+    ConfigKey {
+        name: "ip_limit_snapshot_path",
+        default: Some(""),
+        help: "without redis, file the ip limits are saved to every minute, empty disables it",
+    },
+    ConfigKey {
+        name: "redis_failure_policy",
+        default: Some("open"),
+        help: "open or closed, what the ip limiter does when redis is down",
+    },
+    ConfigKey {
+        name: "ip_visit_error_limit",
+        default: Some("5"),
+        help: "wrong code lookups allowed per ip and day",
+    },
+    ConfigKey {
+        name: "ip_visit_error_duration_day",
+        default: Some("1"),
+        help: "days an ip stays limited",
+    },
+    ConfigKey {
+        name: "ip_upload_limit",
+        default: Some("5"),
+        help: "uploads allowed per ip and day",
+    },
+    ConfigKey {
+        name: "ip_allow_list",
+        default: Some(""),
+        help: "comma separated CIDRs exempt from the ip limits",
+    },
+    ConfigKey {
+        name: "ip_deny_list",
+        default: Some(""),
+        help: "comma separated CIDRs always rejected",
+    },
+    ConfigKey {
+        name: "trusted_proxies",
+        default: Some("127.0.0.1,::1"),
+        help: "comma separated CIDRs of the reverse proxies whose X-REAL-IP is trusted",
+    },
+    ConfigKey {
+        name: "lookup_failure_threshold",
+        default: Some("100"),
+        help: "server wide failed lookups per minute before lookups are delayed",
+    },
+    ConfigKey {
+        name: "lookup_failure_delay_ms",
+        default: Some("100"),
+        help: "delay added per failed lookup over the threshold",
+    },
+    ConfigKey {
+        name: "lookup_failure_max_delay_ms",
+        default: Some("5000"),
+        help: "upper bound of the lookup delay",
+    },
+    ConfigKey {
+        name: "rate_limit_backend",
+        default: Some("memory"),
+        help: "memory or redis, where the global rate limit counters live",
+    },
+    ConfigKey {
+        name: "rate_limit_window_sec",
+        default: Some("1"),
+        help: "window of the global rate limit",
+    },
+    ConfigKey {
+        name: "rate_limit_max_requests",
+        default: Some("60"),
+        help: "requests allowed per ip and window",
+    },
+    ConfigKey {
+        name: "rate_limit_routes",
+        default: Some(""),
+        help: "per route overrides as path=max/window_sec, comma separated",
+    },
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Config {
+    pub database_url: String,
+    pub http_server_addr: String,
+    pub upload_file_path: String,
+    pub storage_min_free_bytes: u64,
+    pub log_format: LogFormat,
+    pub graceful_shutdown_timeout_sec: u64,
+    pub code_len: usize,
+    pub max_upload_size: usize,
+    pub min_duration_day: u8,
+    pub max_duration_day: u8,
+    pub allowed_origin: String,
+    #[serde(serialize_with = "serialize_secret")]
+    pub admin_token: Option<String>,
+    /// Unset when redis is not used, see `ip_limit_snapshot_path`.
+    pub redis_conn_addr: Option<String>,
+    pub ip_limit_snapshot_path: Option<String>,
+    pub redis_failure_policy: LimiterFailurePolicy,
+    pub ip_visit_error_limit: i32,
+    pub ip_visit_error_duration_day: i64,
+    pub ip_upload_limit: i32,
+    #[serde(serialize_with = "serialize_display_list")]
+    pub ip_allow_list: Vec<IpNet>,
+    #[serde(serialize_with = "serialize_display_list")]
+    pub ip_deny_list: Vec<IpNet>,
+    #[serde(serialize_with = "serialize_display_list")]
+    pub trusted_proxies: Vec<IpNet>,
+    pub lookup_failure_threshold: i64,
+    pub lookup_failure_delay_ms: u64,
+    pub lookup_failure_max_delay_ms: u64,
+    pub rate_limit_backend: RateLimitBackendKind,
+    pub rate_limit_window_sec: u64,
+    pub rate_limit_max_requests: u64,
+    #[serde(serialize_with = "serialize_display_list")]
+    pub rate_limit_routes: Vec<RateLimitRule>,
+}
+
+/// All the problems found while loading the configuration.
+#[derive(Debug)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "invalid configuration:")?;
+        for err in &self.0 {
+            writeln!(f, "  - {err}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Default)]
+pub struct CliArgs {
+    pub config_file: Option<PathBuf>,
+    pub print_config: bool,
+    pub help: bool,
+    pub values: BTreeMap<String, String>,
+}
+
+impl CliArgs {
+    /// Parse `--config <file>`, `--print-config`, `--help` and one
+    /// `--<key> <value>` (or `--<key>=<value>`) flag per configuration key.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let mut cli = CliArgs::default();
+        let mut errors = vec![];
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let flag = match arg.strip_prefix("--") {
+                Some(flag) => flag,
+                None => {
+                    errors.push(format!("unexpected argument {arg}"));
+                    continue;
+                }
+            };
+            let (flag, inline_value) = match flag.split_once('=') {
+                Some((flag, value)) => (flag, Some(value.to_string())),
+                None => (flag, None),
+            };
+
+            match flag {
+                "help" => cli.help = true,
+                "print-config" => cli.print_config = true,
+                _ => {
+                    let value = match inline_value.or_else(|| args.next()) {
+                        Some(value) => value,
+                        None => {
+                            errors.push(format!("--{flag} requires a value"));
+                            continue;
+                        }
+                    };
+                    if flag == "config" {
+                        cli.config_file = Some(PathBuf::from(value));
+                        continue;
+                    }
+
+                    let name = flag.replace('-', "_");
+                    if CONFIG_KEYS.iter().any(|key| key.name == name) {
+                        cli.values.insert(name, value);
+                    } else {
+                        errors.push(format!("unknown flag --{flag}"));
+                    }
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(ConfigError(errors));
+        }
+        Ok(cli)
+    }
+
+    pub fn usage() -> String {
+        let mut usage = String::from(
+            "Usage: filebox-server [--config <file>] [--print-config] [--<key> <value>]...\n\n",
+        );
+        usage.push_str(&format!(
+            "Values are read from defaults, the config file ({CONFIG_FILE_ENV} or {DEFAULT_CONFIG_FILE}),\nthe environment and the flags, later sources win.\n\n"
+        ));
+        for key in CONFIG_KEYS {
+            let default = match key.default {
+                Some(default) => format!(" [default: {default:?}]"),
+                None => " [required]".to_string(),
+            };
+            usage.push_str(&format!(
+                "  --{:<30} {}{}\n",
+                key.name.replace('_', "-"),
+                key.help,
+                default
+            ));
+        }
+        usage
+    }
+}
+
+impl Config {
+    /// Load the configuration of the server process.
+    pub fn load(cli: &CliArgs) -> Result<Self, ConfigError> {
+        let config_file = cli
+            .config_file
+            .clone()
+            .or_else(|| std::env::var(CONFIG_FILE_ENV).ok().map(PathBuf::from));
+        let file = match config_file {
+            Some(path) => Some(
+                fs::read_to_string(&path)
+                    .map_err(|err| ConfigError(vec![format!("read {}: {err}", path.display())]))?,
+            ),
+            None => fs::read_to_string(DEFAULT_CONFIG_FILE).ok(),
+        };
+
+        Self::from_sources(file.as_deref(), std::env::vars(), &cli.values)
+    }
+
+    /// Layer defaults, the TOML file, the environment and the flags, then
+    /// validate everything at once.
+    pub fn from_sources(
+        file: Option<&str>,
+        env: impl IntoIterator<Item = (String, String)>,
+        flags: &BTreeMap<String, String>,
+    ) -> Result<Self, ConfigError> {
+        let mut errors = vec![];
+        let mut raw: BTreeMap<String, String> = CONFIG_KEYS
+            .iter()
+            .filter_map(|key| {
+                key.default
+                    .map(|default| (key.name.to_string(), default.to_string()))
+            })
+            .collect();
+
+        if let Some(file) = file {
+            match file.parse::<toml::Table>() {
+                Ok(table) => {
+                    for (name, value) in table {
+                        if !CONFIG_KEYS.iter().any(|key| key.name == name) {
+                            errors.push(format!("unknown key {name} in config file"));
+                            continue;
+                        }
+                        match toml_value_to_string(&value) {
+                            Some(value) => {
+                                raw.insert(name, value);
+                            }
+                            None => errors
+                                .push(format!("{name} in config file has an unsupported type")),
+                        }
+                    }
+                }
+                Err(err) => errors.push(format!("parse config file: {err}")),
+            }
+        }
+
+        let env: BTreeMap<String, String> = env.into_iter().collect();
+        for key in CONFIG_KEYS {
+            // an empty variable, like the ones left blank in .env, does not override
+            match env.get(&key.name.to_uppercase()) {
+                Some(value) if !value.is_empty() => {
+                    raw.insert(key.name.to_string(), value.clone());
+                }
+                _ => {}
+            }
+        }
+
+        raw.extend(flags.clone());
+
+        let mut parser = Parser {
+            raw: &raw,
+            errors: &mut errors,
+        };
+        let config = Config {
+            database_url: parser.string("database_url"),
+            http_server_addr: parser.string("http_server_addr"),
+            upload_file_path: parser.string("upload_file_path"),
+            storage_min_free_bytes: parser.parse("storage_min_free_bytes"),
+            log_format: parser.parse("log_format"),
+            graceful_shutdown_timeout_sec: parser.parse("graceful_shutdown_timeout_sec"),
+            code_len: parser.parse("code_len"),
+            max_upload_size: parser.parse("max_upload_size"),
+            min_duration_day: parser.parse("min_duration_day"),
+            max_duration_day: parser.parse("max_duration_day"),
+            allowed_origin: parser.string("allowed_origin"),
+            admin_token: Some(parser.string("admin_token")).filter(|token| !token.is_empty()),
+            redis_conn_addr: Some(parser.string("redis_conn_addr")).filter(|addr| !addr.is_empty()),
+            ip_limit_snapshot_path: Some(parser.string("ip_limit_snapshot_path"))
+                .filter(|path| !path.is_empty()),
+            redis_failure_policy: parser.parse("redis_failure_policy"),
+            ip_visit_error_limit: parser.parse("ip_visit_error_limit"),
+            ip_visit_error_duration_day: parser.parse("ip_visit_error_duration_day"),
+            ip_upload_limit: parser.parse("ip_upload_limit"),
+            ip_allow_list: parser.with("ip_allow_list", |v| {
+                parse_ip_net_list(v).map_err(|err| err.to_string())
+            }),
+            ip_deny_list: parser.with("ip_deny_list", |v| {
+                parse_ip_net_list(v).map_err(|err| err.to_string())
+            }),
+            trusted_proxies: parser.with("trusted_proxies", |v| {
+                parse_ip_net_list(v).map_err(|err| err.to_string())
+            }),
+            lookup_failure_threshold: parser.parse("lookup_failure_threshold"),
+            lookup_failure_delay_ms: parser.parse("lookup_failure_delay_ms"),
+            lookup_failure_max_delay_ms: parser.parse("lookup_failure_max_delay_ms"),
+            rate_limit_backend: parser.parse("rate_limit_backend"),
+            rate_limit_window_sec: parser.parse("rate_limit_window_sec"),
+            rate_limit_max_requests: parser.parse("rate_limit_max_requests"),
+            rate_limit_routes: parser.with("rate_limit_routes", parse_rate_limit_routes),
+        };
+
+        // a key that failed to parse holds a placeholder, don't report it twice
+        for (name, err) in config.validate() {
+            if !errors.iter().any(|e| e.starts_with(name)) {
+                errors.push(err);
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(ConfigError(errors));
+        }
+        Ok(config)
+    }
+
+    /// Range checks, keyed by the name of the offending key.
+    fn validate(&self) -> Vec<(&'static str, String)> {
+        let mut errors = vec![];
+        if self.database_url.is_empty() {
+            errors.push(("database_url", "database_url is required".to_string()));
+        } else if !["postgres://", "postgresql://", "sqlite:"]
+            .iter()
+            .any(|scheme| self.database_url.starts_with(scheme))
+        {
+            errors.push((
+                "database_url",
+                "database_url must start with postgres://, postgresql:// or sqlite:".to_string(),
+            ));
+        }
+        if !(1..=10).contains(&self.code_len) {
+            errors.push((
+                "code_len",
+                format!(
+                    "code_len should be between 1 and 10 but got {}",
+                    self.code_len
+                ),
+            ));
+        }
+        if self.max_upload_size == 0 || self.max_upload_size > MAX_UPLOAD_SIZE_CEILING {
+            errors.push((
+                "max_upload_size",
+                format!("max_upload_size should be between 1 and {MAX_UPLOAD_SIZE_CEILING}"),
+            ));
+        }
+        if self.min_duration_day == 0 || self.min_duration_day > self.max_duration_day {
+            errors.push((
+                "min_duration_day",
+                "min_duration_day should be between 1 and max_duration_day".to_string(),
+            ));
+        }
+        if self.rate_limit_backend == RateLimitBackendKind::Redis && self.redis_conn_addr.is_none()
+        {
+            errors.push((
+                "rate_limit_backend",
+                "rate_limit_backend redis needs redis_conn_addr".to_string(),
+            ));
+        }
+        for (name, value) in [
+            ("ip_visit_error_limit", self.ip_visit_error_limit as i64),
+            ("ip_upload_limit", self.ip_upload_limit as i64),
+            (
+                "ip_visit_error_duration_day",
+                self.ip_visit_error_duration_day,
+            ),
+            ("rate_limit_window_sec", self.rate_limit_window_sec as i64),
+        ] {
+            if value <= 0 {
+                errors.push((name, format!("{name} should be greater than 0")));
+            }
+        }
+
+        errors
+    }
+
+    /// The configuration as TOML, secrets are masked.
+    pub fn to_toml(&self) -> String {
+        // SAFTEY: every field serializes to a TOML value
+        toml::to_string(self).unwrap()
+    }
+}
+
+struct Parser<'a> {
+    raw: &'a BTreeMap<String, String>,
+    errors: &'a mut Vec<String>,
+}
+
+impl Parser<'_> {
+    fn string(&mut self, name: &str) -> String {
+        self.with(name, |value| Ok(value.to_string()))
+    }
+
+    fn parse<T>(&mut self, name: &str) -> T
+    where
+        T: FromStr + Default,
+        T::Err: fmt::Display,
+    {
+        self.with(name, |value| {
+            value.parse().map_err(|err: T::Err| err.to_string())
+        })
+    }
+
+    fn with<T: Default>(&mut self, name: &str, f: impl Fn(&str) -> Result<T, String>) -> T {
+        let value = match self.raw.get(name) {
+            Some(value) => value,
+            None => {
+                self.errors.push(format!("{name} is required"));
+                return T::default();
+            }
+        };
+        match f(value.trim()) {
+            Ok(value) => value,
+            Err(err) => {
+                self.errors
+                    .push(format!("{name} is invalid, got {value:?}: {err}"));
+                T::default()
+            }
+        }
+    }
+}
+
+fn toml_value_to_string(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(value) => Some(value.clone()),
+        toml::Value::Integer(value) => Some(value.to_string()),
+        toml::Value::Float(value) => Some(value.to_string()),
+        toml::Value::Boolean(value) => Some(value.to_string()),
+        toml::Value::Array(values) => values
+            .iter()
+            .map(toml_value_to_string)
+            .collect::<Option<Vec<_>>>()
+            .map(|values| values.join(",")),
+        toml::Value::Datetime(_) | toml::Value::Table(_) => None,
+    }
+}
+
+fn serialize_secret<S: Serializer>(value: &Option<String>, s: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(_) => s.serialize_str("********"),
+        None => s.serialize_str(""),
+    }
+}
+
+fn serialize_display_list<T: fmt::Display, S: Serializer>(
+    values: &[T],
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    s.collect_seq(values.iter().map(ToString::to_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::UploadLimits;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn config_sources_should_follow_precedence() {
+        let file = r#"
+            database_url = "postgres://file"
+            code_len = 6
+            ip_upload_limit = 7
+            ip_allow_list = ["10.0.0.0/8", "192.168.0.1"]
+        "#;
+        let cli = CliArgs::parse(
+            ["--ip-upload-limit=9", "--print-config"]
+                .iter()
+                .map(|s| s.to_string()),
+        )
+        .unwrap();
+        assert!(cli.print_config);
+
+        let config = Config::from_sources(
+            Some(file),
+            env(&[
+                ("CODE_LEN", "8"),
+                ("IP_UPLOAD_LIMIT", "8"),
+                ("UNRELATED", "x"),
+            ]),
+            &cli.values,
+        )
+        .unwrap();
+
+        assert_eq!(config.database_url, "postgres://file");
+        assert_eq!(config.code_len, 8);
+        assert_eq!(config.ip_upload_limit, 9);
+        assert_eq!(config.ip_allow_list.len(), 2);
+        assert_eq!(config.http_server_addr, "0.0.0.0:8888");
+        assert_eq!(config.redis_failure_policy, LimiterFailurePolicy::FailOpen);
+        assert!(config.admin_token.is_none());
+        assert!(config.redis_conn_addr.is_none());
+    }
+
+    #[test]
+    fn config_should_check_upload_limits() {
+        let config = Config::from_sources(
+            Some(r#"database_url = "postgres://file""#),
+            env(&[("MAX_DURATION_DAY", "7")]),
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        let limits = UploadLimits::from(&config);
+        assert_eq!(
+            limits.max_upload_size,
+            UploadLimits::default().max_upload_size
+        );
+        assert_eq!(limits.max_duration_day, 7);
+
+        let err = Config::from_sources(
+            Some(r#"database_url = "postgres://file""#),
+            env(&[
+                ("MAX_UPLOAD_SIZE", "2147483648"),
+                ("MIN_DURATION_DAY", "8"),
+                ("MAX_DURATION_DAY", "7"),
+            ]),
+            &BTreeMap::new(),
+        )
+        .unwrap_err();
+        assert_eq!(err.0.len(), 2, "{err}");
+
+        let config = Config::from_sources(
+            Some(r#"database_url = "sqlite:data/filebox.db""#),
+            env(&[]),
+            &BTreeMap::new(),
+        );
+        assert!(config.is_ok());
+        let err = Config::from_sources(
+            Some(r#"database_url = "mysql://file""#),
+            env(&[]),
+            &BTreeMap::new(),
+        )
+        .unwrap_err();
+        assert!(err.0[0].starts_with("database_url"), "{err}");
+    }
+
+    #[test]
+    fn config_should_report_all_errors() {
+        let err = Config::from_sources(
+            Some("unknown_key = 1"),
+            env(&[
+                ("CODE_LEN", "abc"),
+                ("IP_DENY_LIST", "10.0.0.0/99"),
+                ("IP_UPLOAD_LIMIT", "0"),
+            ]),
+            &BTreeMap::new(),
+        )
+        .unwrap_err();
+
+        assert_eq!(err.0.len(), 5, "{err}");
+        assert!(err.0.iter().any(|e| e.starts_with("ip_upload_limit")));
+        assert!(err.0.iter().any(|e| e.contains("unknown_key")));
+        assert!(err.0.iter().any(|e| e.starts_with("database_url")));
+        assert!(err.0.iter().any(|e| e.starts_with("code_len")));
+        assert!(err.0.iter().any(|e| e.starts_with("ip_deny_list")));
+    }
+
+    #[test]
+    fn cli_args_should_reject_unknown_flags() {
+        let err = CliArgs::parse(["--nope", "1", "--code-len"].iter().map(|s| s.to_string()))
+            .unwrap_err();
+        assert_eq!(err.0.len(), 2);
+    }
+
+    #[test]
+    fn print_config_should_mask_secrets() {
+        let config = Config::from_sources(
+            None,
+            env(&[
+                ("DATABASE_URL", "postgres://env"),
+                ("ADMIN_TOKEN", "secret"),
+            ]),
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        let printed = config.to_toml();
+        assert!(printed.contains("database_url = \"postgres://env\""));
+        assert!(!printed.contains("secret"));
+        assert!(printed.contains("redis_failure_policy = \"open\""));
+    }
+}
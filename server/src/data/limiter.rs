@@ -0,0 +1,418 @@
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use serde::Serialize;
+
+use crate::{
+    api::IpInfo, api::RedisActorAddr, data::memory::MemoryIpLimitStore, errors, metrics::Metrics,
+};
+
+/// Storage of the per day ip counters used by the ip limit middlewares.
+#[async_trait(?Send)]
+pub trait IpLimitStore {
+    async fn get_ip_info(&self, ip: &str) -> Result<Option<IpInfo>, errors::Error>;
+
+    /// `ttl` is given in seconds.
+    async fn set_ip_info(&self, ip: &str, ip_info: &IpInfo, ttl: i64) -> Result<(), errors::Error>;
+
+    async fn get_counter(&self, key: &str) -> Result<i64, errors::Error>;
+
+    /// Increase the counter and returns the new value, `ttl` is only applied
+    /// when the counter is created.
+    async fn incr_counter(&self, key: &str, ttl: i64) -> Result<i64, errors::Error>;
+}
+
+/// What to do with a request when redis can not be reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum LimiterFailurePolicy {
+    /// Keep serving and count in memory until redis is back.
+    #[default]
+    #[serde(rename = "open")]
+    FailOpen,
+    /// Reject the request.
+    #[serde(rename = "closed")]
+    FailClosed,
+}
+
+impl FromStr for LimiterFailurePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(LimiterFailurePolicy::FailOpen),
+            "closed" => Ok(LimiterFailurePolicy::FailClosed),
+            _ => Err(format!("expected open or closed but got {s}")),
+        }
+    }
+}
+
+/// Redis backed ip limiter, falling back to an in-memory store when redis is
+/// unavailable and the policy allows it. Without redis the in-memory store is
+/// the only one.
+pub struct IpLimiter {
+    redis: Option<Arc<RedisActorAddr>>,
+    memory: Arc<MemoryIpLimitStore>,
+    policy: LimiterFailurePolicy,
+    fallback_active: AtomicBool,
+    metrics: Arc<Metrics>,
+}
+
+impl IpLimiter {
+    pub fn new(
+        redis: Option<Arc<RedisActorAddr>>,
+        policy: LimiterFailurePolicy,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        Self {
+            redis,
+            memory: Arc::new(MemoryIpLimitStore::new()),
+            policy,
+            fallback_active: AtomicBool::new(false),
+            metrics,
+        }
+    }
+
+    pub fn policy(&self) -> LimiterFailurePolicy {
+        self.policy
+    }
+
+    /// The in-memory store, used as the fallback of redis or on its own.
+    pub fn memory_store(&self) -> Arc<MemoryIpLimitStore> {
+        self.memory.clone()
+    }
+
+    pub fn is_fallback_active(&self) -> bool {
+        self.fallback_active.load(Ordering::Relaxed)
+    }
+
+    fn on_redis_ok(&self) {
+        if self.fallback_active.swap(false, Ordering::Relaxed) {
+            tracing::info!("redis is available again, ip limiter leaves fallback mode");
+        }
+    }
+
+    fn on_redis_err(&self, err: errors::Error) -> Result<(), errors::Error> {
+        match self.policy {
+            LimiterFailurePolicy::FailOpen => {
+                if !self.fallback_active.swap(true, Ordering::Relaxed) {
+                    tracing::warn!(
+                        "redis is unavailable, ip limiter falls back to memory: {err:?}"
+                    );
+                }
+                Ok(())
+            }
+            LimiterFailurePolicy::FailClosed => {
+                tracing::error!("redis is unavailable, reject the request: {err:?}");
+                Err(errors::Error::LimiterUnavailable)
+            }
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl IpLimitStore for IpLimiter {
+    async fn get_ip_info(&self, ip: &str) -> Result<Option<IpInfo>, errors::Error> {
+        let Some(redis) = &self.redis else {
+            return self.memory.get_ip_info(ip).await;
+        };
+        match self
+            .metrics
+            .time_redis("get_ip_info", redis.get_ip_info(ip))
+            .await
+        {
+            Ok(ip_info) => {
+                self.on_redis_ok();
+                Ok(ip_info)
+            }
+            Err(err) => {
+                self.on_redis_err(err)?;
+                self.memory.get_ip_info(ip).await
+            }
+        }
+    }
+
+    async fn set_ip_info(&self, ip: &str, ip_info: &IpInfo, ttl: i64) -> Result<(), errors::Error> {
+        let Some(redis) = &self.redis else {
+            return self.memory.set_ip_info(ip, ip_info, ttl).await;
+        };
+        match self
+            .metrics
+            .time_redis("set_ip_info", redis.set_ip_info(ip, ip_info, ttl))
+            .await
+        {
+            Ok(()) => {
+                self.on_redis_ok();
+                Ok(())
+            }
+            Err(err) => {
+                self.on_redis_err(err)?;
+                self.memory.set_ip_info(ip, ip_info, ttl).await
+            }
+        }
+    }
+
+    async fn get_counter(&self, key: &str) -> Result<i64, errors::Error> {
+        let Some(redis) = &self.redis else {
+            return self.memory.get_counter(key).await;
+        };
+        match self
+            .metrics
+            .time_redis("get_counter", redis.get_counter(key))
+            .await
+        {
+            Ok(count) => {
+                self.on_redis_ok();
+                Ok(count)
+            }
+            Err(err) => {
+                self.on_redis_err(err)?;
+                self.memory.get_counter(key).await
+            }
+        }
+    }
+
+    async fn incr_counter(&self, key: &str, ttl: i64) -> Result<i64, errors::Error> {
+        let Some(redis) = &self.redis else {
+            return self.memory.incr_counter(key, ttl).await;
+        };
+        match self
+            .metrics
+            .time_redis("incr_counter", redis.incr_counter(key, ttl))
+            .await
+        {
+            Ok(count) => {
+                self.on_redis_ok();
+                Ok(count)
+            }
+            Err(err) => {
+                self.on_redis_err(err)?;
+                self.memory.incr_counter(key, ttl).await
+            }
+        }
+    }
+}
+
+/// Server wide tracking of failed code lookups, once the failures of the
+/// current minute pass the threshold every lookup is delayed a bit more.
+#[derive(Debug, Clone)]
+pub struct LookupGuard {
+    pub failure_threshold: i64,
+    pub delay_step: std::time::Duration,
+    pub max_delay: std::time::Duration,
+}
+
+impl LookupGuard {
+    pub fn new(
+        failure_threshold: i64,
+        delay_step: std::time::Duration,
+        max_delay: std::time::Duration,
+    ) -> Self {
+        Self {
+            failure_threshold,
+            delay_step,
+            max_delay,
+        }
+    }
+
+    pub fn delay(&self, failure_count: i64) -> std::time::Duration {
+        let over = failure_count - self.failure_threshold;
+        if over <= 0 {
+            return std::time::Duration::ZERO;
+        }
+
+        let over = u32::try_from(over).unwrap_or(u32::MAX);
+        self.delay_step.saturating_mul(over).min(self.max_delay)
+    }
+}
+
+pub async fn get_lookup_failure_count(store: &impl IpLimitStore) -> Result<i64, errors::Error> {
+    store.get_counter(&lookup_failure_key()).await
+}
+
+pub async fn add_lookup_failure_count(store: &impl IpLimitStore) -> Result<i64, errors::Error> {
+    // keep the key a bit longer than its minute so late increments still expire
+    store.incr_counter(&lookup_failure_key(), 120).await
+}
+
+fn lookup_failure_key() -> String {
+    let minute = Utc::now().timestamp() / 60;
+    format!("filebox:lookup_failures:{minute}")
+}
+
+// 每个 ip 的计数用 incr_counter 原子递增, 读出 IpInfo 加一再写回在并发的请求下会少计
+fn ip_visit_error_key(ip: &str) -> String {
+    format!("filebox:visit_errors:{ip}")
+}
+
+fn ip_upload_key(ip: &str) -> String {
+    format!("filebox:uploads:{ip}")
+}
+
+pub async fn get_ip_visit_error_count(
+    store: &impl IpLimitStore,
+    ip: &str,
+) -> Result<i32, errors::Error> {
+    let count = store.get_counter(&ip_visit_error_key(ip)).await?;
+    Ok(i32::try_from(count).unwrap_or(i32::MAX))
+}
+
+pub async fn is_allow_ip_for_visit(
+    store: &impl IpLimitStore,
+    ip: &str,
+    visit_error_limit: i32,
+) -> Result<bool, errors::Error> {
+    Ok(get_ip_visit_error_count(store, ip).await? < visit_error_limit)
+}
+
+/// Returns the visit error count of the ip after the increment.
+pub async fn add_ip_visit_error_limit_count(
+    store: &impl IpLimitStore,
+    ip: &str,
+    ttl: i64,
+) -> Result<i32, errors::Error> {
+    let count = store
+        .incr_counter(&ip_visit_error_key(ip), get_ttl(ttl))
+        .await?;
+    Ok(i32::try_from(count).unwrap_or(i32::MAX))
+}
+
+pub async fn is_allow_ip_for_upload(
+    store: &impl IpLimitStore,
+    ip: &str,
+    upload_limit: i32,
+) -> Result<bool, errors::Error> {
+    let count = store.get_counter(&ip_upload_key(ip)).await?;
+    Ok(count < i64::from(upload_limit))
+}
+
+pub async fn add_ip_upload_limit_count(
+    store: &impl IpLimitStore,
+    ip: &str,
+    ttl: i64,
+) -> Result<(), errors::Error> {
+    store.incr_counter(&ip_upload_key(ip), get_ttl(ttl)).await?;
+    Ok(())
+}
+
+/// Seconds until the UTC midnight `ttl` days ahead, so the daily counters
+/// roll over at the same time whatever `TZ` the server runs in.
+fn get_ttl(ttl: i64) -> i64 {
+    let now = Utc::now();
+
+    let tomorrow_midnight = (now + Duration::days(ttl))
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc();
+
+    tomorrow_midnight
+        .signed_duration_since(now)
+        .to_std()
+        .unwrap()
+        .as_secs() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_redis::RedisActor;
+
+    use super::*;
+
+    #[test]
+    fn get_ttl_should_end_at_utc_midnight() {
+        let ttl = get_ttl(1);
+        assert!(ttl <= 24 * 60 * 60);
+        let expires_at = Utc::now() + Duration::seconds(ttl + 1);
+        assert_eq!(
+            expires_at.date_naive(),
+            Utc::now().date_naive().succ_opt().unwrap()
+        );
+    }
+
+    #[actix_rt::test]
+    async fn ip_limiter_should_fallback_when_redis_unavailable() {
+        // nothing listens on the port, every command fails with not connected
+        let redis = Arc::new(RedisActor::start("127.0.0.1:1"));
+        let ip = "127.0.0.1";
+
+        let limiter = IpLimiter::new(
+            Some(redis.clone()),
+            LimiterFailurePolicy::FailOpen,
+            Arc::new(Metrics::new()),
+        );
+        assert!(is_allow_ip_for_upload(&limiter, ip, 1).await.unwrap());
+        assert!(limiter.is_fallback_active());
+        add_ip_upload_limit_count(&limiter, ip, 1).await.unwrap();
+        assert!(!is_allow_ip_for_upload(&limiter, ip, 1).await.unwrap());
+
+        assert_eq!(add_lookup_failure_count(&limiter).await.unwrap(), 1);
+        assert_eq!(add_lookup_failure_count(&limiter).await.unwrap(), 2);
+        assert_eq!(get_lookup_failure_count(&limiter).await.unwrap(), 2);
+
+        let limiter = IpLimiter::new(
+            Some(redis),
+            LimiterFailurePolicy::FailClosed,
+            Arc::new(Metrics::new()),
+        );
+        let err = is_allow_ip_for_upload(&limiter, ip, 1).await.unwrap_err();
+        assert!(matches!(err, errors::Error::LimiterUnavailable));
+        assert!(!limiter.is_fallback_active());
+    }
+
+    #[actix_rt::test]
+    async fn ip_limiter_should_work_without_redis() {
+        let ip = "127.0.0.1";
+        // the closed policy only applies to redis failures
+        let limiter = IpLimiter::new(
+            None,
+            LimiterFailurePolicy::FailClosed,
+            Arc::new(Metrics::new()),
+        );
+        add_ip_upload_limit_count(&limiter, ip, 1).await.unwrap();
+        assert!(!is_allow_ip_for_upload(&limiter, ip, 1).await.unwrap());
+        assert!(!limiter.is_fallback_active());
+        assert_eq!(
+            limiter
+                .memory_store()
+                .get_counter(&ip_upload_key(ip))
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[actix_rt::test]
+    async fn ip_counters_should_count_every_increment() {
+        let ip = "127.0.0.1";
+        let limiter = IpLimiter::new(
+            None,
+            LimiterFailurePolicy::FailOpen,
+            Arc::new(Metrics::new()),
+        );
+        let adds = (0..10).map(|_| add_ip_visit_error_limit_count(&limiter, ip, 1));
+        futures_util::future::join_all(adds).await;
+        assert_eq!(get_ip_visit_error_count(&limiter, ip).await.unwrap(), 10);
+        assert!(is_allow_ip_for_visit(&limiter, ip, 11).await.unwrap());
+        assert!(!is_allow_ip_for_visit(&limiter, ip, 10).await.unwrap());
+    }
+
+    #[test]
+    fn lookup_guard_delay_should_work() {
+        let guard = LookupGuard::new(
+            10,
+            std::time::Duration::from_millis(100),
+            std::time::Duration::from_secs(1),
+        );
+        assert_eq!(guard.delay(0), std::time::Duration::ZERO);
+        assert_eq!(guard.delay(10), std::time::Duration::ZERO);
+        assert_eq!(guard.delay(13), std::time::Duration::from_millis(300));
+        assert_eq!(guard.delay(1000), std::time::Duration::from_secs(1));
+    }
+}
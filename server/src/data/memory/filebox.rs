@@ -0,0 +1,405 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use async_trait::async_trait;
+use chrono::{NaiveDate, Utc};
+use ipnet::IpNet;
+
+use crate::{
+    api::IpRuleKind,
+    data::repository::FileboxRepository,
+    errors::Error,
+    models::{
+        daily_stats::{aggregate_events, DailyStats},
+        filebox::{AddFilebox, FileType, Filebox, FileboxFilter, TakeOutcome},
+        filebox_event::{AddFileboxEvent, FileboxEvent, FileboxEventFilter},
+    },
+};
+
+/// Fileboxes kept in the process and lost on restart, for the tests and for
+/// trying the server out without a database.
+#[derive(Debug, Default)]
+pub struct MemoryFileboxRepository {
+    fileboxes: Mutex<Vec<Filebox>>,
+    events: Mutex<Vec<FileboxEvent>>,
+    ip_rules: Mutex<Vec<(IpRuleKind, IpNet)>>,
+    daily_stats: Mutex<BTreeMap<NaiveDate, DailyStats>>,
+    // 与 BIGSERIAL 一样从 1 开始, 两张表各自计数
+    filebox_id: Mutex<i64>,
+    event_id: Mutex<i64>,
+}
+
+impl MemoryFileboxRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn next_id(id: &Mutex<i64>) -> i64 {
+    let mut id = id.lock().unwrap();
+    *id += 1;
+    *id
+}
+
+/// Newest first, as `ORDER BY id DESC LIMIT .. OFFSET ..`.
+fn page<T: Clone>(matched: Vec<&T>, limit: i64, offset: i64) -> (Vec<T>, i64) {
+    let total = matched.len() as i64;
+    let items = matched
+        .into_iter()
+        .rev()
+        .skip(offset.max(0) as usize)
+        .take(limit.max(0) as usize)
+        .cloned()
+        .collect();
+    (items, total)
+}
+
+fn filebox_matches(filebox: &Filebox, filter: &FileboxFilter) -> bool {
+    if let Some(keyword) = &filter.keyword {
+        let name = filebox.name.to_lowercase();
+        if filebox.code != *keyword && !name.contains(&keyword.to_lowercase()) {
+            return false;
+        }
+    }
+    filter.file_type.map_or(true, |t| filebox.file_type == t)
+        && filter
+            .created_from
+            .map_or(true, |t| filebox.created_at >= t)
+        && filter.created_to.map_or(true, |t| filebox.created_at < t)
+        && filter
+            .taken
+            .map_or(true, |taken| filebox.has_taken() == taken)
+        && filter.min_size.map_or(true, |size| filebox.size >= size)
+        && filter.max_size.map_or(true, |size| filebox.size <= size)
+}
+
+fn event_matches(event: &FileboxEvent, filter: &FileboxEventFilter) -> bool {
+    filter
+        .code
+        .as_ref()
+        .map_or(true, |code| event.code == *code)
+        && filter.ip.as_ref().map_or(true, |ip| event.ip == *ip)
+        && filter.event_type.map_or(true, |t| event.event_type == t)
+        && filter.created_from.map_or(true, |t| event.created_at >= t)
+        && filter.created_to.map_or(true, |t| event.created_at < t)
+}
+
+#[async_trait]
+impl FileboxRepository for MemoryFileboxRepository {
+    fn backend(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn ping(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn get_filebox(&self, code: &str) -> Result<Filebox, Error> {
+        let fileboxes = self.fileboxes.lock().unwrap();
+        fileboxes
+            .iter()
+            .find(|filebox| filebox.code == code)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn add_filebox(&self, filebox: AddFilebox) -> Result<Filebox, Error> {
+        let new_filebox = Filebox {
+            id: next_id(&self.filebox_id),
+            code: filebox.code,
+            name: filebox.name,
+            size: filebox.size,
+            file_type: filebox.file_type,
+            text: filebox.text,
+            file_path: filebox.file_path,
+            created_at: filebox.created_at,
+            expired_at: filebox.expired_at,
+            used_at: None,
+        };
+        self.fileboxes.lock().unwrap().push(new_filebox.clone());
+        Ok(new_filebox)
+    }
+
+    async fn take_filebox(&self, code: &str) -> Result<TakeOutcome, Error> {
+        let now = Utc::now();
+        let mut fileboxes = self.fileboxes.lock().unwrap();
+        let Some(filebox) = fileboxes.iter_mut().find(|filebox| filebox.code == code) else {
+            return Ok(TakeOutcome::NotFound);
+        };
+        let took = !filebox.has_taken() && filebox.expired_at > now;
+        if took {
+            filebox.used_at = Some(now);
+        }
+        Ok(TakeOutcome::new(Some(filebox.clone()), took))
+    }
+
+    async fn delete_expired_fileboxes(&self) -> Result<Vec<Filebox>, Error> {
+        let now = Utc::now();
+        let mut fileboxes = self.fileboxes.lock().unwrap();
+        let (deleted, kept) = fileboxes
+            .drain(..)
+            .partition(|filebox| filebox.expired_at <= now || filebox.has_taken());
+        *fileboxes = kept;
+        Ok(deleted)
+    }
+
+    async fn get_filebox_usage(&self) -> Result<(i64, i64), Error> {
+        let now = Utc::now();
+        let fileboxes = self.fileboxes.lock().unwrap();
+        let active = fileboxes
+            .iter()
+            .filter(|filebox| !filebox.has_taken() && filebox.expired_at > now)
+            .count() as i64;
+        let bytes = fileboxes
+            .iter()
+            .filter(|filebox| filebox.file_type == FileType::File)
+            .map(|filebox| filebox.size)
+            .sum();
+        Ok((active, bytes))
+    }
+
+    async fn get_filebox_by_id(&self, id: i64) -> Result<Filebox, Error> {
+        let fileboxes = self.fileboxes.lock().unwrap();
+        fileboxes
+            .iter()
+            .find(|filebox| filebox.id == id)
+            .cloned()
+            .ok_or(Error::NotFound)
+    }
+
+    async fn list_fileboxes(
+        &self,
+        filter: &FileboxFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<Filebox>, i64), Error> {
+        let fileboxes = self.fileboxes.lock().unwrap();
+        let matched = fileboxes
+            .iter()
+            .filter(|filebox| filebox_matches(filebox, filter))
+            .collect();
+        Ok(page(matched, limit, offset))
+    }
+
+    async fn delete_filebox_by_id(&self, id: i64) -> Result<Filebox, Error> {
+        let mut fileboxes = self.fileboxes.lock().unwrap();
+        let index = fileboxes
+            .iter()
+            .position(|filebox| filebox.id == id)
+            .ok_or(Error::NotFound)?;
+        Ok(fileboxes.remove(index))
+    }
+
+    async fn delete_fileboxes_by_ids(&self, ids: &[i64]) -> Result<Vec<Filebox>, Error> {
+        let mut fileboxes = self.fileboxes.lock().unwrap();
+        let (deleted, kept) = fileboxes
+            .drain(..)
+            .partition(|filebox| ids.contains(&filebox.id));
+        *fileboxes = kept;
+        Ok(deleted)
+    }
+
+    async fn add_filebox_events(&self, events: &[AddFileboxEvent]) -> Result<(), Error> {
+        let mut stored = self.events.lock().unwrap();
+        for event in events {
+            stored.push(FileboxEvent {
+                id: next_id(&self.event_id),
+                event_type: event.event_type,
+                filebox_id: event.filebox_id,
+                code: event.code.clone(),
+                file_type: event.file_type,
+                size: event.size,
+                ip: event.ip.clone(),
+                user_agent: event.user_agent.clone(),
+                created_at: event.created_at,
+            });
+        }
+        Ok(())
+    }
+
+    async fn list_filebox_events(
+        &self,
+        filter: &FileboxEventFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<FileboxEvent>, i64), Error> {
+        let events = self.events.lock().unwrap();
+        let matched = events
+            .iter()
+            .filter(|event| event_matches(event, filter))
+            .collect();
+        Ok(page(matched, limit, offset))
+    }
+
+    async fn list_ip_rules(&self) -> Result<Vec<(IpRuleKind, IpNet)>, Error> {
+        Ok(self.ip_rules.lock().unwrap().clone())
+    }
+
+    async fn save_ip_rule(&self, kind: IpRuleKind, net: &IpNet) -> Result<(), Error> {
+        let mut ip_rules = self.ip_rules.lock().unwrap();
+        if !ip_rules.contains(&(kind, *net)) {
+            ip_rules.push((kind, *net));
+        }
+        Ok(())
+    }
+
+    async fn delete_ip_rule(&self, kind: IpRuleKind, net: &IpNet) -> Result<(), Error> {
+        let mut ip_rules = self.ip_rules.lock().unwrap();
+        ip_rules.retain(|rule| *rule != (kind, *net));
+        Ok(())
+    }
+
+    async fn aggregate_daily_stats(&self, since: NaiveDate) -> Result<u64, Error> {
+        let stats_vec = aggregate_events(&self.events.lock().unwrap(), since, Utc::now());
+        let mut daily_stats = self.daily_stats.lock().unwrap();
+        for stats in &stats_vec {
+            daily_stats.insert(stats.day, stats.clone());
+        }
+        Ok(stats_vec.len() as u64)
+    }
+
+    async fn list_daily_stats(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<DailyStats>, Error> {
+        let daily_stats = self.daily_stats.lock().unwrap();
+        Ok(daily_stats
+            .range(from..=to)
+            .map(|(_, stats)| stats.clone())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{data::repository::tests, models::filebox_event::FileboxEventType};
+
+    fn add_filebox(code: &str, name: &str, expired_in: Duration) -> AddFilebox {
+        let now = Utc::now();
+        AddFilebox {
+            code: code.to_string(),
+            name: name.to_string(),
+            size: 10,
+            file_type: FileType::File,
+            file_path: format!("uuid/{name}"),
+            created_at: now,
+            expired_at: now + expired_in,
+            ..Default::default()
+        }
+    }
+
+    #[actix_rt::test]
+    async fn memory_filebox_lifecycle() {
+        let repo = MemoryFileboxRepository::new();
+
+        let first = repo
+            .add_filebox(add_filebox("11111", "a.txt", Duration::days(1)))
+            .await
+            .unwrap();
+        let second = repo
+            .add_filebox(add_filebox("22222", "B.txt", Duration::days(-1)))
+            .await
+            .unwrap();
+        assert_eq!((first.id, second.id), (1, 2));
+        assert_eq!(repo.get_filebox("11111").await.unwrap(), first);
+        assert!(matches!(
+            repo.get_filebox("33333").await,
+            Err(Error::NotFound)
+        ));
+        assert_eq!(repo.get_filebox_usage().await.unwrap(), (1, 20));
+
+        let filter = FileboxFilter {
+            keyword: Some("b.TXT".to_string()),
+            ..Default::default()
+        };
+        let (fileboxes, total) = repo.list_fileboxes(&filter, 10, 0).await.unwrap();
+        assert_eq!((fileboxes, total), (vec![second.clone()], 1));
+        let (fileboxes, total) = repo
+            .list_fileboxes(&FileboxFilter::default(), 1, 0)
+            .await
+            .unwrap();
+        assert_eq!((fileboxes, total), (vec![second.clone()], 2));
+
+        let taken = repo.take_filebox("11111").await.unwrap();
+        assert!(matches!(&taken, TakeOutcome::Taken(filebox) if filebox.has_taken()));
+        assert!(matches!(
+            repo.take_filebox("11111").await.unwrap(),
+            TakeOutcome::AlreadyTaken(_)
+        ));
+        assert!(matches!(
+            repo.take_filebox("22222").await.unwrap(),
+            TakeOutcome::Expired(filebox) if !filebox.has_taken()
+        ));
+        assert_eq!(
+            repo.take_filebox("33333").await.unwrap(),
+            TakeOutcome::NotFound
+        );
+
+        // both the taken and the expired box go
+        let deleted = repo.delete_expired_fileboxes().await.unwrap();
+        assert_eq!(deleted.len(), 2);
+        assert!(matches!(
+            repo.get_filebox_by_id(first.id).await,
+            Err(Error::NotFound)
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn memory_filebox_events() {
+        let repo = MemoryFileboxRepository::new();
+        let filebox = repo
+            .add_filebox(add_filebox("11111", "a.txt", Duration::days(1)))
+            .await
+            .unwrap();
+        repo.add_filebox_events(&[
+            AddFileboxEvent::new(FileboxEventType::Created, &filebox),
+            AddFileboxEvent::new(FileboxEventType::Taken, &filebox),
+            AddFileboxEvent::lookup_failed("99999".to_string()),
+        ])
+        .await
+        .unwrap();
+
+        let filter = FileboxEventFilter {
+            code: Some("11111".to_string()),
+            ..Default::default()
+        };
+        let (events, total) = repo.list_filebox_events(&filter, 10, 0).await.unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(events[0].event_type, FileboxEventType::Taken);
+    }
+
+    #[actix_rt::test]
+    async fn filebox_lifecycle() {
+        tests::filebox_lifecycle(Arc::new(MemoryFileboxRepository::new())).await;
+    }
+
+    #[actix_rt::test]
+    async fn filebox_admin_queries() {
+        tests::filebox_admin_queries(Arc::new(MemoryFileboxRepository::new())).await;
+    }
+
+    #[actix_rt::test]
+    async fn take_filebox_outcomes() {
+        tests::take_filebox_outcomes(Arc::new(MemoryFileboxRepository::new())).await;
+    }
+
+    #[actix_rt::test]
+    async fn filebox_event_queries() {
+        tests::filebox_event_queries(Arc::new(MemoryFileboxRepository::new())).await;
+    }
+
+    #[actix_rt::test]
+    async fn ip_rules_should_persist() {
+        tests::ip_rules_should_persist(Arc::new(MemoryFileboxRepository::new())).await;
+    }
+
+    #[actix_rt::test]
+    async fn aggregate_daily_stats_should_work() {
+        tests::aggregate_daily_stats_should_work(Arc::new(MemoryFileboxRepository::new())).await;
+    }
+}
@@ -0,0 +1,235 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::{api::IpInfo, data::limiter::IpLimitStore, errors};
+
+/// Ip limit counters kept in the process, only shared between the workers
+/// of a single server.
+#[derive(Debug, Default)]
+pub struct MemoryIpLimitStore {
+    entries: Mutex<HashMap<String, (IpInfo, Instant)>>,
+    counters: Mutex<HashMap<String, (i64, Instant)>>,
+}
+
+/// What `MemoryIpLimitStore::save_snapshot` writes, expiries are unix
+/// seconds since an `Instant` means nothing to the next process.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Snapshot {
+    entries: HashMap<String, (IpInfo, u64)>,
+    counters: HashMap<String, (i64, u64)>,
+}
+
+impl MemoryIpLimitStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drop the expired entries and counters, run by the scheduler so the
+    /// requests never scan the whole map.
+    pub fn evict_expired(&self) {
+        let now = Instant::now();
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|_, (_, expired_at)| *expired_at > now);
+        self.counters
+            .lock()
+            .unwrap()
+            .retain(|_, (_, expired_at)| *expired_at > now);
+    }
+
+    /// Write the live entries to `path` as json, through a temporary file so
+    /// a crash never leaves half a snapshot behind.
+    pub fn save_snapshot(&self, path: &Path) -> io::Result<()> {
+        let now = Instant::now();
+        let unix_now = unix_secs();
+        let expires_at = |expired_at: &Instant| unix_now + (*expired_at - now).as_secs();
+
+        let snapshot = Snapshot {
+            entries: self
+                .entries
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(_, (_, expired_at))| *expired_at > now)
+                .map(|(ip, (ip_info, expired_at))| {
+                    (ip.clone(), (ip_info.clone(), expires_at(expired_at)))
+                })
+                .collect(),
+            counters: self
+                .counters
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(_, (_, expired_at))| *expired_at > now)
+                .map(|(key, (count, expired_at))| (key.clone(), (*count, expires_at(expired_at))))
+                .collect(),
+        };
+
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec(&snapshot)?)?;
+        fs::rename(tmp_path, path)
+    }
+
+    /// Load the entries of a snapshot written by `save_snapshot`, the expired
+    /// ones are dropped. A missing file is an empty snapshot.
+    pub fn load_snapshot(&self, path: &Path) -> io::Result<()> {
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        let snapshot: Snapshot = serde_json::from_slice(&content)?;
+
+        let now = Instant::now();
+        let unix_now = unix_secs();
+        let expired_at = |expires_at: u64| {
+            (expires_at > unix_now).then(|| now + Duration::from_secs(expires_at - unix_now))
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        for (ip, (ip_info, expires_at)) in snapshot.entries {
+            if let Some(expired_at) = expired_at(expires_at) {
+                entries.insert(ip, (ip_info, expired_at));
+            }
+        }
+        let mut counters = self.counters.lock().unwrap();
+        for (key, (count, expires_at)) in snapshot.counters {
+            if let Some(expired_at) = expired_at(expires_at) {
+                counters.insert(key, (count, expired_at));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[async_trait(?Send)]
+impl IpLimitStore for MemoryIpLimitStore {
+    async fn get_ip_info(&self, ip: &str) -> Result<Option<IpInfo>, errors::Error> {
+        let entries = self.entries.lock().unwrap();
+        match entries.get(ip) {
+            Some((ip_info, expired_at)) if *expired_at > Instant::now() => {
+                Ok(Some(ip_info.clone()))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn set_ip_info(&self, ip: &str, ip_info: &IpInfo, ttl: i64) -> Result<(), errors::Error> {
+        let now = Instant::now();
+        let expired_at = now + Duration::from_secs(ttl.max(0) as u64);
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(ip.to_string(), (ip_info.clone(), expired_at));
+
+        Ok(())
+    }
+
+    async fn get_counter(&self, key: &str) -> Result<i64, errors::Error> {
+        let counters = self.counters.lock().unwrap();
+        match counters.get(key) {
+            Some((count, expired_at)) if *expired_at > Instant::now() => Ok(*count),
+            _ => Ok(0),
+        }
+    }
+
+    async fn incr_counter(&self, key: &str, ttl: i64) -> Result<i64, errors::Error> {
+        let now = Instant::now();
+
+        let mut counters = self.counters.lock().unwrap();
+        let (count, expired_at) = counters
+            .entry(key.to_string())
+            .or_insert_with(|| (0, now + Duration::from_secs(ttl.max(0) as u64)));
+        // 过期的计数等到 evict_expired 才删除, 这里当作新的计数
+        if *expired_at <= now {
+            *count = 0;
+            *expired_at = now + Duration::from_secs(ttl.max(0) as u64);
+        }
+        *count += 1;
+
+        Ok(*count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn memory_ip_limit_store_should_work() {
+        let store = MemoryIpLimitStore::new();
+        let ip = "127.0.0.1";
+        assert!(store.get_ip_info(ip).await.unwrap().is_none());
+
+        let mut ip_info = IpInfo::new();
+        ip_info.upload_limit_of_per_day = 2;
+        store.set_ip_info(ip, &ip_info, 60).await.unwrap();
+        let got = store.get_ip_info(ip).await.unwrap().unwrap();
+        assert_eq!(got.upload_limit_of_per_day, 2);
+
+        store.set_ip_info(ip, &ip_info, 0).await.unwrap();
+        assert!(store.get_ip_info(ip).await.unwrap().is_none());
+    }
+
+    #[actix_rt::test]
+    async fn memory_ip_limit_store_should_survive_a_snapshot() {
+        let path = std::env::temp_dir().join(format!("ip-limits-{}.json", uuid::Uuid::new_v4()));
+        let store = MemoryIpLimitStore::new();
+        let mut ip_info = IpInfo::new();
+        ip_info.visit_error_limit_of_per_day = 3;
+        store.set_ip_info("10.0.0.1", &ip_info, 60).await.unwrap();
+        store.set_ip_info("10.0.0.2", &ip_info, 0).await.unwrap();
+        store.incr_counter("lookups", 60).await.unwrap();
+        store.save_snapshot(&path).unwrap();
+
+        let restored = MemoryIpLimitStore::new();
+        restored.load_snapshot(&path).unwrap();
+        let got = restored.get_ip_info("10.0.0.1").await.unwrap().unwrap();
+        assert_eq!(got.visit_error_limit_of_per_day, 3);
+        assert!(restored.get_ip_info("10.0.0.2").await.unwrap().is_none());
+        assert_eq!(restored.get_counter("lookups").await.unwrap(), 1);
+
+        std::fs::remove_file(&path).unwrap();
+        // 文件不存在时视为空快照
+        assert!(MemoryIpLimitStore::new().load_snapshot(&path).is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn memory_ip_limit_store_should_evict_on_demand() {
+        let store = MemoryIpLimitStore::new();
+        store
+            .set_ip_info("10.0.0.1", &IpInfo::new(), 0)
+            .await
+            .unwrap();
+        store
+            .set_ip_info("10.0.0.2", &IpInfo::new(), 60)
+            .await
+            .unwrap();
+        store.incr_counter("lookups", 0).await.unwrap();
+        assert_eq!(store.entries.lock().unwrap().len(), 2);
+
+        // 过期的计数重新从 1 开始
+        assert_eq!(store.incr_counter("lookups", 60).await.unwrap(), 1);
+        assert_eq!(store.incr_counter("lookups", 60).await.unwrap(), 2);
+
+        store.evict_expired();
+        assert_eq!(store.entries.lock().unwrap().len(), 1);
+        assert_eq!(store.counters.lock().unwrap().len(), 1);
+    }
+}
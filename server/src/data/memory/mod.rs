@@ -0,0 +1,5 @@
+mod filebox;
+pub mod ip_allow;
+
+pub use filebox::*;
+pub use ip_allow::*;
@@ -1,2 +1,6 @@
+pub mod limiter;
+pub mod memory;
 pub mod postgres;
 pub mod redis;
+pub mod repository;
+pub mod sqlite;
@@ -0,0 +1,90 @@
+use chrono::{NaiveDate, Utc};
+use sqlx::PgPool;
+
+use crate::{errors::Error, models::daily_stats::DailyStats};
+
+/// Rebuild the stats of every day since `since` from the audit log. A box can
+/// be taken or expire up to its longest duration after it was created, so the
+/// recent days are rebuilt on every run. Days are UTC days.
+#[tracing::instrument(skip(pool))]
+pub async fn aggregate_daily_stats_db(pool: &PgPool, since: NaiveDate) -> Result<u64, Error> {
+    let now = Utc::now();
+    let result = sqlx::query(
+        r#"
+		WITH created AS (
+			SELECT filebox_id, created_at, (created_at AT TIME ZONE 'UTC')::DATE AS day, file_type, size
+			FROM filebox_event
+			WHERE event_type = 'created' AND created_at >= $1
+		), taken AS (
+			SELECT filebox_id, MIN(created_at) AS taken_at
+			FROM filebox_event
+			WHERE event_type = 'taken'
+			GROUP BY filebox_id
+		), expired AS (
+			SELECT DISTINCT filebox_id
+			FROM filebox_event
+			WHERE event_type = 'expired'
+		)
+		INSERT INTO daily_stats (
+			day,
+			created_count,
+			text_count,
+			file_count,
+			total_bytes,
+			taken_count,
+			median_pickup_secs,
+			expired_count,
+			updated_at
+		)
+		SELECT
+			c.day,
+			COUNT(*),
+			COUNT(*) FILTER (WHERE c.file_type = 'text'),
+			COUNT(*) FILTER (WHERE c.file_type = 'file'),
+			COALESCE(SUM(c.size), 0)::BIGINT,
+			COUNT(t.taken_at),
+			PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY EXTRACT(EPOCH FROM t.taken_at - c.created_at)),
+			COUNT(e.filebox_id),
+			$2
+		FROM created c
+		LEFT JOIN taken t ON t.filebox_id = c.filebox_id
+		LEFT JOIN expired e ON e.filebox_id = c.filebox_id
+		GROUP BY c.day
+		ON CONFLICT (day) DO UPDATE SET
+			created_count = EXCLUDED.created_count,
+			text_count = EXCLUDED.text_count,
+			file_count = EXCLUDED.file_count,
+			total_bytes = EXCLUDED.total_bytes,
+			taken_count = EXCLUDED.taken_count,
+			median_pickup_secs = EXCLUDED.median_pickup_secs,
+			expired_count = EXCLUDED.expired_count,
+			updated_at = EXCLUDED.updated_at
+	"#,
+    )
+    .bind(since.and_hms_opt(0, 0, 0).unwrap().and_utc())
+    .bind(now)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// The stats of the days in `from..=to`, oldest first.
+#[tracing::instrument(skip(pool))]
+pub async fn list_daily_stats_db(
+    pool: &PgPool,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<DailyStats>, Error> {
+    let stats_vec: Vec<DailyStats> = sqlx::query_as(
+        r#"
+		SELECT * FROM daily_stats WHERE day >= $1 AND day <= $2 ORDER BY day
+	"#,
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(stats_vec)
+}
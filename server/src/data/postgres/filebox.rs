@@ -1,11 +1,12 @@
-use chrono::Local;
-use sqlx::PgPool;
+use chrono::Utc;
+use sqlx::{FromRow, PgPool, Postgres, QueryBuilder, Row};
 
 use crate::{
     errors::Error,
-    models::filebox::{AddFilebox, Filebox},
+    models::filebox::{AddFilebox, Filebox, FileboxFilter, TakeOutcome},
 };
 
+#[tracing::instrument(skip_all)]
 pub async fn get_filebox_db(pool: &PgPool, code: String) -> Result<Filebox, Error> {
     let filebox: Filebox = sqlx::query_as(
         r#"
@@ -19,8 +20,9 @@ pub async fn get_filebox_db(pool: &PgPool, code: String) -> Result<Filebox, Erro
     Ok(filebox)
 }
 
+#[tracing::instrument(skip(pool))]
 pub async fn delete_expired_filebox_db(pool: &PgPool) -> Result<Vec<Filebox>, Error> {
-    let now = Local::now().naive_local();
+    let now = Utc::now();
 
     let filebox_vec: Vec<Filebox> = sqlx::query_as(
         r#"
@@ -34,6 +36,7 @@ pub async fn delete_expired_filebox_db(pool: &PgPool) -> Result<Vec<Filebox>, Er
     Ok(filebox_vec)
 }
 
+#[tracing::instrument(skip_all)]
 pub async fn add_new_filebox_db(pool: &PgPool, filebox: AddFilebox) -> Result<Filebox, Error> {
     let file_type: String = filebox.file_type.into();
     let new_filebox: Filebox = sqlx::query_as(
@@ -66,75 +69,165 @@ pub async fn add_new_filebox_db(pool: &PgPool, filebox: AddFilebox) -> Result<Fi
     Ok(new_filebox)
 }
 
-pub async fn update_filebox_db(pool: &PgPool, code: String) -> Result<Filebox, Error> {
-    let now = Local::now().naive_local();
-    let filebox: Filebox = sqlx::query_as(
+/// The row is locked before deciding, a concurrent take of the same code
+/// waits and then sees it taken.
+#[tracing::instrument(skip_all)]
+pub async fn take_filebox_db(pool: &PgPool, code: String) -> Result<TakeOutcome, Error> {
+    let now = Utc::now();
+    let row = sqlx::query(
         r#"
-		UPDATE filebox SET used_at = $1 WHERE code = $2 AND used_at IS NULL RETURNING *
+		WITH target AS (
+			SELECT id, used_at IS NULL AND expired_at > $1 AS took
+			FROM filebox WHERE code = $2 FOR UPDATE
+		)
+		UPDATE filebox SET used_at = CASE WHEN target.took THEN $1 ELSE filebox.used_at END
+		FROM target WHERE filebox.id = target.id
+		RETURNING filebox.*, target.took
 	"#,
     )
     .bind(now)
     .bind(code)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(row) = row else {
+        return Ok(TakeOutcome::NotFound);
+    };
+    Ok(TakeOutcome::new(
+        Some(Filebox::from_row(&row)?),
+        row.try_get("took")?,
+    ))
+}
+
+/// Returns the number of fileboxes not taken nor expired and the bytes of
+/// every file still on disk.
+#[tracing::instrument(skip(pool))]
+pub async fn get_filebox_usage_db(pool: &PgPool) -> Result<(i64, i64), Error> {
+    let now = Utc::now();
+    let usage: (i64, i64) = sqlx::query_as(
+        r#"
+		SELECT
+			COUNT(*) FILTER (WHERE used_at IS NULL AND expired_at > $1),
+			COALESCE(SUM(size) FILTER (WHERE file_type = 'file'), 0)::BIGINT
+		FROM filebox
+	"#,
+    )
+    .bind(now)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(usage)
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_filebox_by_id_db(pool: &PgPool, id: i64) -> Result<Filebox, Error> {
+    let filebox: Filebox = sqlx::query_as(
+        r#"
+			SELECT * FROM filebox WHERE id = $1
+		"#,
+    )
+    .bind(id)
     .fetch_one(pool)
     .await?;
 
     Ok(filebox)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::models::filebox::FileType;
-
-    use std::ops::Add;
-
-    use crate::test_utils::get_tdb;
-    use chrono::{Duration, Local};
-
-    #[actix_rt::test]
-    async fn filebox_lifecycle() {
-        let tdb = get_tdb();
-        let pool = tdb.get_pool().await;
-
-        // 1.insert a new filebox
-        let code = "12345".to_string();
-        let now = Local::now().naive_local();
-        let filebox = AddFilebox {
-            code: code.clone(),
-            name: "test".to_string(),
-            file_type: FileType::Text,
-            text: "21123".to_string(),
-            created_at: now,
-            expired_at: now.add(Duration::days(7)),
-            ..Default::default()
-        };
-        let new_filebox = add_new_filebox_db(&pool, filebox.clone()).await.unwrap();
-        assert_eq!(filebox.code, new_filebox.code);
-        assert_eq!(filebox.name, new_filebox.name);
-        assert_eq!(filebox.file_type, new_filebox.file_type);
-        assert_eq!(filebox.text, new_filebox.text);
-        assert_eq!(
-            filebox.created_at.timestamp(),
-            new_filebox.created_at.timestamp()
-        );
-        assert_eq!(
-            filebox.expired_at.timestamp(),
-            new_filebox.expired_at.timestamp()
-        );
-
-        // 2.get the filebox
-        let get_filebox = get_filebox_db(&pool, code.clone()).await.unwrap();
-        assert_eq!(new_filebox, get_filebox);
-
-        // 3.update the filebox set used
-        let get_update_filebox = update_filebox_db(&pool, code.clone()).await.unwrap();
-
-        assert!(get_update_filebox.has_taken());
-
-        // 4.delete the used filebox
-        let filebox_vec = delete_expired_filebox_db(&pool).await.unwrap();
-        assert_eq!(filebox_vec.len(), 1);
-        let resp = get_filebox_db(&pool, code.clone()).await;
-        assert!(resp.is_err());
+/// Returns one page of the matched fileboxes, newest first, and the total
+/// number of matched rows.
+#[tracing::instrument(skip(pool))]
+pub async fn list_filebox_db(
+    pool: &PgPool,
+    filter: &FileboxFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<Filebox>, i64), Error> {
+    let mut count_query = QueryBuilder::new("SELECT COUNT(*) FROM filebox");
+    push_filebox_filter(&mut count_query, filter);
+    let (total,): (i64,) = count_query.build_query_as().fetch_one(pool).await?;
+
+    let mut query = QueryBuilder::new("SELECT * FROM filebox");
+    push_filebox_filter(&mut query, filter);
+    query
+        .push(" ORDER BY id DESC LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+    let filebox_vec: Vec<Filebox> = query.build_query_as().fetch_all(pool).await?;
+
+    Ok((filebox_vec, total))
+}
+
+fn push_filebox_filter(query: &mut QueryBuilder<'_, Postgres>, filter: &FileboxFilter) {
+    query.push(" WHERE TRUE");
+    if let Some(keyword) = &filter.keyword {
+        let escaped = keyword
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        let pattern = format!("%{escaped}%");
+        query
+            .push(" AND (code = ")
+            .push_bind(keyword.clone())
+            .push(" OR name ILIKE ")
+            .push_bind(pattern)
+            .push(")");
+    }
+    if let Some(file_type) = filter.file_type {
+        let file_type: String = file_type.into();
+        query
+            .push(" AND file_type = ")
+            .push_bind(file_type)
+            .push("::file_type");
+    }
+    if let Some(created_from) = filter.created_from {
+        query.push(" AND created_at >= ").push_bind(created_from);
+    }
+    if let Some(created_to) = filter.created_to {
+        query.push(" AND created_at < ").push_bind(created_to);
     }
+    match filter.taken {
+        Some(true) => {
+            query.push(" AND used_at IS NOT NULL");
+        }
+        Some(false) => {
+            query.push(" AND used_at IS NULL");
+        }
+        None => {}
+    }
+    if let Some(min_size) = filter.min_size {
+        query.push(" AND size >= ").push_bind(min_size);
+    }
+    if let Some(max_size) = filter.max_size {
+        query.push(" AND size <= ").push_bind(max_size);
+    }
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn delete_filebox_by_id_db(pool: &PgPool, id: i64) -> Result<Filebox, Error> {
+    let filebox: Filebox = sqlx::query_as(
+        r#"
+		DELETE FROM filebox WHERE id = $1 RETURNING *
+	"#,
+    )
+    .bind(id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(filebox)
+}
+
+/// Deletes every existing filebox of `ids`, unknown ids are ignored.
+#[tracing::instrument(skip(pool))]
+pub async fn delete_filebox_by_ids_db(pool: &PgPool, ids: &[i64]) -> Result<Vec<Filebox>, Error> {
+    let filebox_vec: Vec<Filebox> = sqlx::query_as(
+        r#"
+		DELETE FROM filebox WHERE id = ANY($1) RETURNING *
+	"#,
+    )
+    .bind(ids)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(filebox_vec)
 }
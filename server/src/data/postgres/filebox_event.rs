@@ -0,0 +1,75 @@
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+use crate::{
+    errors::Error,
+    models::filebox_event::{AddFileboxEvent, FileboxEvent, FileboxEventFilter},
+};
+
+#[tracing::instrument(skip_all, fields(event_type = ?events.first().map(|e| e.event_type)))]
+pub async fn add_filebox_events_db(pool: &PgPool, events: &[AddFileboxEvent]) -> Result<(), Error> {
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    let mut query = QueryBuilder::<Postgres>::new(
+        "INSERT INTO filebox_event \
+         (event_type, filebox_id, code, file_type, size, ip, user_agent, created_at) ",
+    );
+    query.push_values(events, |mut row, event| {
+        row.push_bind(event.event_type)
+            .push_bind(event.filebox_id)
+            .push_bind(event.code.clone())
+            .push_bind(event.file_type)
+            .push_bind(event.size)
+            .push_bind(event.ip.clone())
+            .push_bind(event.user_agent.clone())
+            .push_bind(event.created_at);
+    });
+    query.build().execute(pool).await?;
+
+    Ok(())
+}
+
+/// Returns one page of the matched events, newest first, and the total
+/// number of matched rows.
+#[tracing::instrument(skip(pool))]
+pub async fn list_filebox_event_db(
+    pool: &PgPool,
+    filter: &FileboxEventFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<FileboxEvent>, i64), Error> {
+    let mut count_query = QueryBuilder::new("SELECT COUNT(*) FROM filebox_event");
+    push_filebox_event_filter(&mut count_query, filter);
+    let (total,): (i64,) = count_query.build_query_as().fetch_one(pool).await?;
+
+    let mut query = QueryBuilder::new("SELECT * FROM filebox_event");
+    push_filebox_event_filter(&mut query, filter);
+    query
+        .push(" ORDER BY id DESC LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+    let event_vec: Vec<FileboxEvent> = query.build_query_as().fetch_all(pool).await?;
+
+    Ok((event_vec, total))
+}
+
+fn push_filebox_event_filter(query: &mut QueryBuilder<'_, Postgres>, filter: &FileboxEventFilter) {
+    query.push(" WHERE TRUE");
+    if let Some(code) = &filter.code {
+        query.push(" AND code = ").push_bind(code.clone());
+    }
+    if let Some(ip) = &filter.ip {
+        query.push(" AND ip = ").push_bind(ip.clone());
+    }
+    if let Some(event_type) = filter.event_type {
+        query.push(" AND event_type = ").push_bind(event_type);
+    }
+    if let Some(created_from) = filter.created_from {
+        query.push(" AND created_at >= ").push_bind(created_from);
+    }
+    if let Some(created_to) = filter.created_to {
+        query.push(" AND created_at < ").push_bind(created_to);
+    }
+}
@@ -0,0 +1,38 @@
+use ipnet::IpNet;
+use sqlx::PgPool;
+
+use crate::{api::IpRuleKind, data::redis::parse_persisted_ip_rule, errors::Error};
+
+/// The ip rules added through /admin, oldest first.
+#[tracing::instrument(skip(pool))]
+pub async fn list_ip_rule_db(pool: &PgPool) -> Result<Vec<(IpRuleKind, IpNet)>, Error> {
+    let rows: Vec<(String, String)> =
+        sqlx::query_as("SELECT kind, cidr FROM ip_rule ORDER BY created_at")
+            .fetch_all(pool)
+            .await?;
+
+    Ok(rows
+        .iter()
+        .filter_map(|(kind, cidr)| parse_persisted_ip_rule(kind, cidr))
+        .collect())
+}
+
+#[tracing::instrument(skip(pool), fields(net = %net))]
+pub async fn save_ip_rule_db(pool: &PgPool, kind: IpRuleKind, net: &IpNet) -> Result<(), Error> {
+    sqlx::query("INSERT INTO ip_rule (kind, cidr) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+        .bind(kind.as_str())
+        .bind(net.to_string())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(pool), fields(net = %net))]
+pub async fn delete_ip_rule_db(pool: &PgPool, kind: IpRuleKind, net: &IpNet) -> Result<(), Error> {
+    sqlx::query("DELETE FROM ip_rule WHERE kind = $1 AND cidr = $2")
+        .bind(kind.as_str())
+        .bind(net.to_string())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
@@ -1,10 +1,28 @@
+mod daily_stats;
 mod filebox;
+mod filebox_event;
+mod ip_rule;
+mod repository;
 
+pub use daily_stats::*;
 pub use filebox::*;
+pub use filebox_event::*;
+pub use ip_rule::*;
+pub use repository::*;
 
-use sqlx::{postgres::PgRow, types::chrono::NaiveDateTime, FromRow, Row};
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgRow, FromRow, PgPool, Row};
 
-use crate::models::filebox::{FileType, Filebox};
+use crate::{
+    errors::Error,
+    models::filebox::{FileType, Filebox},
+};
+
+#[tracing::instrument(skip(pool))]
+pub async fn ping_db(pool: &PgPool) -> Result<(), Error> {
+    sqlx::query("SELECT 1").execute(pool).await?;
+    Ok(())
+}
 
 impl FromRow<'_, PgRow> for Filebox {
     fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
@@ -13,9 +31,9 @@ impl FromRow<'_, PgRow> for Filebox {
         let name: String = row.get("name");
         let size: i64 = row.get("size");
         let file_path: String = row.get("file_path");
-        let created_at: NaiveDateTime = row.get("created_at");
-        let expired_at: NaiveDateTime = row.get("expired_at");
-        let used_at: Option<NaiveDateTime> = row.get("used_at");
+        let created_at: DateTime<Utc> = row.get("created_at");
+        let expired_at: DateTime<Utc> = row.get("expired_at");
+        let used_at: Option<DateTime<Utc>> = row.get("used_at");
         let file_type: FileType = row.get("file_type");
         let text: String = row.get("text");
         Ok(Filebox {
@@ -0,0 +1,167 @@
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use ipnet::IpNet;
+use sqlx::PgPool;
+
+use super::*;
+use crate::{
+    api::IpRuleKind,
+    data::repository::FileboxRepository,
+    errors::Error,
+    models::{
+        daily_stats::DailyStats,
+        filebox::{AddFilebox, Filebox, FileboxFilter, TakeOutcome},
+        filebox_event::{AddFileboxEvent, FileboxEvent, FileboxEventFilter},
+    },
+};
+
+/// The fileboxes kept in Postgres, a thin wrapper of the `_db` functions.
+#[derive(Debug, Clone)]
+pub struct PgFileboxRepository {
+    pool: PgPool,
+}
+
+impl PgFileboxRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl FileboxRepository for PgFileboxRepository {
+    fn backend(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn ping(&self) -> Result<(), Error> {
+        ping_db(&self.pool).await
+    }
+
+    async fn get_filebox(&self, code: &str) -> Result<Filebox, Error> {
+        get_filebox_db(&self.pool, code.to_string()).await
+    }
+
+    async fn add_filebox(&self, filebox: AddFilebox) -> Result<Filebox, Error> {
+        add_new_filebox_db(&self.pool, filebox).await
+    }
+
+    async fn take_filebox(&self, code: &str) -> Result<TakeOutcome, Error> {
+        take_filebox_db(&self.pool, code.to_string()).await
+    }
+
+    async fn delete_expired_fileboxes(&self) -> Result<Vec<Filebox>, Error> {
+        delete_expired_filebox_db(&self.pool).await
+    }
+
+    async fn get_filebox_usage(&self) -> Result<(i64, i64), Error> {
+        get_filebox_usage_db(&self.pool).await
+    }
+
+    async fn get_filebox_by_id(&self, id: i64) -> Result<Filebox, Error> {
+        get_filebox_by_id_db(&self.pool, id).await
+    }
+
+    async fn list_fileboxes(
+        &self,
+        filter: &FileboxFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<Filebox>, i64), Error> {
+        list_filebox_db(&self.pool, filter, limit, offset).await
+    }
+
+    async fn delete_filebox_by_id(&self, id: i64) -> Result<Filebox, Error> {
+        delete_filebox_by_id_db(&self.pool, id).await
+    }
+
+    async fn delete_fileboxes_by_ids(&self, ids: &[i64]) -> Result<Vec<Filebox>, Error> {
+        delete_filebox_by_ids_db(&self.pool, ids).await
+    }
+
+    async fn add_filebox_events(&self, events: &[AddFileboxEvent]) -> Result<(), Error> {
+        add_filebox_events_db(&self.pool, events).await
+    }
+
+    async fn list_filebox_events(
+        &self,
+        filter: &FileboxEventFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<FileboxEvent>, i64), Error> {
+        list_filebox_event_db(&self.pool, filter, limit, offset).await
+    }
+
+    async fn list_ip_rules(&self) -> Result<Vec<(IpRuleKind, IpNet)>, Error> {
+        list_ip_rule_db(&self.pool).await
+    }
+
+    async fn save_ip_rule(&self, kind: IpRuleKind, net: &IpNet) -> Result<(), Error> {
+        save_ip_rule_db(&self.pool, kind, net).await
+    }
+
+    async fn delete_ip_rule(&self, kind: IpRuleKind, net: &IpNet) -> Result<(), Error> {
+        delete_ip_rule_db(&self.pool, kind, net).await
+    }
+
+    async fn aggregate_daily_stats(&self, since: NaiveDate) -> Result<u64, Error> {
+        aggregate_daily_stats_db(&self.pool, since).await
+    }
+
+    async fn list_daily_stats(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<DailyStats>, Error> {
+        list_daily_stats_db(&self.pool, from, to).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{data::repository::tests, test_utils::get_tdb};
+
+    #[actix_rt::test]
+    async fn filebox_lifecycle() {
+        let tdb = get_tdb();
+        let repo = PgFileboxRepository::new(tdb.get_pool().await);
+        tests::filebox_lifecycle(Arc::new(repo)).await;
+    }
+
+    #[actix_rt::test]
+    async fn filebox_admin_queries() {
+        let tdb = get_tdb();
+        let repo = PgFileboxRepository::new(tdb.get_pool().await);
+        tests::filebox_admin_queries(Arc::new(repo)).await;
+    }
+
+    #[actix_rt::test]
+    async fn take_filebox_outcomes() {
+        let tdb = get_tdb();
+        let repo = PgFileboxRepository::new(tdb.get_pool().await);
+        tests::take_filebox_outcomes(Arc::new(repo)).await;
+    }
+
+    #[actix_rt::test]
+    async fn filebox_event_queries() {
+        let tdb = get_tdb();
+        let repo = PgFileboxRepository::new(tdb.get_pool().await);
+        tests::filebox_event_queries(Arc::new(repo)).await;
+    }
+
+    #[actix_rt::test]
+    async fn ip_rules_should_persist() {
+        let tdb = get_tdb();
+        let repo = PgFileboxRepository::new(tdb.get_pool().await);
+        tests::ip_rules_should_persist(Arc::new(repo)).await;
+    }
+
+    #[actix_rt::test]
+    async fn aggregate_daily_stats_should_work() {
+        let tdb = get_tdb();
+        let repo = PgFileboxRepository::new(tdb.get_pool().await);
+        tests::aggregate_daily_stats_should_work(Arc::new(repo)).await;
+    }
+}
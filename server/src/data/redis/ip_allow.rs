@@ -1,8 +1,8 @@
 use actix::Addr;
 use actix_redis::{resp_array, Command, RedisActor, RespValue};
-use chrono::{Duration, Local};
+use async_trait::async_trait;
 
-use crate::{api::IpInfo, errors};
+use crate::{api::IpInfo, data::limiter::IpLimitStore, errors};
 
 pub struct IpAllower {
     pub visit_error_limit: i32,
@@ -20,123 +20,102 @@ impl IpAllower {
     }
 }
 
-pub async fn is_allow_ip_for_visit(
-    addr: &Addr<RedisActor>,
-    ip: &str,
-    visit_error_limit: i32,
-) -> Result<bool, errors::Error> {
-    let (ip_info, get_it) = get(addr, ip).await?;
-    if !get_it {
-        return Ok(true);
-    }
-
-    if ip_info.visit_error_limit_of_per_day >= visit_error_limit {
-        return Ok(false);
+// INCR 与 EXPIRE 分两次发送时, 中途失败会留下永不过期的计数
+const INCR_WITH_EXPIRE_SCRIPT: &str = r#"
+local count = redis.call('INCR', KEYS[1])
+if count == 1 then
+    redis.call('EXPIRE', KEYS[1], ARGV[1])
+end
+return count
+"#;
+
+#[async_trait(?Send)]
+impl IpLimitStore for Addr<RedisActor> {
+    #[tracing::instrument(skip(self))]
+    async fn get_ip_info(&self, ip: &str) -> Result<Option<IpInfo>, errors::Error> {
+        let cmd = Command(resp_array!["GET", ip]);
+        let val = self
+            .send(cmd)
+            .await
+            .map_err(Into::into)
+            .map_err(errors::Error::RedisError)?
+            .map_err(Into::into)
+            .map_err(errors::Error::RedisError)?;
+        match val {
+            RespValue::BulkString(ip_info_vec) => {
+                let ip_info_json: String = String::from_utf8(ip_info_vec)?;
+                let ip_info: IpInfo = ip_info_json
+                    .try_into()
+                    .map_err(errors::Error::DeserializeJsonError)?;
+
+                Ok(Some(ip_info))
+            }
+            RespValue::Error(msg) => Err(errors::Error::RedisSendCommandError(msg)),
+            _ => Ok(None),
+        }
     }
-    Ok(true)
-}
-
-pub async fn add_ip_visit_error_limit_count(
-    addr: &Addr<RedisActor>,
-    ip: &str,
-    ttl: i64,
-) -> Result<(), errors::Error> {
-    let (mut ip_info, _) = get(addr, ip).await?;
-    ip_info.visit_error_limit_of_per_day += 1;
 
-    // SAFTEY: we ensure the ip_info implementation of Serialize
-    let value = serde_json::to_string(&ip_info).unwrap();
-    let ttl = get_ttl(ttl);
-    let cmd = Command(resp_array!["SETEX", ip, ttl.to_string(), value]);
-    if let RespValue::Error(msg) = addr
-        .send(cmd)
-        .await
-        .map_err(Into::into)
-        .map_err(errors::Error::RedisError)?
-        .map_err(Into::into)
-        .map_err(errors::Error::RedisError)?
-    {
-        return Err(errors::Error::RedisSendCommandError(msg));
-    };
-
-    Ok(())
-}
-
-pub async fn is_allow_ip_for_upload(
-    addr: &Addr<RedisActor>,
-    ip: &str,
-    upload_limit: i32,
-) -> Result<bool, errors::Error> {
-    let (ip_info, get_it) = get(addr, ip).await?;
-    if !get_it {
-        return Ok(true);
+    #[tracing::instrument(skip(self, ip_info))]
+    async fn set_ip_info(&self, ip: &str, ip_info: &IpInfo, ttl: i64) -> Result<(), errors::Error> {
+        // SAFTEY: we ensure the ip_info implementation of Serialize
+        let value = serde_json::to_string(ip_info).unwrap();
+        let cmd = Command(resp_array!["SETEX", ip, ttl.to_string(), value]);
+        if let RespValue::Error(msg) = self
+            .send(cmd)
+            .await
+            .map_err(Into::into)
+            .map_err(errors::Error::RedisError)?
+            .map_err(Into::into)
+            .map_err(errors::Error::RedisError)?
+        {
+            return Err(errors::Error::RedisSendCommandError(msg));
+        };
+
+        Ok(())
     }
 
-    if ip_info.upload_limit_of_per_day >= upload_limit {
-        return Ok(false);
+    #[tracing::instrument(skip(self))]
+    async fn get_counter(&self, key: &str) -> Result<i64, errors::Error> {
+        let cmd = Command(resp_array!["GET", key]);
+        let val = self
+            .send(cmd)
+            .await
+            .map_err(Into::into)
+            .map_err(errors::Error::RedisError)?
+            .map_err(Into::into)
+            .map_err(errors::Error::RedisError)?;
+        match val {
+            RespValue::BulkString(count) => {
+                let count = String::from_utf8(count)?;
+                count
+                    .parse()
+                    .map_err(|_| errors::Error::RedisSendCommandError(count))
+            }
+            RespValue::Error(msg) => Err(errors::Error::RedisSendCommandError(msg)),
+            _ => Ok(0),
+        }
     }
-    Ok(true)
-}
 
-pub async fn add_ip_upload_limit_count(
-    addr: &Addr<RedisActor>,
-    ip: &str,
-    ttl: i64,
-) -> Result<(), errors::Error> {
-    let (mut ip_info, _) = get(addr, ip).await?;
-    ip_info.upload_limit_of_per_day += 1;
-
-    // SAFTEY: we ensure the ip_info implementation of Serialize
-    let value = serde_json::to_string(&ip_info).unwrap();
-    let ttl = get_ttl(ttl);
-    let cmd = Command(resp_array!["SETEX", ip, ttl.to_string(), value]);
-    if let RespValue::Error(msg) = addr
-        .send(cmd)
-        .await
-        .map_err(Into::into)
-        .map_err(errors::Error::RedisError)?
-        .map_err(Into::into)
-        .map_err(errors::Error::RedisError)?
-    {
-        return Err(errors::Error::RedisSendCommandError(msg));
-    };
-
-    Ok(())
-}
-
-async fn get(addr: &Addr<RedisActor>, ip: &str) -> Result<(IpInfo, bool), errors::Error> {
-    let cmd = Command(resp_array!["GET", ip]);
-    let val = addr
-        .send(cmd)
-        .await
-        .map_err(Into::into)
-        .map_err(errors::Error::RedisError)?
-        .map_err(Into::into)
-        .map_err(errors::Error::RedisError)?;
-    match val {
-        RespValue::BulkString(ip_info_vec) => {
-            let ip_info_json: String = String::from_utf8(ip_info_vec)?;
-            let ip_info: IpInfo = ip_info_json
-                .try_into()
-                .map_err(errors::Error::DeserializeJsonError)?;
-
-            Ok((ip_info, true))
+    #[tracing::instrument(skip(self))]
+    async fn incr_counter(&self, key: &str, ttl: i64) -> Result<i64, errors::Error> {
+        let cmd = Command(resp_array![
+            "EVAL",
+            INCR_WITH_EXPIRE_SCRIPT,
+            "1",
+            key,
+            ttl.to_string()
+        ]);
+        match self
+            .send(cmd)
+            .await
+            .map_err(Into::into)
+            .map_err(errors::Error::RedisError)?
+            .map_err(Into::into)
+            .map_err(errors::Error::RedisError)?
+        {
+            RespValue::Integer(count) => Ok(count),
+            RespValue::Error(msg) => Err(errors::Error::RedisSendCommandError(msg)),
+            val => Err(errors::Error::RedisSendCommandError(format!("{val:?}"))),
         }
-        _ => Ok((IpInfo::default(), false)),
     }
 }
-
-fn get_ttl(ttl: i64) -> i64 {
-    let now = Local::now();
-
-    let tomorrow_midnight = (now + Duration::days(ttl))
-        .date_naive()
-        .and_hms_opt(0, 0, 0)
-        .unwrap();
-
-    tomorrow_midnight
-        .signed_duration_since(now.naive_local())
-        .to_std()
-        .unwrap()
-        .as_secs() as i64
-}
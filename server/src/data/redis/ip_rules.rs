@@ -0,0 +1,230 @@
+use std::{net::IpAddr, str::FromStr};
+
+use actix::Addr;
+use actix_redis::{resp_array, Command, RedisActor, RespValue};
+use ipnet::IpNet;
+
+use crate::{api::IpRuleKind, errors};
+
+const IP_ALLOW_LIST_KEY: &str = "filebox:ip_rules:allow";
+const IP_DENY_LIST_KEY: &str = "filebox:ip_rules:deny";
+
+/// Allow and deny CIDR lists consulted by the ip limit middlewares before
+/// touching the per-day counters. Never changed in place, the admin handlers
+/// and the reload swap in a changed copy.
+#[derive(Debug, Default, Clone)]
+pub struct IpRules {
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+    // 配置文件中的规则, 重启或重新加载后总会回来, 不能通过 /admin 删除
+    configured_allow: Vec<IpNet>,
+    configured_deny: Vec<IpNet>,
+}
+
+impl IpRules {
+    /// Rules from the config, see `is_configured`.
+    pub fn new(allow: Vec<IpNet>, deny: Vec<IpNet>) -> Self {
+        Self {
+            configured_allow: allow.clone(),
+            configured_deny: deny.clone(),
+            allow,
+            deny,
+        }
+    }
+
+    /// The rule comes from the config, removing it at runtime would only last
+    /// until the next restart or reload.
+    pub fn is_configured(&self, kind: IpRuleKind, net: &IpNet) -> bool {
+        match kind {
+            IpRuleKind::Allow => self.configured_allow.contains(net),
+            IpRuleKind::Deny => self.configured_deny.contains(net),
+        }
+    }
+
+    /// The ip is exempt from the upload and visit error limits.
+    pub fn is_allowed(&self, ip: &str) -> bool {
+        contains(&self.allow, ip)
+    }
+
+    /// The ip is blocked, the deny list wins over the allow list.
+    pub fn is_denied(&self, ip: &str) -> bool {
+        contains(&self.deny, ip)
+    }
+
+    pub fn list(&self, kind: IpRuleKind) -> Vec<IpNet> {
+        match kind {
+            IpRuleKind::Allow => self.allow.clone(),
+            IpRuleKind::Deny => self.deny.clone(),
+        }
+    }
+
+    /// The rules added at runtime, the ones a reload has to carry over when
+    /// redis can't be read.
+    pub fn list_added(&self, kind: IpRuleKind) -> Vec<IpNet> {
+        self.list(kind)
+            .into_iter()
+            .filter(|net| !self.is_configured(kind, net))
+            .collect()
+    }
+
+    pub fn add(&mut self, kind: IpRuleKind, net: IpNet) {
+        let rules = self.rules_mut(kind);
+        if !rules.contains(&net) {
+            rules.push(net);
+        }
+    }
+
+    pub fn remove(&mut self, kind: IpRuleKind, net: &IpNet) -> bool {
+        let rules = self.rules_mut(kind);
+        let len = rules.len();
+        rules.retain(|rule| rule != net);
+        rules.len() != len
+    }
+
+    fn rules_mut(&mut self, kind: IpRuleKind) -> &mut Vec<IpNet> {
+        match kind {
+            IpRuleKind::Allow => &mut self.allow,
+            IpRuleKind::Deny => &mut self.deny,
+        }
+    }
+}
+
+fn contains(rules: &[IpNet], ip: &str) -> bool {
+    match IpAddr::from_str(ip) {
+        Ok(ip) => rules.iter().any(|net| net.contains(&ip)),
+        Err(_) => false,
+    }
+}
+
+/// Parse a CIDR like `10.0.0.0/8`, a bare address is treated as a single host.
+pub fn parse_ip_net(value: &str) -> Result<IpNet, errors::Error> {
+    let value = value.trim();
+    if let Ok(net) = IpNet::from_str(value) {
+        return Ok(net);
+    }
+
+    IpAddr::from_str(value)
+        .map(IpNet::from)
+        .map_err(|_| errors::Error::ValidateArgsError(format!("invalid cidr: {value}")))
+}
+
+/// Parse a comma separated list of CIDRs, as given by `IP_ALLOW_LIST` and `IP_DENY_LIST`.
+pub fn parse_ip_net_list(value: &str) -> Result<Vec<IpNet>, errors::Error> {
+    value
+        .split(',')
+        .filter(|item| !item.trim().is_empty())
+        .map(parse_ip_net)
+        .collect()
+}
+
+/// A rule persisted by a repository, `None` with a warning when the stored
+/// row no longer parses.
+pub fn parse_persisted_ip_rule(kind: &str, cidr: &str) -> Option<(IpRuleKind, IpNet)> {
+    let parsed = IpRuleKind::ALL.into_iter().find(|k| k.as_str() == kind);
+    match (parsed, parse_ip_net(cidr)) {
+        (Some(parsed), Ok(net)) => Some((parsed, net)),
+        _ => {
+            tracing::warn!("skip persisted ip rule {kind} {cidr}");
+            None
+        }
+    }
+}
+
+/// Merge the rules persisted in redis into the ones loaded from config.
+#[tracing::instrument(skip_all)]
+pub async fn load_ip_rules(
+    addr: &Addr<RedisActor>,
+    ip_rules: &mut IpRules,
+) -> Result<(), errors::Error> {
+    for kind in IpRuleKind::ALL {
+        let cmd = Command(resp_array!["SMEMBERS", key(kind)]);
+        if let RespValue::Array(members) = send(addr, cmd).await? {
+            for member in members {
+                if let RespValue::BulkString(value) = member {
+                    let value = String::from_utf8(value)?;
+                    match parse_ip_net(&value) {
+                        Ok(net) => ip_rules.add(kind, net),
+                        Err(err) => tracing::warn!("skip persisted ip rule {value}: {err}"),
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(addr), fields(net = %net))]
+pub async fn save_ip_rule(
+    addr: &Addr<RedisActor>,
+    kind: IpRuleKind,
+    net: &IpNet,
+) -> Result<(), errors::Error> {
+    let cmd = Command(resp_array!["SADD", key(kind), net.to_string()]);
+    send(addr, cmd).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(addr), fields(net = %net))]
+pub async fn delete_ip_rule(
+    addr: &Addr<RedisActor>,
+    kind: IpRuleKind,
+    net: &IpNet,
+) -> Result<(), errors::Error> {
+    let cmd = Command(resp_array!["SREM", key(kind), net.to_string()]);
+    send(addr, cmd).await?;
+    Ok(())
+}
+
+async fn send(addr: &Addr<RedisActor>, cmd: Command) -> Result<RespValue, errors::Error> {
+    let val = addr
+        .send(cmd)
+        .await
+        .map_err(Into::into)
+        .map_err(errors::Error::RedisError)?
+        .map_err(Into::into)
+        .map_err(errors::Error::RedisError)?;
+    if let RespValue::Error(msg) = val {
+        return Err(errors::Error::RedisSendCommandError(msg));
+    }
+
+    Ok(val)
+}
+
+fn key(kind: IpRuleKind) -> &'static str {
+    match kind {
+        IpRuleKind::Allow => IP_ALLOW_LIST_KEY,
+        IpRuleKind::Deny => IP_DENY_LIST_KEY,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ip_rules_should_work() {
+        let allow = parse_ip_net_list("10.0.0.0/8, 192.168.1.7").unwrap();
+        let deny = parse_ip_net_list("10.1.0.0/16").unwrap();
+        let mut ip_rules = IpRules::new(allow, deny);
+
+        assert!(ip_rules.is_allowed("10.2.3.4"));
+        assert!(ip_rules.is_allowed("192.168.1.7"));
+        assert!(!ip_rules.is_allowed("192.168.1.8"));
+        assert!(ip_rules.is_denied("10.1.2.3"));
+        assert!(!ip_rules.is_denied("not an ip"));
+
+        let net = parse_ip_net("2001:db8::/32").unwrap();
+        ip_rules.add(IpRuleKind::Deny, net);
+        assert!(ip_rules.is_denied("2001:db8::1"));
+        assert_eq!(ip_rules.list_added(IpRuleKind::Deny), vec![net]);
+        assert!(ip_rules.remove(IpRuleKind::Deny, &net));
+        assert!(!ip_rules.is_denied("2001:db8::1"));
+        assert!(!ip_rules.remove(IpRuleKind::Deny, &net));
+        assert!(!ip_rules.is_configured(IpRuleKind::Deny, &net));
+        assert!(ip_rules.is_configured(IpRuleKind::Deny, &parse_ip_net("10.1.0.0/16").unwrap()));
+        assert!(!ip_rules.is_configured(IpRuleKind::Allow, &parse_ip_net("10.1.0.0/16").unwrap()));
+
+        assert!(parse_ip_net("10.0.0.0/33").is_err());
+    }
+}
@@ -1,8 +1,31 @@
 pub mod ip_allow;
+pub mod ip_rules;
 
 pub use ip_allow::*;
+pub use ip_rules::*;
 
-use crate::api::IpInfo;
+use actix_redis::{resp_array, Command, RespValue};
+
+use crate::{
+    api::{IpInfo, RedisActorAddr},
+    errors,
+};
+
+#[tracing::instrument(skip(addr))]
+pub async fn ping_redis(addr: &RedisActorAddr) -> Result<(), errors::Error> {
+    let cmd = Command(resp_array!["PING"]);
+    match addr
+        .send(cmd)
+        .await
+        .map_err(Into::into)
+        .map_err(errors::Error::RedisError)?
+        .map_err(Into::into)
+        .map_err(errors::Error::RedisError)?
+    {
+        RespValue::Error(msg) => Err(errors::Error::RedisSendCommandError(msg)),
+        _ => Ok(()),
+    }
+}
 
 impl TryFrom<String> for IpInfo {
     type Error = serde_json::Error;
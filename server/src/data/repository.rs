@@ -0,0 +1,411 @@
+use std::{fmt, path::Path, str::FromStr, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use ipnet::IpNet;
+use sqlx::{postgres::PgPoolOptions, sqlite::SqliteConnectOptions, SqlitePool};
+
+use crate::{
+    api::IpRuleKind,
+    data::{postgres::PgFileboxRepository, sqlite::SqliteFileboxRepository},
+    errors::Error,
+    models::{
+        daily_stats::DailyStats,
+        filebox::{AddFilebox, Filebox, FileboxFilter, TakeOutcome},
+        filebox_event::{AddFileboxEvent, FileboxEvent, FileboxEventFilter},
+    },
+};
+
+/// Storage of the fileboxes and their audit log, shared by the handlers and
+/// the scheduler through `AppState::repo`.
+///
+/// Unlike `IpLimitStore` the futures are `Send`, the scheduler runs them on
+/// `tokio::spawn`.
+#[async_trait]
+pub trait FileboxRepository: fmt::Debug + Send + Sync {
+    /// Name of the storage, the readiness probe reports it as a component.
+    fn backend(&self) -> &'static str;
+
+    async fn ping(&self) -> Result<(), Error>;
+
+    /// `Error::NotFound` when no filebox has the code.
+    async fn get_filebox(&self, code: &str) -> Result<Filebox, Error>;
+
+    async fn add_filebox(&self, filebox: AddFilebox) -> Result<Filebox, Error>;
+
+    /// Mark the filebox of `code` as taken when it is neither taken nor
+    /// expired, in a single statement, and report why it could not be.
+    async fn take_filebox(&self, code: &str) -> Result<TakeOutcome, Error>;
+
+    /// Delete and return the expired and the taken fileboxes.
+    async fn delete_expired_fileboxes(&self) -> Result<Vec<Filebox>, Error>;
+
+    /// The number of fileboxes not taken nor expired and the bytes of every
+    /// file still on disk.
+    async fn get_filebox_usage(&self) -> Result<(i64, i64), Error>;
+
+    async fn get_filebox_by_id(&self, id: i64) -> Result<Filebox, Error>;
+
+    /// One page of the matched fileboxes, newest first, and the total number
+    /// of matched fileboxes.
+    async fn list_fileboxes(
+        &self,
+        filter: &FileboxFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<Filebox>, i64), Error>;
+
+    async fn delete_filebox_by_id(&self, id: i64) -> Result<Filebox, Error>;
+
+    /// Delete every existing filebox of `ids`, unknown ids are ignored.
+    async fn delete_fileboxes_by_ids(&self, ids: &[i64]) -> Result<Vec<Filebox>, Error>;
+
+    async fn add_filebox_events(&self, events: &[AddFileboxEvent]) -> Result<(), Error>;
+
+    /// One page of the matched events, newest first, and the total number of
+    /// matched events.
+    async fn list_filebox_events(
+        &self,
+        filter: &FileboxEventFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<FileboxEvent>, i64), Error>;
+
+    /// The ip rules added through /admin, kept here when redis is not
+    /// configured.
+    async fn list_ip_rules(&self) -> Result<Vec<(IpRuleKind, IpNet)>, Error>;
+
+    /// Adding a rule twice keeps one.
+    async fn save_ip_rule(&self, kind: IpRuleKind, net: &IpNet) -> Result<(), Error>;
+
+    async fn delete_ip_rule(&self, kind: IpRuleKind, net: &IpNet) -> Result<(), Error>;
+
+    /// Rebuild the daily stats of the days since `since` from the audit log
+    /// and return the number of days written.
+    async fn aggregate_daily_stats(&self, _since: NaiveDate) -> Result<u64, Error> {
+        Err(Error::Unsupported("daily stats".to_string()))
+    }
+
+    async fn list_daily_stats(
+        &self,
+        _from: NaiveDate,
+        _to: NaiveDate,
+    ) -> Result<Vec<DailyStats>, Error> {
+        Err(Error::Unsupported("daily stats".to_string()))
+    }
+}
+
+/// Connect to the storage picked by the scheme of `database_url` and run its
+/// migrations: `postgres://` or `postgresql://` for Postgres, `sqlite:` for a
+/// SQLite file which is created along with its directory when missing.
+pub async fn connect_repository(database_url: &str) -> Result<Arc<dyn FileboxRepository>, Error> {
+    if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+        let pool = PgPoolOptions::new().connect(database_url).await?;
+        sqlx::migrate!("./migrations")
+            .run(&pool)
+            .await
+            .map_err(sqlx::Error::from)?;
+        return Ok(Arc::new(PgFileboxRepository::new(pool)));
+    }
+
+    if let Some(path) = database_url.strip_prefix("sqlite:") {
+        let path = path.trim_start_matches("//");
+        let path = path.split('?').next().unwrap_or_default();
+        if let Some(dir) = Path::new(path).parent() {
+            if !path.starts_with(':') && !dir.as_os_str().is_empty() {
+                std::fs::create_dir_all(dir)?;
+            }
+        }
+        let options = SqliteConnectOptions::from_str(database_url)?.create_if_missing(true);
+        let pool = SqlitePool::connect_with(options).await?;
+        sqlx::migrate!("./migrations_sqlite")
+            .run(&pool)
+            .await
+            .map_err(sqlx::Error::from)?;
+        return Ok(Arc::new(SqliteFileboxRepository::new(pool)));
+    }
+
+    Err(Error::InvalidConfig(format!(
+        "unsupported database_url scheme: {}",
+        database_url.split(':').next().unwrap_or_default()
+    )))
+}
+
+/// The cases every storage has to pass, run by the tests of each backend.
+#[cfg(test)]
+pub(crate) mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::*;
+    use crate::{
+        data::redis::parse_ip_net,
+        models::{filebox::FileType, filebox_event::FileboxEventType},
+    };
+
+    pub async fn filebox_lifecycle(repo: Arc<dyn FileboxRepository>) {
+        // 1.insert a new filebox
+        let code = "12345";
+        let now = Utc::now();
+        let filebox = AddFilebox {
+            code: code.to_string(),
+            name: "test".to_string(),
+            file_type: FileType::Text,
+            text: "21123".to_string(),
+            created_at: now,
+            expired_at: now + Duration::days(7),
+            ..Default::default()
+        };
+        let new_filebox = repo.add_filebox(filebox.clone()).await.unwrap();
+        assert_eq!(filebox.code, new_filebox.code);
+        assert_eq!(filebox.name, new_filebox.name);
+        assert_eq!(filebox.file_type, new_filebox.file_type);
+        assert_eq!(filebox.text, new_filebox.text);
+        assert_eq!(
+            filebox.created_at.timestamp(),
+            new_filebox.created_at.timestamp()
+        );
+        assert_eq!(
+            filebox.expired_at.timestamp(),
+            new_filebox.expired_at.timestamp()
+        );
+
+        // 2.get the filebox
+        let get_filebox = repo.get_filebox(code).await.unwrap();
+        assert_eq!(new_filebox, get_filebox);
+        assert_eq!(repo.get_filebox_usage().await.unwrap(), (1, 0));
+
+        // 3.update the filebox set used
+        let taken = repo
+            .take_filebox(code)
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+        assert!(taken.has_taken());
+        assert_eq!(
+            repo.take_filebox(code).await.unwrap(),
+            TakeOutcome::AlreadyTaken(taken)
+        );
+        assert_eq!(
+            repo.take_filebox("00000").await.unwrap(),
+            TakeOutcome::NotFound
+        );
+
+        // 4.delete the used filebox
+        let filebox_vec = repo.delete_expired_fileboxes().await.unwrap();
+        assert_eq!(filebox_vec.len(), 1);
+        assert!(matches!(repo.get_filebox(code).await, Err(Error::NotFound)));
+    }
+
+    pub async fn filebox_admin_queries(repo: Arc<dyn FileboxRepository>) {
+        let now = Utc::now();
+        let mut ids = vec![];
+        for (code, name, file_type, size) in [
+            ("aaaaa", "report", FileType::File, 100),
+            ("bbbbb", "notes", FileType::Text, 0),
+            ("ccccc", "Big_Report", FileType::File, 5000),
+        ] {
+            let filebox = AddFilebox {
+                code: code.to_string(),
+                name: name.to_string(),
+                size,
+                file_type,
+                created_at: now,
+                expired_at: now + Duration::days(1),
+                ..Default::default()
+            };
+            ids.push(repo.add_filebox(filebox).await.unwrap().id);
+        }
+        repo.take_filebox("bbbbb").await.unwrap();
+
+        let (all, total) = repo
+            .list_fileboxes(&FileboxFilter::default(), 2, 0)
+            .await
+            .unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].code, "ccccc");
+
+        let filter = FileboxFilter {
+            keyword: Some("report".to_string()),
+            file_type: Some(FileType::File),
+            min_size: Some(1000),
+            taken: Some(false),
+            ..Default::default()
+        };
+        let (found, total) = repo.list_fileboxes(&filter, 10, 0).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(found[0].code, "ccccc");
+
+        // `_` is matched literally, case insensitive
+        let filter = FileboxFilter {
+            keyword: Some("g_r".to_string()),
+            ..Default::default()
+        };
+        let (found, _) = repo.list_fileboxes(&filter, 10, 0).await.unwrap();
+        assert_eq!(found.len(), 1);
+
+        let filter = FileboxFilter {
+            keyword: Some("bbbbb".to_string()),
+            taken: Some(true),
+            ..Default::default()
+        };
+        let (found, _) = repo.list_fileboxes(&filter, 10, 0).await.unwrap();
+        assert_eq!(found.len(), 1);
+
+        let deleted = repo.delete_filebox_by_id(ids[0]).await.unwrap();
+        assert_eq!(deleted.code, "aaaaa");
+        assert!(repo.get_filebox_by_id(ids[0]).await.is_err());
+
+        let mut unknown = ids.clone();
+        unknown.push(999);
+        let revoked = repo.delete_fileboxes_by_ids(&unknown).await.unwrap();
+        assert_eq!(revoked.len(), 2);
+        let (_, total) = repo
+            .list_fileboxes(&FileboxFilter::default(), 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(total, 0);
+    }
+
+    pub async fn take_filebox_outcomes(repo: Arc<dyn FileboxRepository>) {
+        let now = Utc::now();
+        let filebox = AddFilebox {
+            code: "expd1".to_string(),
+            created_at: now - Duration::days(2),
+            expired_at: now - Duration::days(1),
+            ..Default::default()
+        };
+        let expired = repo.add_filebox(filebox).await.unwrap();
+        assert_eq!(
+            repo.take_filebox("expd1").await.unwrap(),
+            TakeOutcome::Expired(expired)
+        );
+
+        // 并发取同一个口令只有一个能取走
+        let filebox = AddFilebox {
+            code: "race1".to_string(),
+            created_at: now,
+            expired_at: now + Duration::days(1),
+            ..Default::default()
+        };
+        repo.add_filebox(filebox).await.unwrap();
+        let takes =
+            futures_util::future::join_all((0..8).map(|_| repo.take_filebox("race1"))).await;
+        let taken = takes
+            .into_iter()
+            .filter(|outcome| matches!(outcome, Ok(TakeOutcome::Taken(_))))
+            .count();
+        assert_eq!(taken, 1);
+    }
+
+    pub async fn filebox_event_queries(repo: Arc<dyn FileboxRepository>) {
+        let now = Utc::now();
+        let event = |event_type, code: &str, ip: &str| AddFileboxEvent {
+            event_type,
+            filebox_id: None,
+            code: code.to_string(),
+            file_type: None,
+            size: 0,
+            ip: ip.to_string(),
+            user_agent: "curl/7.88".to_string(),
+            created_at: now,
+        };
+        repo.add_filebox_events(&[
+            event(FileboxEventType::Created, "aaaaa", "10.0.0.1"),
+            event(FileboxEventType::Taken, "aaaaa", "10.0.0.2"),
+            event(FileboxEventType::LookupFailed, "zzzzz", "10.0.0.2"),
+        ])
+        .await
+        .unwrap();
+
+        let filter = FileboxEventFilter {
+            code: Some("aaaaa".to_string()),
+            ..Default::default()
+        };
+        let (events, total) = repo.list_filebox_events(&filter, 10, 0).await.unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(events[0].event_type, FileboxEventType::Taken);
+
+        let filter = FileboxEventFilter {
+            ip: Some("10.0.0.2".to_string()),
+            event_type: Some(FileboxEventType::LookupFailed),
+            ..Default::default()
+        };
+        let (events, _) = repo.list_filebox_events(&filter, 10, 0).await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].code, "zzzzz");
+    }
+
+    pub async fn ip_rules_should_persist(repo: Arc<dyn FileboxRepository>) {
+        let net = parse_ip_net("192.0.2.0/24").unwrap();
+        repo.save_ip_rule(IpRuleKind::Deny, &net).await.unwrap();
+        // adding twice keeps one
+        repo.save_ip_rule(IpRuleKind::Deny, &net).await.unwrap();
+        repo.save_ip_rule(IpRuleKind::Allow, &net).await.unwrap();
+        assert_eq!(
+            repo.list_ip_rules().await.unwrap(),
+            vec![(IpRuleKind::Deny, net), (IpRuleKind::Allow, net)]
+        );
+
+        repo.delete_ip_rule(IpRuleKind::Deny, &net).await.unwrap();
+        assert_eq!(
+            repo.list_ip_rules().await.unwrap(),
+            vec![(IpRuleKind::Allow, net)]
+        );
+    }
+
+    pub async fn aggregate_daily_stats_should_work(repo: Arc<dyn FileboxRepository>) {
+        let created_at = Utc::now() - Duration::hours(1);
+        let event = |event_type, filebox_id, file_type, size, created_at| AddFileboxEvent {
+            event_type,
+            filebox_id: Some(filebox_id),
+            code: format!("{filebox_id:05}"),
+            file_type: Some(file_type),
+            size,
+            ip: String::new(),
+            user_agent: String::new(),
+            created_at,
+        };
+        use FileboxEventType::*;
+        repo.add_filebox_events(&[
+            event(Created, 1, FileType::File, 100, created_at),
+            event(Created, 2, FileType::Text, 10, created_at),
+            event(Created, 3, FileType::File, 50, created_at),
+            event(
+                Taken,
+                1,
+                FileType::File,
+                100,
+                created_at + Duration::seconds(60),
+            ),
+            event(
+                Taken,
+                2,
+                FileType::Text,
+                10,
+                created_at + Duration::seconds(120),
+            ),
+            event(Expired, 3, FileType::File, 50, created_at),
+        ])
+        .await
+        .unwrap();
+
+        let day = created_at.date_naive();
+        repo.aggregate_daily_stats(day).await.unwrap();
+        // rebuilding is idempotent
+        repo.aggregate_daily_stats(day).await.unwrap();
+
+        let stats_vec = repo.list_daily_stats(day, day).await.unwrap();
+        assert_eq!(stats_vec.len(), 1);
+        let stats = &stats_vec[0];
+        assert_eq!(stats.created_count, 3);
+        assert_eq!(stats.text_count, 1);
+        assert_eq!(stats.file_count, 2);
+        assert_eq!(stats.total_bytes, 160);
+        assert_eq!(stats.taken_count, 2);
+        assert_eq!(stats.median_pickup_secs, Some(90.0));
+        assert_eq!(stats.expired_count, 1);
+        assert!((stats.pickup_ratio() - 2.0 / 3.0).abs() < f64::EPSILON);
+    }
+}
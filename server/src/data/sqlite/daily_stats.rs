@@ -0,0 +1,93 @@
+use chrono::{NaiveDate, Utc};
+use sqlx::SqlitePool;
+
+use crate::{
+    errors::Error,
+    models::{
+        daily_stats::{aggregate_events, DailyStats},
+        filebox_event::FileboxEvent,
+    },
+};
+
+/// Rebuild the stats of every day since `since` from the audit log, as the
+/// Postgres aggregation does. SQLite has no `PERCENTILE_CONT`, so the events
+/// of the boxes created since then are aggregated in the process.
+#[tracing::instrument(skip(pool))]
+pub async fn aggregate_daily_stats_db(pool: &SqlitePool, since: NaiveDate) -> Result<u64, Error> {
+    let events: Vec<FileboxEvent> = sqlx::query_as(
+        r#"
+		SELECT * FROM filebox_event
+		WHERE event_type IN ('created', 'taken', 'expired') AND filebox_id IN (
+			SELECT filebox_id FROM filebox_event
+			WHERE event_type = 'created' AND created_at >= ?
+		)
+	"#,
+    )
+    .bind(since.and_hms_opt(0, 0, 0).unwrap().and_utc())
+    .fetch_all(pool)
+    .await?;
+
+    let stats_vec = aggregate_events(&events, since, Utc::now());
+    let mut tx = pool.begin().await?;
+    for stats in &stats_vec {
+        sqlx::query(
+            r#"
+			INSERT INTO daily_stats (
+				day,
+				created_count,
+				text_count,
+				file_count,
+				total_bytes,
+				taken_count,
+				median_pickup_secs,
+				expired_count,
+				updated_at
+			)
+			VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+			ON CONFLICT (day) DO UPDATE SET
+				created_count = excluded.created_count,
+				text_count = excluded.text_count,
+				file_count = excluded.file_count,
+				total_bytes = excluded.total_bytes,
+				taken_count = excluded.taken_count,
+				median_pickup_secs = excluded.median_pickup_secs,
+				expired_count = excluded.expired_count,
+				updated_at = excluded.updated_at
+		"#,
+        )
+        .bind(stats.day)
+        .bind(stats.created_count)
+        .bind(stats.text_count)
+        .bind(stats.file_count)
+        .bind(stats.total_bytes)
+        .bind(stats.taken_count)
+        .bind(stats.median_pickup_secs)
+        .bind(stats.expired_count)
+        .bind(stats.updated_at)
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(stats_vec.len() as u64)
+}
+
+/// The stats of the days in `from..=to`, oldest first.
+#[tracing::instrument(skip(pool))]
+pub async fn list_daily_stats_db(
+    pool: &SqlitePool,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<DailyStats>, Error> {
+    let stats_vec: Vec<DailyStats> = sqlx::query_as(
+        r#"
+		SELECT * FROM daily_stats WHERE day >= ? AND day <= ? ORDER BY day
+	"#,
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(stats_vec)
+}
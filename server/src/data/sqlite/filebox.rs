@@ -0,0 +1,234 @@
+use chrono::Utc;
+use sqlx::{FromRow, QueryBuilder, Row, Sqlite, SqlitePool};
+
+use crate::{
+    errors::Error,
+    models::filebox::{AddFilebox, Filebox, FileboxFilter, TakeOutcome},
+};
+
+#[tracing::instrument(skip_all)]
+pub async fn get_filebox_db(pool: &SqlitePool, code: String) -> Result<Filebox, Error> {
+    let filebox: Filebox = sqlx::query_as(
+        r#"
+			SELECT * FROM filebox WHERE code = $1
+		"#,
+    )
+    .bind(code)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(filebox)
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn delete_expired_filebox_db(pool: &SqlitePool) -> Result<Vec<Filebox>, Error> {
+    let now = Utc::now();
+
+    let filebox_vec: Vec<Filebox> = sqlx::query_as(
+        r#"
+		DELETE FROM filebox WHERE expired_at <= $1 OR used_at IS NOT NULL RETURNING *
+	"#,
+    )
+    .bind(now)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(filebox_vec)
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn add_new_filebox_db(pool: &SqlitePool, filebox: AddFilebox) -> Result<Filebox, Error> {
+    let new_filebox: Filebox = sqlx::query_as(
+        r#"
+			INSERT INTO filebox (
+				code,
+				name,
+				size,
+				file_type,
+				text,
+				file_path,
+				created_at,
+				expired_at
+			) VALUES (
+				$1, $2, $3, $4, $5, $6, $7, $8
+			) RETURNING *
+		"#,
+    )
+    .bind(filebox.code)
+    .bind(filebox.name)
+    .bind(filebox.size)
+    .bind(filebox.file_type)
+    .bind(filebox.text)
+    .bind(filebox.file_path)
+    .bind(filebox.created_at)
+    .bind(filebox.expired_at)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(new_filebox)
+}
+
+/// SQLite runs one write at a time, so the row can be updated whether it is
+/// takeable or not. RETURNING only sees the new row, the take is ours when
+/// `used_at` is the time it was just set to.
+#[tracing::instrument(skip_all)]
+pub async fn take_filebox_db(pool: &SqlitePool, code: String) -> Result<TakeOutcome, Error> {
+    let now = Utc::now();
+    let row = sqlx::query(
+        r#"
+		UPDATE filebox
+		SET used_at = CASE WHEN used_at IS NULL AND expired_at > $1 THEN $1 ELSE used_at END
+		WHERE code = $2
+		RETURNING *, used_at IS $1 AS took
+	"#,
+    )
+    .bind(now)
+    .bind(code)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(row) = row else {
+        return Ok(TakeOutcome::NotFound);
+    };
+    Ok(TakeOutcome::new(
+        Some(Filebox::from_row(&row)?),
+        row.try_get("took")?,
+    ))
+}
+
+/// Returns the number of fileboxes not taken nor expired and the bytes of
+/// every file still on disk.
+#[tracing::instrument(skip(pool))]
+pub async fn get_filebox_usage_db(pool: &SqlitePool) -> Result<(i64, i64), Error> {
+    let now = Utc::now();
+    let usage: (i64, i64) = sqlx::query_as(
+        r#"
+		SELECT
+			COUNT(*) FILTER (WHERE used_at IS NULL AND expired_at > $1),
+			COALESCE(SUM(size) FILTER (WHERE file_type = 'file'), 0)
+		FROM filebox
+	"#,
+    )
+    .bind(now)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(usage)
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_filebox_by_id_db(pool: &SqlitePool, id: i64) -> Result<Filebox, Error> {
+    let filebox: Filebox = sqlx::query_as(
+        r#"
+			SELECT * FROM filebox WHERE id = $1
+		"#,
+    )
+    .bind(id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(filebox)
+}
+
+/// Returns one page of the matched fileboxes, newest first, and the total
+/// number of matched rows.
+#[tracing::instrument(skip(pool))]
+pub async fn list_filebox_db(
+    pool: &SqlitePool,
+    filter: &FileboxFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<Filebox>, i64), Error> {
+    let mut count_query = QueryBuilder::new("SELECT COUNT(*) FROM filebox");
+    push_filebox_filter(&mut count_query, filter);
+    let (total,): (i64,) = count_query.build_query_as().fetch_one(pool).await?;
+
+    let mut query = QueryBuilder::new("SELECT * FROM filebox");
+    push_filebox_filter(&mut query, filter);
+    query
+        .push(" ORDER BY id DESC LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+    let filebox_vec: Vec<Filebox> = query.build_query_as().fetch_all(pool).await?;
+
+    Ok((filebox_vec, total))
+}
+
+fn push_filebox_filter(query: &mut QueryBuilder<'_, Sqlite>, filter: &FileboxFilter) {
+    query.push(" WHERE TRUE");
+    if let Some(keyword) = &filter.keyword {
+        let escaped = keyword
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        let pattern = format!("%{escaped}%");
+        // LIKE 对 ASCII 不区分大小写, 等同于 postgres 的 ILIKE
+        query
+            .push(" AND (code = ")
+            .push_bind(keyword.clone())
+            .push(" OR name LIKE ")
+            .push_bind(pattern)
+            .push(" ESCAPE '\\')");
+    }
+    if let Some(file_type) = filter.file_type {
+        query.push(" AND file_type = ").push_bind(file_type);
+    }
+    if let Some(created_from) = filter.created_from {
+        query.push(" AND created_at >= ").push_bind(created_from);
+    }
+    if let Some(created_to) = filter.created_to {
+        query.push(" AND created_at < ").push_bind(created_to);
+    }
+    match filter.taken {
+        Some(true) => {
+            query.push(" AND used_at IS NOT NULL");
+        }
+        Some(false) => {
+            query.push(" AND used_at IS NULL");
+        }
+        None => {}
+    }
+    if let Some(min_size) = filter.min_size {
+        query.push(" AND size >= ").push_bind(min_size);
+    }
+    if let Some(max_size) = filter.max_size {
+        query.push(" AND size <= ").push_bind(max_size);
+    }
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn delete_filebox_by_id_db(pool: &SqlitePool, id: i64) -> Result<Filebox, Error> {
+    let filebox: Filebox = sqlx::query_as(
+        r#"
+		DELETE FROM filebox WHERE id = $1 RETURNING *
+	"#,
+    )
+    .bind(id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(filebox)
+}
+
+/// Deletes every existing filebox of `ids`, unknown ids are ignored.
+#[tracing::instrument(skip(pool))]
+pub async fn delete_filebox_by_ids_db(
+    pool: &SqlitePool,
+    ids: &[i64],
+) -> Result<Vec<Filebox>, Error> {
+    if ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    // 没有数组类型, 展开为 IN (...)
+    let mut query = QueryBuilder::<Sqlite>::new("DELETE FROM filebox WHERE id IN (");
+    let mut separated = query.separated(", ");
+    for id in ids {
+        separated.push_bind(*id);
+    }
+    query.push(") RETURNING *");
+    let filebox_vec: Vec<Filebox> = query.build_query_as().fetch_all(pool).await?;
+
+    Ok(filebox_vec)
+}
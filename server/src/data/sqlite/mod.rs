@@ -0,0 +1,52 @@
+mod daily_stats;
+mod filebox;
+mod filebox_event;
+mod ip_rule;
+mod repository;
+
+pub use daily_stats::*;
+pub use filebox::*;
+pub use filebox_event::*;
+pub use ip_rule::*;
+pub use repository::*;
+
+use chrono::{DateTime, Utc};
+use sqlx::{sqlite::SqliteRow, FromRow, Row, SqlitePool};
+
+use crate::{
+    errors::Error,
+    models::filebox::{FileType, Filebox},
+};
+
+#[tracing::instrument(skip(pool))]
+pub async fn ping_db(pool: &SqlitePool) -> Result<(), Error> {
+    sqlx::query("SELECT 1").execute(pool).await?;
+    Ok(())
+}
+
+impl FromRow<'_, SqliteRow> for Filebox {
+    fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
+        let id: i64 = row.get("id");
+        let code: String = row.get("code");
+        let name: String = row.get("name");
+        let size: i64 = row.get("size");
+        let file_path: String = row.get("file_path");
+        let created_at: DateTime<Utc> = row.get("created_at");
+        let expired_at: DateTime<Utc> = row.get("expired_at");
+        let used_at: Option<DateTime<Utc>> = row.get("used_at");
+        let file_type: FileType = row.get("file_type");
+        let text: String = row.get("text");
+        Ok(Filebox {
+            id,
+            code,
+            name,
+            size,
+            file_path,
+            created_at,
+            expired_at,
+            used_at,
+            file_type,
+            text,
+        })
+    }
+}
@@ -1,10 +1,15 @@
 use std::{io, string};
 
-use actix_web::{http::StatusCode, HttpResponse, ResponseError};
-use chrono::Local;
+use actix_easy_multipart::MultipartFormConfig;
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use chrono::{DateTime, Local, Utc};
 use validator::ValidationErrors;
 
-use crate::api::{ErrorResponse, DATE_FORMAT, IP_UPLOAD_LIMIT_HEADER, IP_VISIT_ERROR_LIMIT_HEADER};
+use crate::api::{
+    rfc3339, ErrorResponse, DATE_FORMAT, IP_UPLOAD_LIMIT_HEADER, IP_VISIT_ERROR_LIMIT_HEADER,
+    MAX_UPLOAD_SIZE_CEILING,
+};
+use crate::i18n::{self, Lang};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -32,12 +37,36 @@ pub enum Error {
     #[error("No file box found by the given condition")]
     NotFound,
 
+    #[error("File box has been taken at {0}")]
+    AlreadyTaken(DateTime<Utc>),
+
+    #[error("File box expired at {0}")]
+    Expired(DateTime<Utc>),
+
     #[error("Ip visit error limit")]
     IpVisitErrorLimit(i32),
 
     #[error("Ip upload limit")]
     IpUploadLimit(i32),
 
+    #[error("Ip denied")]
+    IpDenied,
+
+    #[error("Unauthorized")]
+    Unauthorized,
+
+    #[error("Ip limiter unavailable")]
+    LimiterUnavailable,
+
+    #[error("Invalid config: {0}")]
+    InvalidConfig(String),
+
+    #[error("Ip rule {0} is set by {1} in the config")]
+    ConfiguredIpRule(String, &'static str),
+
+    #[error("Unsupported: {0}")]
+    Unsupported(String),
+
     #[error("Unknown error")]
     Unknown,
 
@@ -58,33 +87,53 @@ pub enum Error {
 }
 
 impl Error {
-    pub fn to_response(&self) -> HttpResponse {
-        self.error_response()
+    /// Keeps the error in the response, so `localize_error_mw` can translate
+    /// its message.
+    pub fn to_response(self) -> HttpResponse {
+        HttpResponse::from_error(self)
     }
-    fn error_message(&self) -> String {
-        match self {
-            Error::IpVisitErrorLimit(limit) => {
-                format!("今日文件口令错误已达 {limit} 次, 请明天再访问")
+
+    /// Only a lookup with a wrong code counts toward the ip visit error limit,
+    /// validation and infrastructure failures are not the client guessing and
+    /// neither is a right code whose box is taken or expired.
+    pub fn is_visit_error(&self) -> bool {
+        matches!(self, Error::NotFound | Error::InvalidCode(_))
+    }
+
+    /// The `message` of the `ErrorResponse` in `lang`. The 500 errors share
+    /// one message so nothing of the internals leaks.
+    pub fn message(&self, lang: Lang) -> String {
+        let key = if self.status_code() == StatusCode::INTERNAL_SERVER_ERROR {
+            "INTERNAL_SERVER_ERROR".to_string()
+        } else {
+            self.name()
+        };
+        let args = match self {
+            Error::IpVisitErrorLimit(limit) | Error::IpUploadLimit(limit) => {
+                vec![("limit", limit.to_string())]
             }
-            Error::IpUploadLimit(limit) => {
-                format!("今日文件上传已达 {limit} 次, 请明天再上传")
+            Error::InvalidConfig(detail) | Error::InvalidCode(detail) => {
+                vec![("detail", detail.to_string())]
             }
-            Error::InvalidCode(msg) => msg.to_string(),
-            Error::ValidateArgsError(_) | Error::InputValidateError(_) => {
-                "input validate error".to_string()
+            Error::InvalidFileType(detail) => vec![("detail", detail.to_string())],
+            Error::Unsupported(what) => vec![("what", what.to_string())],
+            Error::ConfiguredIpRule(cidr, key) => {
+                vec![("cidr", cidr.to_string()), ("key", key.to_string())]
             }
+            Error::AlreadyTaken(used_at) => vec![("used_at", rfc3339(used_at))],
+            Error::Expired(expired_at) => vec![("expired_at", rfc3339(expired_at))],
+            _ => vec![],
+        };
+        i18n::message(lang, &key, &args)
+    }
 
-            Error::InvalidFileType(err) => format!("invalid file type: {err}"),
-            Error::NotFound => "not found".to_string(),
-            Error::ActixWebError(_)
-            | Error::IOError(_)
-            | Error::MultipartError(_)
-            | Error::DeserializeJsonError(_)
-            | Error::RedisError(_)
-            | Error::RedisSendCommandError(_)
-            | Error::DbError(_)
-            | Error::ParseGetRedisValue(_)
-            | Error::Unknown => "internal server error".to_string(),
+    /// The `ErrorResponse` body in `lang`, the status and the headers do not
+    /// depend on the language.
+    pub fn to_error_response(&self, lang: Lang) -> ErrorResponse {
+        ErrorResponse {
+            message: self.message(lang),
+            code: self.status_code().as_u16(),
+            error: self.name(),
         }
     }
 
@@ -95,8 +144,16 @@ impl Error {
             Error::InvalidFileType(_) => "INVALID_FILE_TYPE".to_string(),
             Error::InputValidateError(_) => "INPUT_VALIDATE_ERROR".to_string(),
             Error::NotFound => "NOT_FOUND".to_string(),
+            Error::AlreadyTaken(_) => "ALREADY_TAKEN".to_string(),
+            Error::Expired(_) => "EXPIRED".to_string(),
             Error::IpVisitErrorLimit(_) => "IP_VISIT_ERROR_LIMIT".to_string(),
             Error::IpUploadLimit(_) => "IP_UPLOAD_LIMIT".to_string(),
+            Error::IpDenied => "IP_DENIED".to_string(),
+            Error::Unauthorized => "UNAUTHORIZED".to_string(),
+            Error::LimiterUnavailable => "LIMITER_UNAVAILABLE".to_string(),
+            Error::InvalidConfig(_) => "INVALID_CONFIG".to_string(),
+            Error::ConfiguredIpRule(..) => "CONFIGURED_IP_RULE".to_string(),
+            Error::Unsupported(_) => "UNSUPPORTED".to_string(),
             Error::IOError(_) => "IO_ERROR".to_string(),
             Error::DbError(_) => "DB_ERROR".to_string(),
             Error::Unknown => "UNKNOWN".to_string(),
@@ -110,6 +167,29 @@ impl Error {
     }
 }
 
+/// Answer a request rejected by the path, query, json or multipart extractor
+/// with the same `ErrorResponse` as the handlers instead of a plain text body.
+pub fn configure_extractors(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(
+        web::PathConfig::default()
+            .error_handler(|err, _| Error::ValidateArgsError(err.to_string()).into()),
+    )
+    .app_data(
+        web::QueryConfig::default()
+            .error_handler(|err, _| Error::ValidateArgsError(err.to_string()).into()),
+    )
+    .app_data(
+        web::JsonConfig::default()
+            .error_handler(|err, _| Error::ValidateArgsError(err.to_string()).into()),
+    )
+    // 实际大小限制由可热加载的 max_upload_size 在 UploadForm 中检查
+    .app_data(
+        MultipartFormConfig::default()
+            .total_limit(MAX_UPLOAD_SIZE_CEILING)
+            .error_handler(|err, _| Error::MultipartError(err).into()),
+    );
+}
+
 impl From<sqlx::Error> for Error {
     fn from(e: sqlx::Error) -> Self {
         match e {
@@ -124,20 +204,35 @@ impl ResponseError for Error {
     fn status_code(&self) -> actix_web::http::StatusCode {
         match self {
             Error::ValidateArgsError(_)
+            | Error::InvalidConfig(_)
             | Error::InvalidCode(_)
             | Error::InvalidFileType(_)
             | Error::InputValidateError(_) => StatusCode::BAD_REQUEST,
 
             Error::NotFound => StatusCode::NOT_FOUND,
 
-            Error::IpVisitErrorLimit(_) | Error::IpUploadLimit(_) => StatusCode::FORBIDDEN,
+            // 表单本身有误时为 4xx, 写临时文件失败时为 5xx
+            Error::MultipartError(err) => err.status_code(),
+
+            Error::AlreadyTaken(_) | Error::ConfiguredIpRule(..) => StatusCode::CONFLICT,
+
+            Error::Expired(_) => StatusCode::GONE,
+
+            Error::Unauthorized => StatusCode::UNAUTHORIZED,
+
+            Error::LimiterUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+
+            Error::Unsupported(_) => StatusCode::NOT_IMPLEMENTED,
+
+            Error::IpVisitErrorLimit(_) | Error::IpUploadLimit(_) | Error::IpDenied => {
+                StatusCode::FORBIDDEN
+            }
 
             Error::ActixWebError(_)
             | Error::IOError(_)
             | Error::DbError(_)
             | Error::Unknown
             | Error::DeserializeJsonError(_)
-            | Error::MultipartError(_)
             | Error::ParseGetRedisValue(_)
             | Error::RedisSendCommandError(_)
             | Error::RedisError(_) => StatusCode::INTERNAL_SERVER_ERROR,
@@ -157,10 +252,107 @@ impl ResponseError for Error {
             }
             _ => &mut builder,
         };
-        builder.json(ErrorResponse {
-            message: self.error_message(),
-            code: self.status_code().as_u16(),
-            error: self.name(),
-        })
+        builder.json(self.to_error_response(Lang::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_visit_error_should_work() {
+        assert!(Error::NotFound.is_visit_error());
+        assert!(Error::InvalidCode("bad code".to_string()).is_visit_error());
+        assert!(!Error::ValidateArgsError("bad args".to_string()).is_visit_error());
+        assert!(!Error::DbError(sqlx::Error::PoolTimedOut).is_visit_error());
+        assert!(!Error::LimiterUnavailable.is_visit_error());
+        assert!(!Error::AlreadyTaken(Utc::now()).is_visit_error());
+        assert!(!Error::Expired(Utc::now()).is_visit_error());
+    }
+
+    #[actix_rt::test]
+    async fn error_names_and_statuses_should_match() {
+        let now = Utc::now();
+        let cases = [
+            (Error::DbError(sqlx::Error::PoolTimedOut), "DB_ERROR", 500),
+            (Error::InvalidCode("bad".to_string()), "INVALID_CODE", 400),
+            (
+                Error::InvalidFileType("exe".to_string()),
+                "INVALID_FILE_TYPE",
+                400,
+            ),
+            (
+                Error::InputValidateError(ValidationErrors::new()),
+                "INPUT_VALIDATE_ERROR",
+                400,
+            ),
+            (
+                Error::MultipartError(actix_easy_multipart::Error::MissingField(
+                    "name".to_string(),
+                )),
+                "MULTIPART_ERROR",
+                400,
+            ),
+            (Error::IOError(io::Error::other("disk")), "IO_ERROR", 500),
+            (
+                Error::ValidateArgsError("bad".to_string()),
+                "VALIDATE_ARGS_ERROR",
+                400,
+            ),
+            (Error::NotFound, "NOT_FOUND", 404),
+            (Error::AlreadyTaken(now), "ALREADY_TAKEN", 409),
+            (Error::Expired(now), "EXPIRED", 410),
+            (Error::IpVisitErrorLimit(3), "IP_VISIT_ERROR_LIMIT", 403),
+            (Error::IpUploadLimit(3), "IP_UPLOAD_LIMIT", 403),
+            (Error::IpDenied, "IP_DENIED", 403),
+            (Error::Unauthorized, "UNAUTHORIZED", 401),
+            (Error::LimiterUnavailable, "LIMITER_UNAVAILABLE", 503),
+            (
+                Error::InvalidConfig("bad".to_string()),
+                "INVALID_CONFIG",
+                400,
+            ),
+            (
+                Error::ConfiguredIpRule("10.0.0.0/8".to_string(), "ip_allow_list"),
+                "CONFIGURED_IP_RULE",
+                409,
+            ),
+            (Error::Unsupported("stats".to_string()), "UNSUPPORTED", 501),
+            (Error::Unknown, "UNKNOWN", 500),
+            (
+                Error::RedisError(anyhow::anyhow!("down")),
+                "REDIS_ERROR",
+                500,
+            ),
+            (
+                Error::ActixWebError(actix_web::error::ErrorBadRequest("bad")),
+                "ACTIX_WEB_ERROR",
+                500,
+            ),
+            (
+                Error::RedisSendCommandError("down".to_string()),
+                "REDIS_SEND_COMMAND_ERROR",
+                500,
+            ),
+            (
+                Error::DeserializeJsonError(serde_json::from_str::<i32>("x").unwrap_err()),
+                "DESERIALIZE_JSON_ERROR",
+                500,
+            ),
+            (
+                Error::ParseGetRedisValue(String::from_utf8(vec![0xff]).unwrap_err()),
+                "PARSE_GET_REDIS_VALUE",
+                500,
+            ),
+        ];
+
+        for (err, name, status) in cases {
+            let resp = err.to_response();
+            assert_eq!(resp.status().as_u16(), status, "{name}");
+            let body = actix_web::body::to_bytes(resp.into_body()).await.unwrap();
+            let body: ErrorResponse = serde_json::from_slice(&body).unwrap();
+            assert_eq!((body.error.as_str(), body.code), (name, status));
+        }
     }
 }
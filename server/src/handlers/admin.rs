@@ -0,0 +1,250 @@
+use std::fs;
+
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+
+use crate::api::{
+    AdminFileboxResponse, ClientInfo, DailyStatsQuery, DailyStatsResponse, IpRuleKind,
+    IpRuleRequest, IpRulesResponse, ListDailyStatsResponse, ListFileboxEventQuery,
+    ListFileboxEventResponse, ListFileboxQuery, ListFileboxResponse, RevokeFileboxRequest,
+    RevokeFileboxResponse, StatsFormat,
+};
+use crate::config::CliArgs;
+use crate::data::redis::{delete_ip_rule, parse_ip_net, save_ip_rule, IpRules};
+use crate::errors::Error;
+use crate::handlers::record_events;
+use crate::models::filebox::Filebox;
+use crate::models::filebox_event::{AddFileboxEvent, FileboxEventType};
+use crate::state::{reload_state, AppState, CacheState};
+
+pub async fn list_ip_rules(cache_state: web::Data<CacheState>) -> HttpResponse {
+    HttpResponse::Ok().json(ip_rules_response(&cache_state.ip_rules.load()))
+}
+
+#[tracing::instrument(skip(app_state, cache_state))]
+pub async fn add_ip_rule(
+    app_state: web::Data<AppState>,
+    cache_state: web::Data<CacheState>,
+    body: web::Json<IpRuleRequest>,
+) -> Result<HttpResponse, Error> {
+    let net = parse_ip_net(&body.cidr)?;
+
+    let _guard = cache_state.ip_rules_lock.lock().await;
+    match &cache_state.redis_actor {
+        Some(redis_actor) => save_ip_rule(redis_actor, body.kind, &net).await?,
+        None => app_state.repo.save_ip_rule(body.kind, &net).await?,
+    }
+    cache_state.ip_rules.rcu(|ip_rules| {
+        let mut ip_rules = IpRules::clone(ip_rules);
+        ip_rules.add(body.kind, net);
+        ip_rules
+    });
+
+    Ok(HttpResponse::Ok().json(ip_rules_response(&cache_state.ip_rules.load())))
+}
+
+#[tracing::instrument(skip(app_state, cache_state))]
+pub async fn delete_ip_rule_by_cidr(
+    app_state: web::Data<AppState>,
+    cache_state: web::Data<CacheState>,
+    body: web::Json<IpRuleRequest>,
+) -> Result<HttpResponse, Error> {
+    let net = parse_ip_net(&body.cidr)?;
+
+    let _guard = cache_state.ip_rules_lock.lock().await;
+    if cache_state.ip_rules.load().is_configured(body.kind, &net) {
+        let key = match body.kind {
+            IpRuleKind::Allow => "ip_allow_list",
+            IpRuleKind::Deny => "ip_deny_list",
+        };
+        return Err(Error::ConfiguredIpRule(net.to_string(), key));
+    }
+    match &cache_state.redis_actor {
+        Some(redis_actor) => delete_ip_rule(redis_actor, body.kind, &net).await?,
+        None => app_state.repo.delete_ip_rule(body.kind, &net).await?,
+    }
+    let mut removed = false;
+    cache_state.ip_rules.rcu(|ip_rules| {
+        let mut ip_rules = IpRules::clone(ip_rules);
+        removed = ip_rules.remove(body.kind, &net);
+        ip_rules
+    });
+    if !removed {
+        return Err(Error::NotFound);
+    }
+
+    Ok(HttpResponse::Ok().json(ip_rules_response(&cache_state.ip_rules.load())))
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn reload_config(
+    cli: web::Data<CliArgs>,
+    app_state: web::Data<AppState>,
+    cache_state: web::Data<CacheState>,
+) -> Result<HttpResponse, Error> {
+    reload_state(&cli, &app_state, &cache_state).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[tracing::instrument(skip(app_state))]
+pub async fn list_fileboxes(
+    app_state: web::Data<AppState>,
+    query: web::Query<ListFileboxQuery>,
+) -> Result<HttpResponse, Error> {
+    let filter = query.to_filter()?;
+    let (filebox_vec, total) = app_state
+        .repo
+        .list_fileboxes(&filter, query.page_size, query.offset())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ListFileboxResponse {
+        items: filebox_vec.into_iter().map(Into::into).collect(),
+        total,
+        page: query.page,
+        page_size: query.page_size,
+    }))
+}
+
+#[tracing::instrument(skip(app_state))]
+pub async fn get_filebox_detail(
+    app_state: web::Data<AppState>,
+    id: web::Path<i64>,
+) -> Result<HttpResponse, Error> {
+    let filebox = app_state.repo.get_filebox_by_id(id.into_inner()).await?;
+    let resp: AdminFileboxResponse = filebox.into();
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[tracing::instrument(skip(app_state, client))]
+pub async fn delete_filebox(
+    app_state: web::Data<AppState>,
+    client: ClientInfo,
+    id: web::Path<i64>,
+) -> Result<HttpResponse, Error> {
+    let filebox = app_state.repo.delete_filebox_by_id(id.into_inner()).await?;
+    remove_filebox_blob(&app_state.upload_path, &filebox);
+    let revoked = [AddFileboxEvent::new(FileboxEventType::Revoked, &filebox)];
+    record_events(&app_state, revoked, &client).await;
+    app_state
+        .metrics
+        .deleted_fileboxes
+        .with_label_values(&["admin"])
+        .inc();
+
+    let resp: AdminFileboxResponse = filebox.into();
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[tracing::instrument(skip(app_state, client))]
+pub async fn revoke_fileboxes(
+    app_state: web::Data<AppState>,
+    client: ClientInfo,
+    body: web::Json<RevokeFileboxRequest>,
+) -> Result<HttpResponse, Error> {
+    let filebox_vec = app_state.repo.delete_fileboxes_by_ids(&body.ids).await?;
+    for filebox in &filebox_vec {
+        remove_filebox_blob(&app_state.upload_path, filebox);
+    }
+    let revoked = filebox_vec
+        .iter()
+        .map(|filebox| AddFileboxEvent::new(FileboxEventType::Revoked, filebox));
+    record_events(&app_state, revoked, &client).await;
+    app_state
+        .metrics
+        .deleted_fileboxes
+        .with_label_values(&["admin"])
+        .inc_by(filebox_vec.len() as u64);
+
+    Ok(HttpResponse::Ok().json(RevokeFileboxResponse {
+        revoked: filebox_vec.iter().map(|filebox| filebox.id).collect(),
+    }))
+}
+
+#[tracing::instrument(skip(app_state))]
+pub async fn list_filebox_events(
+    app_state: web::Data<AppState>,
+    query: web::Query<ListFileboxEventQuery>,
+) -> Result<HttpResponse, Error> {
+    let filter = query.to_filter()?;
+    let (event_vec, total) = app_state
+        .repo
+        .list_filebox_events(&filter, query.page_size, query.offset())
+        .await?;
+
+    Ok(HttpResponse::Ok().json(ListFileboxEventResponse {
+        items: event_vec.into_iter().map(Into::into).collect(),
+        total,
+        page: query.page,
+        page_size: query.page_size,
+    }))
+}
+
+#[tracing::instrument(skip(app_state))]
+pub async fn list_daily_stats(
+    app_state: web::Data<AppState>,
+    query: web::Query<DailyStatsQuery>,
+) -> Result<HttpResponse, Error> {
+    let (from, to) = query.range(Utc::now().date_naive())?;
+    let items: Vec<DailyStatsResponse> = app_state
+        .repo
+        .list_daily_stats(from, to)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+
+    match query.format {
+        StatsFormat::Json => Ok(HttpResponse::Ok().json(ListDailyStatsResponse { items })),
+        StatsFormat::Csv => Ok(HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .body(daily_stats_csv(&items))),
+    }
+}
+
+fn daily_stats_csv(items: &[DailyStatsResponse]) -> String {
+    let mut csv = String::from(
+        "day,created_count,text_count,file_count,total_bytes,taken_count,pickup_ratio,median_pickup_secs,expired_count\n",
+    );
+    for item in items {
+        let median = item
+            .median_pickup_secs
+            .map(|secs| secs.to_string())
+            .unwrap_or_default();
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{:.4},{},{}\n",
+            item.day,
+            item.created_count,
+            item.text_count,
+            item.file_count,
+            item.total_bytes,
+            item.taken_count,
+            item.pickup_ratio,
+            median,
+            item.expired_count,
+        ));
+    }
+    csv
+}
+
+/// The row is already gone, a blob that can not be removed is only logged.
+fn remove_filebox_blob(upload_path: &str, filebox: &Filebox) {
+    if let Some(blob_path) = filebox.blob_path(upload_path) {
+        if let Err(err) = fs::remove_file(&blob_path) {
+            tracing::warn!("remove {blob_path} of filebox {} failed: {err}", filebox.id);
+        }
+    }
+}
+
+fn ip_rules_response(ip_rules: &IpRules) -> IpRulesResponse {
+    let to_strings = |kind| {
+        ip_rules
+            .list(kind)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    };
+    IpRulesResponse {
+        allow: to_strings(IpRuleKind::Allow),
+        deny: to_strings(IpRuleKind::Deny),
+    }
+}
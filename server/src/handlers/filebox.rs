@@ -1,69 +1,110 @@
 use std::fs;
+use std::io;
 use std::ops::Add;
 use std::path::Path;
 
-use actix_easy_multipart::MultipartForm;
 use actix_files::NamedFile;
 use actix_http::header::{Charset, ExtendedValue};
 use actix_http::{body, header};
-use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
 use actix_web::web::Bytes;
 use actix_web::{web, HttpRequest, HttpResponse};
-use chrono::{Duration, Local};
+use chrono::{Duration, Utc};
+use futures_util::StreamExt;
+use tokio::io::AsyncWriteExt;
 use uuid::Uuid;
-use validator::Validate;
 
 use crate::api::{
-    CreateFileboxRequest, CreateFileboxResponse, FileboxFileType, GetFileboxResponse,
-    TakeTextResponse,
+    validate_text_filebox_request, validate_upload_size, ClientInfo, CreateFileboxRequest,
+    CreateFileboxResponse, CreateTextFileboxRequest, FileboxFileType, GetFileboxResponse,
+    RawUploadQuery, TakeTextResponse, UploadForm, UploadLimits,
 };
-use crate::data::postgres::{add_new_filebox_db, get_filebox_db, update_filebox_db};
 use crate::errors::Error;
-use crate::models::filebox::{AddFilebox, FileType};
+use crate::handlers::record_events;
+use crate::models::filebox::{AddFilebox, FileType, TakeOutcome};
+use crate::models::filebox_event::{AddFileboxEvent, FileboxEventType};
 use crate::state::AppState;
 
+#[utoipa::path(
+    get,
+    path = "/v1/filebox/{code}",
+    tag = "filebox",
+    params(("code" = String, Path, description = "code of the filebox")),
+    responses(
+        (status = 200, description = "the filebox is waiting to be taken", body = GetFileboxResponse),
+        (status = 403, description = "ip limited or denied", body = ErrorResponse),
+        (status = 404, description = "no filebox of the code", body = ErrorResponse),
+        (status = 409, description = "the filebox has been taken already", body = ErrorResponse),
+        (status = 410, description = "the filebox has expired", body = ErrorResponse),
+    )
+)]
+#[tracing::instrument(skip_all)]
 pub async fn get_filebox_by_code(
     app_state: web::Data<AppState>,
+    client: ClientInfo,
     code: web::Path<String>,
 ) -> Result<HttpResponse, Error> {
     let code = code.into_inner();
 
-    let filebox = get_filebox_db(&app_state.db, code).await?;
+    let filebox = app_state
+        .metrics
+        .time_db("get_filebox", app_state.repo.get_filebox(&code))
+        .await;
+    let filebox = match filebox {
+        Ok(filebox) => filebox,
+        Err(err) => {
+            if err.is_visit_error() {
+                let lookup = [AddFileboxEvent::lookup_failed(code)];
+                record_events(&app_state, lookup, &client).await;
+            }
+            return Err(err);
+        }
+    };
+    let viewed = [AddFileboxEvent::new(FileboxEventType::Viewed, &filebox)];
+    record_events(&app_state, viewed, &client).await;
 
-    if filebox.has_taken() {
-        return Ok(HttpResponse::BadRequest().body("file box has taken"));
+    // 与取件的结果一致, 已取走或已过期但尚未清理的不再展示
+    if let Some(used_at) = filebox.used_at {
+        return Err(Error::AlreadyTaken(used_at));
+    }
+    if filebox.expired_at <= Utc::now() {
+        return Err(Error::Expired(filebox.expired_at));
     }
     let resp: GetFileboxResponse = filebox.into();
     Ok(HttpResponse::Ok().json(resp))
 }
 
+#[utoipa::path(
+    post,
+    path = "/v1/filebox",
+    tag = "filebox",
+    request_body(content = CreateFileboxRequest, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "the filebox is created", body = CreateFileboxResponse),
+        (status = 400, description = "invalid form", body = ErrorResponse),
+        (status = 403, description = "ip limited or denied", body = ErrorResponse),
+    )
+)]
+#[tracing::instrument(skip_all)]
 pub async fn add_new_filebox(
     app_state: web::Data<AppState>,
-    form: MultipartForm<CreateFileboxRequest>,
+    client: ClientInfo,
+    form: UploadForm<CreateFileboxRequest>,
 ) -> Result<HttpResponse, Error> {
-    let code_gen = app_state.code_gen.lock().await;
-    let code = code_gen.borrow_mut().next_string();
+    let code = next_code(&app_state).await;
 
     let form = form.into_inner(); // need to take mutable ownership of the form
-    form.validate()?;
+    form.validate(&app_state.upload_limits.load())?;
     let day = *form.duration_day as i64;
     let name = &*form.name;
 
     let file_type = *form.file_type;
 
-    let now = Local::now().naive_local();
+    let now = Utc::now();
     let new_filebox = match file_type {
         FileboxFileType::Text => {
-            let text = &*form.text.unwrap();
-            AddFilebox {
-                code,
-                name: name.clone(),
-                file_type: FileType::Text,
-                text: text.clone(),
-                created_at: now,
-                expired_at: now.add(Duration::days(day)),
-                ..Default::default()
-            }
+            let text = form.text.unwrap().into_inner();
+            new_text_filebox(code, name.clone(), text, day)
         }
         FileboxFileType::File => {
             let folder_name = Uuid::new_v4().to_string();
@@ -71,12 +112,14 @@ pub async fn add_new_filebox(
             fs::create_dir_all(prefix.clone())?;
 
             let upload_file = form.file.unwrap();
+            let size = upload_file.size as i64;
             let file_name = upload_file.file_name.unwrap();
             let store_filepath = format!("{prefix}/{file_name}");
             upload_file.file.persist(store_filepath).unwrap();
             AddFilebox {
                 code,
                 name: name.clone(),
+                size,
                 file_type: FileType::File,
                 file_path: format!("{folder_name}/{file_name}"),
                 created_at: now,
@@ -86,21 +129,243 @@ pub async fn add_new_filebox(
         }
     };
 
-    let new_filebox = add_new_filebox_db(&app_state.db, new_filebox).await?;
-    let resp: CreateFileboxResponse = new_filebox.into();
+    let resp = save_new_filebox(&app_state, &client, new_filebox).await?;
     Ok(HttpResponse::Ok().json(resp))
 }
 
+#[utoipa::path(
+    post,
+    path = "/v1/filebox/text",
+    tag = "filebox",
+    request_body = CreateTextFileboxRequest,
+    responses(
+        (status = 200, description = "the filebox is created", body = CreateFileboxResponse),
+        (status = 400, description = "invalid body", body = ErrorResponse),
+        (status = 403, description = "ip limited or denied", body = ErrorResponse),
+    )
+)]
+#[tracing::instrument(skip_all)]
+pub async fn add_new_text_filebox(
+    app_state: web::Data<AppState>,
+    client: ClientInfo,
+    body: web::Json<CreateTextFileboxRequest>,
+) -> Result<HttpResponse, Error> {
+    let body = body.into_inner();
+    validate_text_filebox_request(&body, &app_state.upload_limits.load())?;
+
+    let code = next_code(&app_state).await;
+    let new_filebox = new_text_filebox(code, body.name, body.text, body.duration_day as i64);
+    let resp = save_new_filebox(&app_state, &client, new_filebox).await?;
+    Ok(HttpResponse::Ok().json(resp))
+}
+
+#[utoipa::path(
+    put,
+    path = "/v1/filebox/raw/{filename}",
+    tag = "filebox",
+    params(
+        ("filename" = String, Path, description = "name of the stored file"),
+        RawUploadQuery,
+        ("X-Filebox-Duration-Day" = Option<u8>, Header, description = "used when the query has no duration_day"),
+        ("X-Filebox-Max-Downloads" = Option<u32>, Header, description = "used when the query has no max_downloads"),
+    ),
+    request_body(content = [u8], content_type = "application/octet-stream"),
+    responses(
+        (
+            status = 200,
+            description = "the filebox is created, the code is plain text unless json is accepted",
+            body = CreateFileboxResponse,
+        ),
+        (status = 400, description = "invalid options or body", body = ErrorResponse),
+        (status = 403, description = "ip limited or denied", body = ErrorResponse),
+    )
+)]
+#[tracing::instrument(skip(app_state, client, query, req, payload))]
+pub async fn add_new_raw_filebox(
+    app_state: web::Data<AppState>,
+    client: ClientInfo,
+    file_name: web::Path<String>,
+    query: web::Query<RawUploadQuery>,
+    req: HttpRequest,
+    mut payload: web::Payload,
+) -> Result<HttpResponse, Error> {
+    let file_name = file_name.into_inner();
+    let limits = app_state.upload_limits.load();
+    let day = query
+        .into_inner()
+        .or_headers(&req)?
+        .validate(&file_name, &limits)?;
+    let content_length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<usize>().ok());
+    if let Some(size) = content_length {
+        validate_upload_size(size, &limits)?;
+    }
+
+    let folder_name = Uuid::new_v4().to_string();
+    let prefix = format!("{}/{}", app_state.upload_path, folder_name);
+    fs::create_dir_all(&prefix)?;
+    let store_filepath = format!("{prefix}/{file_name}");
+    let size = match write_payload(&mut payload, &store_filepath, &limits).await {
+        Ok(size) => size,
+        Err(err) => {
+            // 上传失败时不保留残缺的文件
+            let _ = fs::remove_dir_all(&prefix);
+            return Err(err);
+        }
+    };
+
+    let now = Utc::now();
+    let new_filebox = AddFilebox {
+        code: next_code(&app_state).await,
+        name: file_name.clone(),
+        size: size as i64,
+        file_type: FileType::File,
+        file_path: format!("{folder_name}/{file_name}"),
+        created_at: now,
+        expired_at: now.add(Duration::days(day as i64)),
+        ..Default::default()
+    };
+    let resp = match save_new_filebox(&app_state, &client, new_filebox).await {
+        Ok(resp) => resp,
+        Err(err) => {
+            // 未入库的文件不会被定时任务清理
+            let _ = fs::remove_dir_all(&prefix);
+            return Err(err);
+        }
+    };
+
+    let accepts_json = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("application/json"));
+    if accepts_json {
+        Ok(HttpResponse::Ok().json(resp))
+    } else {
+        Ok(HttpResponse::Ok()
+            .content_type(ContentType::plaintext())
+            .body(format!("{}\n", resp.code)))
+    }
+}
+
+/// Stream the request body into `path`, stopping once it grows past
+/// `max_upload_size`, and return the bytes written.
+async fn write_payload(
+    payload: &mut web::Payload,
+    path: &str,
+    limits: &UploadLimits,
+) -> Result<usize, Error> {
+    let mut file = tokio::fs::File::create(path).await?;
+    let mut size = 0;
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(io::Error::other)?;
+        size += chunk.len();
+        if size > limits.max_upload_size {
+            validate_upload_size(size, limits)?;
+        }
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    validate_upload_size(size, limits)?;
+    Ok(size)
+}
+
+async fn next_code(app_state: &AppState) -> String {
+    let code_gen = app_state.code_gen.lock().await;
+    let code = code_gen.borrow_mut().next_string();
+    code
+}
+
+fn new_text_filebox(code: String, name: String, text: String, day: i64) -> AddFilebox {
+    let now = Utc::now();
+    AddFilebox {
+        code,
+        name,
+        file_type: FileType::Text,
+        text,
+        created_at: now,
+        expired_at: now.add(Duration::days(day)),
+        ..Default::default()
+    }
+}
+
+/// Store a filebox of any upload path, count it and record its creation.
+async fn save_new_filebox(
+    app_state: &AppState,
+    client: &ClientInfo,
+    new_filebox: AddFilebox,
+) -> Result<CreateFileboxResponse, Error> {
+    let metrics = &app_state.metrics;
+    let new_filebox = metrics
+        .time_db("add_filebox", app_state.repo.add_filebox(new_filebox))
+        .await?;
+    metrics
+        .uploads
+        .with_label_values(&[&String::from(new_filebox.file_type)])
+        .inc();
+    metrics.bytes_in.inc_by(new_filebox.content_size() as u64);
+    let created = [AddFileboxEvent::new(
+        FileboxEventType::Created,
+        &new_filebox,
+    )];
+    record_events(app_state, created, client).await;
+    Ok(new_filebox.into())
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/filebox/{code}",
+    tag = "filebox",
+    params(("code" = String, Path, description = "code of the filebox")),
+    responses(
+        (
+            status = 200,
+            description = "the text or the file as an attachment, the filebox is taken",
+            body = [u8],
+            content_type = "application/octet-stream",
+        ),
+        (status = 403, description = "ip limited or denied", body = ErrorResponse),
+        (status = 404, description = "no filebox of the code", body = ErrorResponse),
+        (status = 409, description = "the filebox has been taken already", body = ErrorResponse),
+        (status = 410, description = "the filebox has expired", body = ErrorResponse),
+    )
+)]
+#[tracing::instrument(skip_all)]
 pub async fn take_filebox_by_code(
     app_state: web::Data<AppState>,
+    client: ClientInfo,
     code: web::Path<String>,
     req: HttpRequest,
 ) -> Result<HttpResponse, Error> {
     let code = code.into_inner();
 
-    let filebox = update_filebox_db(&app_state.db, code).await?;
+    let metrics = &app_state.metrics;
+    let filebox = metrics
+        .time_db("take_filebox", app_state.repo.take_filebox(&code))
+        .await
+        .and_then(TakeOutcome::into_result);
+    let filebox = match filebox {
+        Ok(filebox) => filebox,
+        Err(err) => {
+            if err.is_visit_error() {
+                let lookup = [AddFileboxEvent::lookup_failed(code)];
+                record_events(&app_state, lookup, &client).await;
+            }
+            return Err(err);
+        }
+    };
+    let taken = [AddFileboxEvent::new(FileboxEventType::Taken, &filebox)];
+    record_events(&app_state, taken, &client).await;
+    metrics
+        .pickups
+        .with_label_values(&[&String::from(filebox.file_type)])
+        .inc();
+
     match filebox.file_type {
         FileType::Text => {
+            metrics.bytes_out.inc_by(filebox.content_size() as u64);
             let resp: TakeTextResponse = filebox.into();
             let file_name = format!("{}.txt", resp.name);
             let cd = ContentDisposition {
@@ -129,6 +394,7 @@ pub async fn take_filebox_by_code(
 
             let file_path = format!("{}/{}", app_state.upload_path, filebox.file_path);
             let file_stream = NamedFile::open_async(file_path).await?;
+            metrics.bytes_out.inc_by(filebox.content_size() as u64);
             let into_resp = file_stream.into_response(&req);
             let mut resp = HttpResponse::Ok();
             let cd = ContentDisposition {
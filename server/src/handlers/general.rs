@@ -1,12 +1,159 @@
-use crate::{api::HealthCheckResponse, state::AppState};
-use actix_web::{web, HttpResponse};
+use std::{
+    collections::BTreeMap, ffi::CString, fs, io, os::unix::ffi::OsStrExt, path::Path,
+    time::Duration,
+};
 
-pub async fn health_check_handler(app_state: web::Data<AppState>) -> HttpResponse {
+use crate::{
+    api::{ComponentHealth, ComponentStatus, HealthCheckResponse, ProbeResponse},
+    data::{limiter::LimiterFailurePolicy, redis::ping_redis},
+    errors::Error,
+    openapi::ApiDoc,
+    state::{AppState, CacheState},
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use utoipa::OpenApi;
+use uuid::Uuid;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "general",
+    responses((status = 200, description = "the server is up", body = HealthCheckResponse))
+)]
+pub async fn health_check_handler(
+    app_state: web::Data<AppState>,
+    req: HttpRequest,
+) -> HttpResponse {
     let mut visit_count = app_state.visit_count.lock().unwrap();
     *visit_count += 1;
 
+    // CacheState is optional so the health check works without redis configured
+    let limiter_fallback = req
+        .app_data::<web::Data<CacheState>>()
+        .map(|cache_state| cache_state.ip_limiter.is_fallback_active())
+        .unwrap_or(false);
+
     HttpResponse::Ok().json(HealthCheckResponse {
         message: app_state.health_check_response.clone(),
         health_check_count: *visit_count,
+        limiter_fallback,
+    })
+}
+
+/// The usage gauges are refreshed by `scheduler::start_refresh_usage_metrics`.
+pub async fn metrics_handler(app_state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(app_state.metrics.encode())
+}
+
+/// The process is up and serving requests, dependencies are not checked.
+pub async fn liveness_handler() -> HttpResponse {
+    HttpResponse::Ok().json(ProbeResponse {
+        status: ComponentStatus::Up,
+        components: BTreeMap::new(),
     })
 }
+
+/// Check the filebox storage, redis and the upload directory, responds 503 when a
+/// required one is down. Redis is only required with the closed failure
+/// policy, otherwise the ip limiter keeps working in memory. It is not
+/// checked at all when not configured.
+pub async fn readiness_handler(app_state: web::Data<AppState>, req: HttpRequest) -> HttpResponse {
+    let mut components = BTreeMap::new();
+
+    let db = probe(app_state.repo.ping()).await;
+    components.insert(
+        app_state.repo.backend().to_string(),
+        component_health(db, true),
+    );
+
+    let cache_state = req.app_data::<web::Data<CacheState>>();
+    if let Some(cache_state) = cache_state.filter(|state| state.redis_actor.is_some()) {
+        let redis_actor = cache_state.redis_actor.as_ref().unwrap();
+        let redis = probe(ping_redis(redis_actor)).await;
+        let required = cache_state.ip_limiter.policy() == LimiterFailurePolicy::FailClosed;
+        components.insert("redis".to_string(), component_health(redis, required));
+    }
+
+    let storage = check_storage(&app_state.upload_path, app_state.storage_min_free_bytes);
+    components.insert("storage".to_string(), component_health(storage, true));
+
+    let ready = components
+        .values()
+        .all(|c| !c.required || c.status == ComponentStatus::Up);
+    let resp = ProbeResponse {
+        status: if ready {
+            ComponentStatus::Up
+        } else {
+            ComponentStatus::Down
+        },
+        components,
+    };
+    if ready {
+        HttpResponse::Ok().json(resp)
+    } else {
+        HttpResponse::ServiceUnavailable().json(resp)
+    }
+}
+
+async fn probe(fut: impl std::future::Future<Output = Result<(), Error>>) -> Result<(), String> {
+    match actix_rt::time::timeout(PROBE_TIMEOUT, fut).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(err)) => Err(err.to_string()),
+        Err(_) => Err(format!("no response in {PROBE_TIMEOUT:?}")),
+    }
+}
+
+fn component_health(result: Result<(), String>, required: bool) -> ComponentHealth {
+    match result {
+        Ok(()) => ComponentHealth {
+            status: ComponentStatus::Up,
+            required,
+            message: None,
+        },
+        Err(message) => ComponentHealth {
+            status: ComponentStatus::Down,
+            required,
+            message: Some(message),
+        },
+    }
+}
+
+fn check_storage(upload_path: &str, min_free_bytes: u64) -> Result<(), String> {
+    // 并发的探测各写各的文件, 不会删掉彼此的
+    let probe_path = Path::new(upload_path).join(format!(".ready-{}", Uuid::new_v4()));
+    fs::write(&probe_path, b"ok")
+        .and_then(|()| fs::remove_file(&probe_path))
+        .map_err(|err| format!("{upload_path} is not writable: {err}"))?;
+
+    let free = free_space(Path::new(upload_path))
+        .map_err(|err| format!("read free space of {upload_path} failed: {err}"))?;
+    if free < min_free_bytes {
+        return Err(format!(
+            "{free} bytes free in {upload_path}, at least {min_free_bytes} needed"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Bytes available to the server process in the file system of `path`.
+fn free_space(path: &Path) -> io::Result<u64> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFTEY: c_path is a valid nul terminated string and stat a valid out pointer
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+pub async fn openapi_handler() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
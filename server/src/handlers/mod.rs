@@ -1,2 +1,28 @@
+pub mod admin;
 pub mod filebox;
 pub mod general;
+
+use crate::{api::ClientInfo, models::filebox_event::AddFileboxEvent, state::AppState};
+
+/// Write audit events on behalf of `client`, a failed insert is logged but
+/// never fails the request.
+async fn record_events(
+    app_state: &AppState,
+    events: impl IntoIterator<Item = AddFileboxEvent>,
+    client: &ClientInfo,
+) {
+    let events: Vec<AddFileboxEvent> = events
+        .into_iter()
+        .map(|event| AddFileboxEvent {
+            ip: client.ip.clone(),
+            user_agent: client.user_agent.clone(),
+            ..event
+        })
+        .collect();
+
+    if let Err(err) = app_state.repo.add_filebox_events(&events).await {
+        // 事件里的 code 即取件凭证, 不能写进日志
+        let event_types: Vec<_> = events.iter().map(|event| event.event_type).collect();
+        tracing::error!("record {event_types:?} events failed: {err:?}");
+    }
+}
@@ -0,0 +1,193 @@
+use actix_web::{
+    http::header::{AcceptLanguage, Header, Preference},
+    web, HttpRequest,
+};
+use serde::Deserialize;
+
+/// Language of the `message` of an `ErrorResponse`, the `error` name is the
+/// same in every language.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Lang {
+    #[default]
+    En,
+    ZhCn,
+}
+
+#[derive(Debug, Deserialize)]
+struct LangQuery {
+    lang: Option<String>,
+}
+
+impl Lang {
+    /// Only the primary subtag is looked at, every `zh-*` gets the simplified
+    /// Chinese messages.
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.split(['-', '_']).next().unwrap_or_default();
+        match primary.to_ascii_lowercase().as_str() {
+            "en" => Some(Lang::En),
+            "zh" => Some(Lang::ZhCn),
+            _ => None,
+        }
+    }
+
+    /// `?lang=` first, then the most preferred supported language of
+    /// `Accept-Language`, English otherwise.
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let query = web::Query::<LangQuery>::from_query(req.query_string()).ok();
+        if let Some(lang) = query
+            .and_then(|query| query.into_inner().lang)
+            .and_then(|lang| Lang::from_tag(&lang))
+        {
+            return lang;
+        }
+
+        AcceptLanguage::parse(req)
+            .ok()
+            .and_then(|accept| {
+                accept.ranked().into_iter().find_map(|pref| match pref {
+                    Preference::Specific(tag) => Lang::from_tag(tag.as_str()),
+                    Preference::Any => None,
+                })
+            })
+            .unwrap_or_default()
+    }
+
+    fn catalog(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Lang::En => EN,
+            Lang::ZhCn => ZH_CN,
+        }
+    }
+}
+
+// 键为 ErrorResponse 的 error 名, 5xx 的内部错误共用 INTERNAL_SERVER_ERROR
+const EN: &[(&str, &str)] = &[
+    ("VALIDATE_ARGS_ERROR", "input validate error"),
+    ("INPUT_VALIDATE_ERROR", "input validate error"),
+    ("MULTIPART_ERROR", "invalid multipart form"),
+    ("INVALID_CODE", "{detail}"),
+    ("INVALID_FILE_TYPE", "invalid file type: {detail}"),
+    ("INVALID_CONFIG", "{detail}"),
+    (
+        "CONFIGURED_IP_RULE",
+        "{cidr} comes from {key} in the configuration, remove it there and reload",
+    ),
+    ("NOT_FOUND", "not found"),
+    ("ALREADY_TAKEN", "file box has been taken at {used_at}"),
+    ("EXPIRED", "file box expired at {expired_at}"),
+    (
+        "IP_VISIT_ERROR_LIMIT",
+        "{limit} wrong codes today, please come back tomorrow",
+    ),
+    (
+        "IP_UPLOAD_LIMIT",
+        "{limit} uploads today, please upload again tomorrow",
+    ),
+    ("IP_DENIED", "your ip has been denied"),
+    ("UNAUTHORIZED", "unauthorized"),
+    ("LIMITER_UNAVAILABLE", "service temporarily unavailable"),
+    ("UNSUPPORTED", "{what} is not supported by this storage"),
+    ("INTERNAL_SERVER_ERROR", "internal server error"),
+];
+
+const ZH_CN: &[(&str, &str)] = &[
+    ("VALIDATE_ARGS_ERROR", "输入参数有误"),
+    ("INPUT_VALIDATE_ERROR", "输入参数有误"),
+    ("MULTIPART_ERROR", "表单格式有误"),
+    ("INVALID_CODE", "{detail}"),
+    ("INVALID_FILE_TYPE", "不支持的文件类型: {detail}"),
+    ("INVALID_CONFIG", "{detail}"),
+    (
+        "CONFIGURED_IP_RULE",
+        "{cidr} 来自配置中的 {key}, 请在配置中删除后重新加载",
+    ),
+    ("NOT_FOUND", "文件不存在"),
+    ("ALREADY_TAKEN", "文件已于 {used_at} 被取走"),
+    ("EXPIRED", "文件已于 {expired_at} 过期"),
+    (
+        "IP_VISIT_ERROR_LIMIT",
+        "今日文件口令错误已达 {limit} 次, 请明天再访问",
+    ),
+    (
+        "IP_UPLOAD_LIMIT",
+        "今日文件上传已达 {limit} 次, 请明天再上传",
+    ),
+    ("IP_DENIED", "你的 ip 已被禁止访问"),
+    ("UNAUTHORIZED", "未授权"),
+    ("LIMITER_UNAVAILABLE", "服务暂时不可用"),
+    ("UNSUPPORTED", "当前存储不支持 {what}"),
+    ("INTERNAL_SERVER_ERROR", "服务器内部错误"),
+];
+
+/// The message of `key` in `lang` with every `{name}` of `args` filled in,
+/// the English one when `lang` misses it.
+pub fn message(lang: Lang, key: &str, args: &[(&str, String)]) -> String {
+    let template = [lang, Lang::En]
+        .into_iter()
+        .find_map(|lang| {
+            lang.catalog()
+                .iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, template)| *template)
+        })
+        .unwrap_or(key);
+    args.iter()
+        .fold(template.to_string(), |message, (name, value)| {
+            message.replace(&format!("{{{name}}}"), value)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[test]
+    fn catalogs_should_have_the_same_keys() {
+        let keys = |catalog: &[(&'static str, &'static str)]| {
+            let mut keys: Vec<_> = catalog.iter().map(|(key, _)| *key).collect();
+            keys.sort();
+            keys
+        };
+        assert_eq!(keys(EN), keys(ZH_CN));
+    }
+
+    #[test]
+    fn message_should_fill_args() {
+        let args = [("limit", "3".to_string())];
+        assert_eq!(
+            message(Lang::ZhCn, "IP_VISIT_ERROR_LIMIT", &args),
+            "今日文件口令错误已达 3 次, 请明天再访问"
+        );
+        assert_eq!(
+            message(Lang::En, "IP_UPLOAD_LIMIT", &args),
+            "3 uploads today, please upload again tomorrow"
+        );
+        assert_eq!(message(Lang::ZhCn, "NO_SUCH_KEY", &[]), "NO_SUCH_KEY");
+    }
+
+    #[test]
+    fn lang_should_be_negotiated() {
+        let lang = |req: TestRequest| Lang::from_request(&req.to_http_request());
+
+        assert_eq!(lang(TestRequest::default()), Lang::En);
+        assert_eq!(
+            lang(TestRequest::default().insert_header(("Accept-Language", "fr, zh-TW;q=0.8"))),
+            Lang::ZhCn
+        );
+        assert_eq!(
+            lang(TestRequest::default().insert_header(("Accept-Language", "zh-CN;q=0.5, en"))),
+            Lang::En
+        );
+        assert_eq!(
+            lang(TestRequest::with_uri("/?lang=zh-CN").insert_header(("Accept-Language", "en"))),
+            Lang::ZhCn
+        );
+        // 不支持的 lang 参数交给 Accept-Language
+        assert_eq!(
+            lang(TestRequest::with_uri("/?lang=fr").insert_header(("Accept-Language", "zh"))),
+            Lang::ZhCn
+        );
+    }
+}
@@ -1,11 +1,18 @@
 pub mod api;
+pub mod cli;
+pub mod config;
 pub mod data;
 pub mod errors;
 pub mod handlers;
+pub mod i18n;
+pub mod metrics;
 pub mod middlewares;
 pub mod models;
+pub mod openapi;
+pub mod rate_limit;
 pub mod scheduler;
 pub mod state;
+pub mod telemetry;
 
 #[cfg(test)]
 pub mod test_utils;
@@ -0,0 +1,151 @@
+use std::{future::Future, time::Instant};
+
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+
+/// Prometheus metrics of the server, served on `/metrics`.
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Registry,
+    /// labels: file_type
+    pub uploads: IntCounterVec,
+    /// labels: file_type
+    pub pickups: IntCounterVec,
+    pub bytes_in: IntCounter,
+    pub bytes_out: IntCounter,
+    pub lookup_failures: IntCounter,
+    /// labels: kind, one of visit_error, upload, denied, rate_limit
+    pub limit_rejections: IntCounterVec,
+    /// labels: op
+    pub redis_latency: HistogramVec,
+    /// labels: op
+    pub db_latency: HistogramVec,
+    pub scheduler_run_duration: Histogram,
+    /// labels: reason, one of cleanup, admin
+    pub deleted_fileboxes: IntCounterVec,
+    pub active_fileboxes: IntGauge,
+    pub storage_used_bytes: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("filebox".to_string()), None).unwrap();
+
+        // SAFTEY: the names below are valid and registered only once
+        let counter_vec = |name: &str, help: &str, labels: &[&str]| {
+            let counter = IntCounterVec::new(Opts::new(name, help), labels).unwrap();
+            registry.register(Box::new(counter.clone())).unwrap();
+            counter
+        };
+        let counter = |name: &str, help: &str| {
+            let counter = IntCounter::new(name, help).unwrap();
+            registry.register(Box::new(counter.clone())).unwrap();
+            counter
+        };
+        let histogram_vec = |name: &str, help: &str, labels: &[&str]| {
+            let histogram = HistogramVec::new(HistogramOpts::new(name, help), labels).unwrap();
+            registry.register(Box::new(histogram.clone())).unwrap();
+            histogram
+        };
+        let gauge = |name: &str, help: &str| {
+            let gauge = IntGauge::new(name, help).unwrap();
+            registry.register(Box::new(gauge.clone())).unwrap();
+            gauge
+        };
+
+        let scheduler_run_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "scheduler_run_duration_seconds",
+                "duration of the expired filebox cleanup",
+            )
+            .buckets(vec![0.01, 0.1, 1.0, 10.0, 60.0, 300.0]),
+        )
+        .unwrap();
+        registry
+            .register(Box::new(scheduler_run_duration.clone()))
+            .unwrap();
+
+        Self {
+            uploads: counter_vec("uploads_total", "created fileboxes", &["file_type"]),
+            pickups: counter_vec("pickups_total", "taken fileboxes", &["file_type"]),
+            bytes_in: counter("bytes_in_total", "bytes of uploaded files and texts"),
+            bytes_out: counter("bytes_out_total", "bytes of taken files and texts"),
+            lookup_failures: counter("lookup_failures_total", "lookups of an unknown code"),
+            limit_rejections: counter_vec(
+                "limit_rejections_total",
+                "requests rejected by a limit",
+                &["kind"],
+            ),
+            redis_latency: histogram_vec(
+                "redis_latency_seconds",
+                "latency of the redis commands",
+                &["op"],
+            ),
+            db_latency: histogram_vec("db_latency_seconds", "latency of the db queries", &["op"]),
+            scheduler_run_duration,
+            deleted_fileboxes: counter_vec(
+                "deleted_fileboxes_total",
+                "deleted fileboxes",
+                &["reason"],
+            ),
+            active_fileboxes: gauge("active_fileboxes", "fileboxes not taken nor expired"),
+            storage_used_bytes: gauge("storage_used_bytes", "bytes of the stored files"),
+            registry,
+        }
+    }
+
+    /// Await `fut` and record its duration as a db query `op`.
+    pub async fn time_db<T>(&self, op: &str, fut: impl Future<Output = T>) -> T {
+        time(&self.db_latency, op, fut).await
+    }
+
+    /// Await `fut` and record its duration as a redis command `op`.
+    pub async fn time_redis<T>(&self, op: &str, fut: impl Future<Output = T>) -> T {
+        time(&self.redis_latency, op, fut).await
+    }
+
+    /// Render every metric in the prometheus text format.
+    pub fn encode(&self) -> String {
+        let mut buf = vec![];
+        // SAFTEY: the text encoder only fails on a writer error and the vec never fails
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buf)
+            .unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn time<T>(histogram: &HistogramVec, op: &str, fut: impl Future<Output = T>) -> T {
+    let start = Instant::now();
+    let output = fut.await;
+    histogram
+        .with_label_values(&[op])
+        .observe(start.elapsed().as_secs_f64());
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn metrics_should_encode() {
+        let metrics = Metrics::new();
+        metrics.uploads.with_label_values(&["file"]).inc();
+        metrics.bytes_in.inc_by(42);
+        assert_eq!(metrics.time_db("get", async { 1 }).await, 1);
+
+        let text = metrics.encode();
+        assert!(text.contains(r#"filebox_uploads_total{file_type="file"} 1"#));
+        assert!(text.contains("filebox_bytes_in_total 42"));
+        assert!(text.contains(r#"filebox_db_latency_seconds_count{op="get"} 1"#));
+    }
+}
@@ -1,81 +1,333 @@
+use std::{str::FromStr, time::Instant};
+
 use crate::{
-    api::{DATE_FORMAT, IP_UPLOAD_LIMIT_HEADER, IP_VISIT_ERROR_LIMIT_HEADER},
-    data::redis::{
-        add_ip_upload_limit_count, add_ip_visit_error_limit_count, is_allow_ip_for_upload,
-        is_allow_ip_for_visit,
+    api::{
+        client_ip, DATE_FORMAT, IP_UPLOAD_LIMIT_HEADER, IP_VISIT_ERROR_LIMIT_HEADER,
+        IP_VISIT_ERROR_REMAINING_HEADER, REQUEST_ID_HEADER,
+    },
+    data::limiter::{
+        add_ip_upload_limit_count, add_ip_visit_error_limit_count, add_lookup_failure_count,
+        get_ip_visit_error_count, get_lookup_failure_count, is_allow_ip_for_upload,
     },
     errors,
-    state::CacheState,
+    i18n::Lang,
+    state::{AppState, CacheState},
+};
+use actix_http::{
+    body::{BoxBody, MessageBody},
+    header::{HeaderName, HeaderValue},
 };
-use actix_http::body::BoxBody;
 use actix_web::{
     dev::{ServiceRequest, ServiceResponse},
-    web, Error,
+    error::InternalError,
+    http::{header, StatusCode},
+    web, Error, HttpResponse,
 };
 
 use actix_web_lab::middleware::Next;
 use chrono::{DateTime, Datelike, Local, TimeZone, Utc};
+use subtle::ConstantTimeEq;
+use tracing::Instrument;
+use uuid::Uuid;
 
 pub async fn ip_visit_error_limit_of_day_mw(
+    app_state: web::Data<AppState>,
     cache_state: web::Data<CacheState>,
     req: ServiceRequest,
     next: Next<BoxBody>,
 ) -> Result<ServiceResponse<BoxBody>, Error> {
-    let ip_allower = &cache_state.ip_allower;
-    let addr = &cache_state.redis_actor;
+    let ip_allower = cache_state.ip_allower.load_full();
+    let ip_rules = cache_state.ip_rules.load_full();
+    let ip_limiter = cache_state.ip_limiter.as_ref();
 
-    if !is_allow_ip_from_header(&req, IP_VISIT_ERROR_LIMIT_HEADER) {
+    let ip = get_ip(&req);
+    if ip_rules.is_denied(&ip) {
+        count_limit_rejection(&app_state, "denied");
         return Ok(ServiceResponse::new(
             req.request().clone(),
-            errors::Error::IpVisitErrorLimit(ip_allower.visit_error_limit).to_response(),
+            errors::Error::IpDenied.to_response(),
         ));
     }
+    if ip_rules.is_allowed(&ip) {
+        return next.call(req).await;
+    }
 
-    let ip = get_ip(&req);
-    if !is_allow_ip_for_visit(addr, &ip, ip_allower.visit_error_limit).await? {
+    if !is_allow_ip_from_header(&req, IP_VISIT_ERROR_LIMIT_HEADER) {
+        count_limit_rejection(&app_state, "visit_error");
         return Ok(ServiceResponse::new(
             req.request().clone(),
-            errors::Error::IpVisitErrorLimit(ip_allower.visit_error_limit).to_response(),
+            visit_error_limit_response(ip_allower.visit_error_limit),
         ));
     }
 
-    let res = next.call(req).await?;
-    if res.response().error().is_some() {
-        add_ip_visit_error_limit_count(addr, &ip, ip_allower.ttl).await?
+    let visit_error_count = get_ip_visit_error_count(ip_limiter, &ip).await?;
+    if visit_error_count >= ip_allower.visit_error_limit {
+        count_limit_rejection(&app_state, "visit_error");
+        return Ok(ServiceResponse::new(
+            req.request().clone(),
+            visit_error_limit_response(ip_allower.visit_error_limit),
+        ));
     }
 
+    let mut res = next.call(req).await?;
+    let is_visit_error = res
+        .response()
+        .error()
+        .and_then(|err| err.as_error::<errors::Error>())
+        .map(errors::Error::is_visit_error)
+        .unwrap_or(false);
+    let visit_error_count = if is_visit_error {
+        add_ip_visit_error_limit_count(ip_limiter, &ip, ip_allower.ttl).await?
+    } else {
+        visit_error_count
+    };
+
+    let remaining = (ip_allower.visit_error_limit - visit_error_count).max(0);
+    res.headers_mut().insert(
+        HeaderName::from_str(IP_VISIT_ERROR_REMAINING_HEADER).unwrap(),
+        HeaderValue::from(remaining),
+    );
+
     Ok(res)
 }
 
 pub async fn ip_upload_limit_of_day_mw(
+    app_state: web::Data<AppState>,
     cache_state: web::Data<CacheState>,
     req: ServiceRequest,
     next: Next<BoxBody>,
 ) -> Result<ServiceResponse<BoxBody>, Error> {
-    let ip_allower = &cache_state.ip_allower;
-    let addr = &cache_state.redis_actor;
+    let ip_allower = cache_state.ip_allower.load_full();
+    let ip_rules = cache_state.ip_rules.load_full();
+    let ip_limiter = cache_state.ip_limiter.as_ref();
+
+    let ip = get_ip(&req);
+    if ip_rules.is_denied(&ip) {
+        count_limit_rejection(&app_state, "denied");
+        return Ok(ServiceResponse::new(
+            req.request().clone(),
+            errors::Error::IpDenied.to_response(),
+        ));
+    }
+    if ip_rules.is_allowed(&ip) {
+        return next.call(req).await;
+    }
 
     if !is_allow_ip_from_header(&req, IP_UPLOAD_LIMIT_HEADER) {
+        count_limit_rejection(&app_state, "upload");
         return Ok(ServiceResponse::new(
             req.request().clone(),
             errors::Error::IpUploadLimit(ip_allower.upload_limit).to_response(),
         ));
     }
 
-    let ip = get_ip(&req);
-
-    if !is_allow_ip_for_upload(addr, &ip, ip_allower.upload_limit).await? {
+    if !is_allow_ip_for_upload(ip_limiter, &ip, ip_allower.upload_limit).await? {
+        count_limit_rejection(&app_state, "upload");
         return Ok(ServiceResponse::new(
             req.request().clone(),
             errors::Error::IpUploadLimit(ip_allower.upload_limit).to_response(),
         ));
     }
 
-    add_ip_upload_limit_count(addr, &ip, ip_allower.ttl).await?;
+    add_ip_upload_limit_count(ip_limiter, &ip, ip_allower.ttl).await?;
 
     next.call(req).await
 }
 
+pub async fn lookup_failure_delay_mw(
+    app_state: web::Data<AppState>,
+    cache_state: web::Data<CacheState>,
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let ip_limiter = cache_state.ip_limiter.as_ref();
+    let lookup_guard = &cache_state.lookup_guard;
+
+    let failure_count = get_lookup_failure_count(ip_limiter).await?;
+    let delay = lookup_guard.delay(failure_count);
+    if !delay.is_zero() {
+        tracing::warn!(
+            "{failure_count} failed lookups in the current minute, delay lookup {delay:?}"
+        );
+        actix_rt::time::sleep(delay).await;
+    }
+
+    let res = next.call(req).await?;
+    let is_visit_error = res
+        .response()
+        .error()
+        .and_then(|err| err.as_error::<errors::Error>())
+        .map(errors::Error::is_visit_error)
+        .unwrap_or(false);
+    if is_visit_error {
+        app_state.metrics.lookup_failures.inc();
+        add_lookup_failure_count(ip_limiter).await?;
+    }
+
+    Ok(res)
+}
+
+/// Run the request in a span carrying its `X-Request-Id`, taken from the
+/// request when it looks sane or generated otherwise, and echo the id back.
+/// Replaces the actix access log, it has to be the outermost middleware.
+pub async fn request_id_mw<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, Error> {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| is_valid_request_id(value))
+        .map(String::from)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let ip = client_ip(req.request());
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %req.method(),
+        route = tracing::field::Empty,
+        ip = %ip,
+    );
+    let start = Instant::now();
+    let mut res = next.call(req).instrument(span.clone()).await?;
+    // 记录路由模板而不是路径, /v1/filebox/{code} 的路径里就是取件码
+    if let Some(route) = res.request().match_pattern() {
+        span.record("route", route.as_str());
+    }
+    span.in_scope(|| {
+        let status = res.status().as_u16();
+        let elapsed_ms = start.elapsed().as_millis() as u64;
+        match res.response().error() {
+            Some(err) if res.status().is_server_error() => {
+                tracing::error!(status, elapsed_ms, error = ?err, "request failed")
+            }
+            Some(err) => tracing::info!(status, elapsed_ms, error = %err, "request finished"),
+            None => tracing::info!(status, elapsed_ms, "request finished"),
+        }
+    });
+
+    // SAFTEY: the id is either a uuid or only contains visible ascii checked above
+    res.headers_mut().insert(
+        HeaderName::from_str(REQUEST_ID_HEADER).unwrap(),
+        HeaderValue::from_str(&request_id).unwrap(),
+    );
+    Ok(res)
+}
+
+fn is_valid_request_id(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= 128
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Count the requests rejected by the global rate limiter, it has to wrap
+/// the rate limiter.
+pub async fn rate_limit_metrics_mw<B: MessageBody>(
+    app_state: web::Data<AppState>,
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, Error> {
+    let res = next.call(req).await?;
+    if res.status() == StatusCode::TOO_MANY_REQUESTS {
+        count_limit_rejection(&app_state, "rate_limit");
+    }
+
+    Ok(res)
+}
+
+/// Translate the `message` of an `errors::Error` response into the language
+/// of the request, see `Lang::from_request`. The status, the headers and the
+/// `error` name stay the same.
+pub async fn localize_error_mw<B: MessageBody + 'static>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let lang = Lang::from_request(req.request());
+    if lang == Lang::default() {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_boxed_body);
+    }
+
+    match next.call(req).await {
+        Ok(res) => {
+            let body = res
+                .response()
+                .error()
+                .and_then(|err| err.as_error::<errors::Error>())
+                .and_then(|err| serde_json::to_vec(&err.to_error_response(lang)).ok());
+            match body {
+                Some(body) => Ok(res.map_body(|_, _| BoxBody::new(body))),
+                None => Ok(res.map_into_boxed_body()),
+            }
+        }
+        Err(err) => {
+            let Some(body) = err
+                .as_error::<errors::Error>()
+                .and_then(|e| serde_json::to_vec(&e.to_error_response(lang)).ok())
+            else {
+                return Err(err);
+            };
+            let resp = err.error_response().set_body(BoxBody::new(body));
+            Err(InternalError::from_response(err, resp).into())
+        }
+    }
+}
+
+pub async fn admin_token_mw(
+    app_state: web::Data<AppState>,
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if !is_admin_token_match(&req, app_state.admin_token.as_deref()) {
+        return Ok(ServiceResponse::new(
+            req.request().clone(),
+            errors::Error::Unauthorized.to_response(),
+        ));
+    }
+
+    next.call(req).await
+}
+
+/// The 403 of a used up visit error limit, telling the client no attempt is
+/// left just like the responses that got through.
+fn visit_error_limit_response(limit: i32) -> HttpResponse {
+    let mut res = errors::Error::IpVisitErrorLimit(limit).to_response();
+    res.headers_mut().insert(
+        HeaderName::from_str(IP_VISIT_ERROR_REMAINING_HEADER).unwrap(),
+        HeaderValue::from(0),
+    );
+    res
+}
+
+fn count_limit_rejection(app_state: &AppState, kind: &str) {
+    app_state
+        .metrics
+        .limit_rejections
+        .with_label_values(&[kind])
+        .inc();
+}
+
+fn is_admin_token_match(req: &ServiceRequest, admin_token: Option<&str>) -> bool {
+    let admin_token = match admin_token {
+        Some(token) if !token.is_empty() => token,
+        _ => return false,
+    };
+
+    // 逐字节比较会从耗时泄露前缀是否匹配
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.as_bytes().ct_eq(admin_token.as_bytes()).into())
+        .unwrap_or(false)
+}
+
 fn is_allow_ip_from_header(req: &ServiceRequest, header_name: &str) -> bool {
     let header_value = match req.headers().get(header_name) {
         Some(header) => header.to_str().unwrap(),
@@ -98,10 +350,7 @@ fn is_the_interval_one_day(timestamp: i64) -> bool {
 }
 
 fn get_ip(req: &ServiceRequest) -> String {
-    match req.headers().get("X-REAL-IP") {
-        Some(header) => String::from(header.to_str().unwrap()),
-        None => req.peer_addr().unwrap().ip().to_string(),
-    }
+    client_ip(req.request())
 }
 
 #[cfg(test)]
@@ -122,4 +371,26 @@ mod tests {
             .unwrap();
         assert!(!is_the_interval_one_day(next_day.timestamp()));
     }
+
+    #[test]
+    fn is_valid_request_id_should_work() {
+        assert!(is_valid_request_id("0b6a2d0e-7d7c-4a53-9c5e-1c2d3e4f5a6b"));
+        assert!(is_valid_request_id("req_1.2"));
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id("a b"));
+        assert!(!is_valid_request_id(&"a".repeat(129)));
+    }
+
+    #[test]
+    fn is_admin_token_match_should_work() {
+        let req = actix_web::test::TestRequest::default()
+            .insert_header((header::AUTHORIZATION, "Bearer secret"))
+            .to_srv_request();
+        assert!(is_admin_token_match(&req, Some("secret")));
+        assert!(!is_admin_token_match(&req, Some("other")));
+        assert!(!is_admin_token_match(&req, None));
+
+        let req = actix_web::test::TestRequest::default().to_srv_request();
+        assert!(!is_admin_token_match(&req, Some("secret")));
+    }
 }
@@ -0,0 +1,118 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::models::{
+    filebox::FileType,
+    filebox_event::{FileboxEvent, FileboxEventType},
+};
+
+/// Fileboxes created on `day` and what became of them so far.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct DailyStats {
+    pub day: NaiveDate,
+    pub created_count: i64,
+    pub text_count: i64,
+    pub file_count: i64,
+    pub total_bytes: i64,
+    pub taken_count: i64,
+    /// Unset when none of them has been taken.
+    pub median_pickup_secs: Option<f64>,
+    pub expired_count: i64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl DailyStats {
+    pub fn pickup_ratio(&self) -> f64 {
+        if self.created_count == 0 {
+            return 0.0;
+        }
+        self.taken_count as f64 / self.created_count as f64
+    }
+}
+
+/// The stats of every day since `since` rebuilt from the audit log, oldest
+/// first, as the Postgres aggregation computes them. For the stores without
+/// `PERCENTILE_CONT`, `events` has to hold the created, taken and expired
+/// events of the boxes created since `since`.
+pub fn aggregate_events(
+    events: &[FileboxEvent],
+    since: NaiveDate,
+    updated_at: DateTime<Utc>,
+) -> Vec<DailyStats> {
+    let mut taken_at: HashMap<i64, DateTime<Utc>> = HashMap::new();
+    let mut expired = HashSet::new();
+    for event in events {
+        let Some(filebox_id) = event.filebox_id else {
+            continue;
+        };
+        match event.event_type {
+            FileboxEventType::Taken => {
+                let at = taken_at.entry(filebox_id).or_insert(event.created_at);
+                *at = (*at).min(event.created_at);
+            }
+            FileboxEventType::Expired => {
+                expired.insert(filebox_id);
+            }
+            _ => {}
+        }
+    }
+
+    let since = since.and_hms_opt(0, 0, 0).unwrap().and_utc();
+    let mut days: BTreeMap<NaiveDate, (DailyStats, Vec<f64>)> = BTreeMap::new();
+    for event in events
+        .iter()
+        .filter(|e| e.event_type == FileboxEventType::Created && e.created_at >= since)
+    {
+        let day = event.created_at.date_naive();
+        let (stats, pickup_secs) = days.entry(day).or_insert_with(|| {
+            let stats = DailyStats {
+                day,
+                created_count: 0,
+                text_count: 0,
+                file_count: 0,
+                total_bytes: 0,
+                taken_count: 0,
+                median_pickup_secs: None,
+                expired_count: 0,
+                updated_at,
+            };
+            (stats, vec![])
+        });
+        stats.created_count += 1;
+        match event.file_type {
+            Some(FileType::Text) => stats.text_count += 1,
+            Some(FileType::File) => stats.file_count += 1,
+            None => {}
+        }
+        stats.total_bytes += event.size;
+        if let Some(at) = event.filebox_id.and_then(|id| taken_at.get(&id)) {
+            stats.taken_count += 1;
+            pickup_secs.push((*at - event.created_at).num_milliseconds() as f64 / 1000.0);
+        }
+        if event.filebox_id.is_some_and(|id| expired.contains(&id)) {
+            stats.expired_count += 1;
+        }
+    }
+
+    days.into_values()
+        .map(|(mut stats, pickup_secs)| {
+            stats.median_pickup_secs = median(pickup_secs);
+            stats
+        })
+        .collect()
+}
+
+/// Interpolated between the two middle values, as `PERCENTILE_CONT(0.5)`.
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        Some((values[mid - 1] + values[mid]) / 2.0)
+    } else {
+        Some(values[mid])
+    }
+}
@@ -1,4 +1,6 @@
-use sqlx::types::chrono::NaiveDateTime;
+use chrono::{DateTime, Utc};
+
+use crate::errors::Error;
 
 #[derive(Debug, Clone, Default)]
 pub struct AddFilebox {
@@ -8,13 +10,13 @@ pub struct AddFilebox {
     pub file_type: FileType,
     pub text: String,
     pub file_path: String,
-    pub created_at: NaiveDateTime,
-    pub expired_at: NaiveDateTime,
+    pub created_at: DateTime<Utc>,
+    pub expired_at: DateTime<Utc>,
 }
 
 pub struct UpdateFilebox {
     pub code: String,
-    pub used_at: NaiveDateTime,
+    pub used_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -26,15 +28,77 @@ pub struct Filebox {
     pub file_type: FileType,
     pub text: String,
     pub file_path: String,
-    pub created_at: NaiveDateTime,
-    pub expired_at: NaiveDateTime,
-    pub used_at: Option<NaiveDateTime>,
+    pub created_at: DateTime<Utc>,
+    pub expired_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
 }
 
 impl Filebox {
     pub fn has_taken(&self) -> bool {
         self.used_at.is_some()
     }
+
+    /// Bytes of the file, or of the text for a `Text` box.
+    pub fn content_size(&self) -> i64 {
+        match self.file_type {
+            FileType::File => self.size,
+            FileType::Text => self.text.len() as i64,
+        }
+    }
+
+    /// Where the file of a `File` box is stored under `upload_path`.
+    pub fn blob_path(&self, upload_path: &str) -> Option<String> {
+        match self.file_type {
+            FileType::File => Some(format!("{}/{}", upload_path, self.file_path)),
+            FileType::Text => None,
+        }
+    }
+}
+
+/// How taking a code ended, decided by the statement that marks the filebox
+/// as taken so a concurrent take can not change the answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TakeOutcome {
+    Taken(Filebox),
+    NotFound,
+    AlreadyTaken(Filebox),
+    Expired(Filebox),
+}
+
+impl TakeOutcome {
+    /// The outcome of the filebox returned by the take, `took` when it was
+    /// this take that set `used_at`.
+    pub fn new(filebox: Option<Filebox>, took: bool) -> Self {
+        match filebox {
+            None => TakeOutcome::NotFound,
+            Some(filebox) if took => TakeOutcome::Taken(filebox),
+            Some(filebox) if filebox.has_taken() => TakeOutcome::AlreadyTaken(filebox),
+            Some(filebox) => TakeOutcome::Expired(filebox),
+        }
+    }
+
+    pub fn into_result(self) -> Result<Filebox, Error> {
+        match self {
+            TakeOutcome::Taken(filebox) => Ok(filebox),
+            TakeOutcome::NotFound => Err(Error::NotFound),
+            TakeOutcome::AlreadyTaken(filebox) => {
+                Err(Error::AlreadyTaken(filebox.used_at.unwrap_or_default()))
+            }
+            TakeOutcome::Expired(filebox) => Err(Error::Expired(filebox.expired_at)),
+        }
+    }
+}
+
+/// Conditions of the admin filebox listing, unset fields match every row.
+#[derive(Debug, Clone, Default)]
+pub struct FileboxFilter {
+    pub keyword: Option<String>,
+    pub file_type: Option<FileType>,
+    pub created_from: Option<DateTime<Utc>>,
+    pub created_to: Option<DateTime<Utc>>,
+    pub taken: Option<bool>,
+    pub min_size: Option<i64>,
+    pub max_size: Option<i64>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type, Default)]
@@ -0,0 +1,111 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+
+use super::filebox::{FileType, Filebox};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "filebox_event_type", rename_all = "snake_case")]
+pub enum FileboxEventType {
+    Created,
+    Viewed,
+    Taken,
+    Expired,
+    Revoked,
+    LookupFailed,
+}
+
+impl From<FileboxEventType> for String {
+    fn from(value: FileboxEventType) -> Self {
+        match value {
+            FileboxEventType::Created => "created".to_string(),
+            FileboxEventType::Viewed => "viewed".to_string(),
+            FileboxEventType::Taken => "taken".to_string(),
+            FileboxEventType::Expired => "expired".to_string(),
+            FileboxEventType::Revoked => "revoked".to_string(),
+            FileboxEventType::LookupFailed => "lookup_failed".to_string(),
+        }
+    }
+}
+
+impl FromStr for FileboxEventType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created" => Ok(FileboxEventType::Created),
+            "viewed" => Ok(FileboxEventType::Viewed),
+            "taken" => Ok(FileboxEventType::Taken),
+            "expired" => Ok(FileboxEventType::Expired),
+            "revoked" => Ok(FileboxEventType::Revoked),
+            "lookup_failed" => Ok(FileboxEventType::LookupFailed),
+            _ => Err(format!("unknown event type {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AddFileboxEvent {
+    pub event_type: FileboxEventType,
+    /// Unset for failed lookups, there is no filebox behind the code.
+    pub filebox_id: Option<i64>,
+    pub code: String,
+    pub file_type: Option<FileType>,
+    /// See `Filebox::content_size`.
+    pub size: i64,
+    pub ip: String,
+    pub user_agent: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AddFileboxEvent {
+    /// An event of `filebox` happening now, the client is left empty.
+    pub fn new(event_type: FileboxEventType, filebox: &Filebox) -> Self {
+        Self {
+            event_type,
+            filebox_id: Some(filebox.id),
+            code: filebox.code.clone(),
+            file_type: Some(filebox.file_type),
+            size: filebox.content_size(),
+            ip: String::new(),
+            user_agent: String::new(),
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn lookup_failed(code: String) -> Self {
+        Self {
+            event_type: FileboxEventType::LookupFailed,
+            filebox_id: None,
+            code,
+            file_type: None,
+            size: 0,
+            ip: String::new(),
+            user_agent: String::new(),
+            created_at: Utc::now(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct FileboxEvent {
+    pub id: i64,
+    pub event_type: FileboxEventType,
+    pub filebox_id: Option<i64>,
+    pub code: String,
+    pub file_type: Option<FileType>,
+    pub size: i64,
+    pub ip: String,
+    pub user_agent: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Conditions of the audit log query, unset fields match every row.
+#[derive(Debug, Clone, Default)]
+pub struct FileboxEventFilter {
+    pub code: Option<String>,
+    pub ip: Option<String>,
+    pub event_type: Option<FileboxEventType>,
+    pub created_from: Option<DateTime<Utc>>,
+    pub created_to: Option<DateTime<Utc>>,
+}
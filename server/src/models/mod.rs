@@ -1 +1,3 @@
+pub mod daily_stats;
 pub mod filebox;
+pub mod filebox_event;
@@ -0,0 +1,38 @@
+use utoipa::OpenApi;
+
+use crate::{
+    api::{
+        CreateFileboxRequest, CreateFileboxResponse, CreateTextFileboxRequest, ErrorResponse,
+        FileboxFileType, GetFileboxResponse, HealthCheckResponse,
+    },
+    handlers::{filebox, general},
+};
+
+/// The public api, served on `/openapi.json`. The committed `openapi.json`
+/// snapshot has to be updated along with it, see `tests::openapi`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Filebox", description = "Share texts and files by a short code"),
+    paths(
+        filebox::add_new_filebox,
+        filebox::add_new_text_filebox,
+        filebox::add_new_raw_filebox,
+        filebox::get_filebox_by_code,
+        filebox::take_filebox_by_code,
+        general::health_check_handler,
+    ),
+    components(schemas(
+        CreateFileboxRequest,
+        CreateFileboxResponse,
+        CreateTextFileboxRequest,
+        GetFileboxResponse,
+        FileboxFileType,
+        ErrorResponse,
+        HealthCheckResponse,
+    )),
+    tags(
+        (name = "filebox", description = "Create, look up and take fileboxes"),
+        (name = "general"),
+    )
+)]
+pub struct ApiDoc;
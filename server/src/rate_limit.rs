@@ -0,0 +1,270 @@
+use std::{
+    fmt,
+    future::ready,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use actix_extensible_rate_limit::backend::{
+    memory::InMemoryBackend, Backend, SimpleInput, SimpleInputFuture, SimpleOutput,
+};
+use actix_web::{dev::ServiceRequest, rt::time::Instant};
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::{
+    api::client_ip,
+    data::limiter::{IpLimitStore, IpLimiter},
+    errors,
+};
+
+/// Where the global rate limit counters are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitBackendKind {
+    /// Per worker counters, limits are multiplied by the number of workers and replicas.
+    #[default]
+    Memory,
+    /// Shared counters in redis, limits hold across workers and replicas.
+    Redis,
+}
+
+impl FromStr for RateLimitBackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "memory" => Ok(RateLimitBackendKind::Memory),
+            "redis" => Ok(RateLimitBackendKind::Redis),
+            _ => Err(format!("expected memory or redis but got {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitRule {
+    pub path: String,
+    pub window: Duration,
+    pub max_requests: u64,
+}
+
+impl fmt::Display for RateLimitRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}={}/{}",
+            self.path,
+            self.max_requests,
+            self.window.as_secs()
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    pub window: Duration,
+    pub max_requests: u64,
+    pub routes: Vec<RateLimitRule>,
+}
+
+impl RateLimitConfig {
+    pub fn new(window: Duration, max_requests: u64, routes: Vec<RateLimitRule>) -> Self {
+        Self {
+            window,
+            max_requests,
+            routes,
+        }
+    }
+
+    /// The override with the longest path prefix matching the request path,
+    /// the global limit is keyed by the empty path.
+    pub fn rule_for(&self, path: &str) -> (&str, Duration, u64) {
+        self.routes
+            .iter()
+            .filter(|rule| path.starts_with(&rule.path))
+            .max_by_key(|rule| rule.path.len())
+            .map(|rule| (rule.path.as_str(), rule.window, rule.max_requests))
+            .unwrap_or(("", self.window, self.max_requests))
+    }
+}
+
+/// Parse route overrides written as `path=max/window_sec`, separated by commas,
+/// e.g. `/v1/filebox=10/60,/admin=30/60`.
+pub fn parse_rate_limit_routes(value: &str) -> Result<Vec<RateLimitRule>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let invalid = || format!("expected path=max/window_sec but got {item}");
+            let (path, limit) = item.split_once('=').ok_or_else(invalid)?;
+            let (max_requests, window) = limit.split_once('/').ok_or_else(invalid)?;
+            let max_requests: u64 = max_requests.trim().parse().map_err(|_| invalid())?;
+            let window: u64 = window.trim().parse().map_err(|_| invalid())?;
+            if window == 0 {
+                return Err(invalid());
+            }
+
+            Ok(RateLimitRule {
+                path: path.trim().to_string(),
+                window: Duration::from_secs(window),
+                max_requests,
+            })
+        })
+        .collect()
+}
+
+/// Build the rate limiter input, keyed by the client real ip and the matched route.
+pub fn rate_limit_input(
+    config: Arc<RateLimitConfig>,
+) -> impl Fn(&ServiceRequest) -> SimpleInputFuture + 'static {
+    move |req| {
+        let (path, window, max_requests) = config.rule_for(req.path());
+        let ip = client_ip(req.request());
+
+        ready(Ok(SimpleInput {
+            interval: window,
+            max_requests,
+            key: format!("{ip}-{path}"),
+        }))
+    }
+}
+
+#[derive(Clone)]
+pub enum RateLimitBackend {
+    Memory(InMemoryBackend),
+    Store(Arc<IpLimiter>),
+}
+
+impl RateLimitBackend {
+    pub fn new(kind: RateLimitBackendKind, ip_limiter: Arc<IpLimiter>) -> Self {
+        match kind {
+            RateLimitBackendKind::Memory => {
+                RateLimitBackend::Memory(InMemoryBackend::builder().build())
+            }
+            RateLimitBackendKind::Redis => RateLimitBackend::Store(ip_limiter),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Backend<SimpleInput> for RateLimitBackend {
+    type Output = SimpleOutput;
+    type RollbackToken = String;
+    type Error = errors::Error;
+
+    async fn request(
+        &self,
+        input: SimpleInput,
+    ) -> Result<(bool, Self::Output, Self::RollbackToken), Self::Error> {
+        match self {
+            RateLimitBackend::Memory(backend) => match backend.request(input).await {
+                Ok(res) => Ok(res),
+                Err(never) => match never {},
+            },
+            RateLimitBackend::Store(store) => {
+                // fixed window aligned to the epoch so every replica agrees on it
+                let window = input.interval.as_secs().max(1);
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs();
+                let key = format!("filebox:rate_limit:{}:{}", input.key, now / window);
+                let count = store.incr_counter(&key, window as i64).await?;
+                let count = count.max(0) as u64;
+
+                let output = SimpleOutput {
+                    limit: input.max_requests,
+                    remaining: input.max_requests.saturating_sub(count),
+                    reset: Instant::now() + Duration::from_secs(window - now % window),
+                };
+                Ok((count <= input.max_requests, output, key))
+            }
+        }
+    }
+
+    async fn rollback(&self, token: Self::RollbackToken) -> Result<(), Self::Error> {
+        match self {
+            RateLimitBackend::Memory(backend) => match backend.rollback(token).await {
+                Ok(()) => Ok(()),
+                Err(never) => match never {},
+            },
+            // the limiter is built without a rollback condition
+            RateLimitBackend::Store(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_redis::RedisActor;
+
+    use super::*;
+    use crate::{data::limiter::LimiterFailurePolicy, metrics::Metrics};
+
+    #[test]
+    fn parse_rate_limit_routes_should_work() {
+        let routes = parse_rate_limit_routes("/v1/filebox=10/60, /admin=30/1").unwrap();
+        assert_eq!(
+            routes,
+            vec![
+                RateLimitRule {
+                    path: "/v1/filebox".to_string(),
+                    window: Duration::from_secs(60),
+                    max_requests: 10,
+                },
+                RateLimitRule {
+                    path: "/admin".to_string(),
+                    window: Duration::from_secs(1),
+                    max_requests: 30,
+                },
+            ]
+        );
+        assert!(parse_rate_limit_routes("").unwrap().is_empty());
+        assert!(parse_rate_limit_routes("/v1/filebox=10").is_err());
+        assert!(parse_rate_limit_routes("/v1/filebox=10/0").is_err());
+    }
+
+    #[test]
+    fn rule_for_should_use_longest_prefix() {
+        let routes = parse_rate_limit_routes("/v1=20/60,/v1/filebox=10/60").unwrap();
+        let config = RateLimitConfig::new(Duration::from_secs(1), 60, routes);
+
+        assert_eq!(config.rule_for("/health"), ("", Duration::from_secs(1), 60));
+        assert_eq!(
+            config.rule_for("/v1/filebox/abcde"),
+            ("/v1/filebox", Duration::from_secs(60), 10)
+        );
+        assert_eq!(
+            config.rule_for("/v1/other"),
+            ("/v1", Duration::from_secs(60), 20)
+        );
+    }
+
+    #[actix_rt::test]
+    async fn store_backend_should_limit() {
+        // redis is unreachable, the limiter counts in its memory fallback
+        let redis = Arc::new(RedisActor::start("127.0.0.1:1"));
+        let ip_limiter = Arc::new(IpLimiter::new(
+            Some(redis),
+            LimiterFailurePolicy::FailOpen,
+            Arc::new(Metrics::new()),
+        ));
+        let backend = RateLimitBackend::new(RateLimitBackendKind::Redis, ip_limiter);
+
+        let input = SimpleInput {
+            interval: Duration::from_secs(3600),
+            max_requests: 2,
+            key: "127.0.0.1-".to_string(),
+        };
+        let (allowed, output, _) = backend.request(input.clone()).await.unwrap();
+        assert!(allowed);
+        assert_eq!(output.remaining, 1);
+        let (allowed, _, _) = backend.request(input.clone()).await.unwrap();
+        assert!(allowed);
+        let (allowed, output, _) = backend.request(input).await.unwrap();
+        assert!(!allowed);
+        assert_eq!(output.remaining, 0);
+    }
+}
@@ -1,29 +1,122 @@
-use chrono::Utc;
-use sqlx::PgPool;
-use std::fs;
+use chrono::{Duration, Utc};
+use std::{fs, path::PathBuf, sync::Arc};
 use tokio_schedule::{every, Job};
+use tracing::Instrument;
+use uuid::Uuid;
 
-use crate::{data::postgres::delete_expired_filebox_db, models::filebox::FileType};
+use crate::{
+    data::{memory::MemoryIpLimitStore, repository::FileboxRepository},
+    metrics::Metrics,
+    models::filebox_event::{AddFileboxEvent, FileboxEventType},
+};
 
-pub async fn start_clean_expired_filebox(pool: &PgPool, upload_path: String) {
+pub async fn start_clean_expired_filebox(
+    repo: Arc<dyn FileboxRepository>,
+    upload_path: String,
+    metrics: &Metrics,
+) {
     every(1)
         .hours()
         .in_timezone(&Utc)
-        .perform(|| async {
-            log::info!("start_clean_expired_filebox event - start");
-            match delete_expired_filebox_db(pool).await {
-                Ok(filebox_vec) => {
-                    // clean expired path
-                    for filebox in &filebox_vec {
-                        if filebox.file_type == FileType::File {
-                            let file_path = format!("{}/{}", upload_path, filebox.file_path);
-                            let _ = fs::remove_file(file_path);
-                        }
+        .perform(|| {
+            async {
+                tracing::info!("start_clean_expired_filebox event - start");
+                let timer = metrics.scheduler_run_duration.start_timer();
+                let filebox_vec = match repo.delete_expired_fileboxes().await {
+                    Ok(filebox_vec) => filebox_vec,
+                    Err(err) => {
+                        tracing::error!("start_clean_expired_filebox event - failed {:?}", err);
+                        vec![]
+                    }
+                };
+                metrics
+                    .deleted_fileboxes
+                    .with_label_values(&["cleanup"])
+                    .inc_by(filebox_vec.len() as u64);
+                // clean expired path
+                for filebox in &filebox_vec {
+                    if let Some(file_path) = filebox.blob_path(&upload_path) {
+                        let _ = fs::remove_file(file_path);
                     }
                 }
-                Err(err) => log::error!("start_clean_expired_filebox event - failed {:?}", err),
+
+                // taken fileboxes are purged as well, only the untaken ones expired
+                let expired: Vec<AddFileboxEvent> = filebox_vec
+                    .iter()
+                    .filter(|filebox| !filebox.has_taken())
+                    .map(|filebox| AddFileboxEvent::new(FileboxEventType::Expired, filebox))
+                    .collect();
+                if let Err(err) = repo.add_filebox_events(&expired).await {
+                    tracing::error!("record expired events failed: {err:?}");
+                }
+                timer.observe_duration();
+                tracing::info!("start_clean_expired_filebox event - end");
+            }
+            .instrument(tracing::info_span!("clean_expired_filebox", run_id = %Uuid::new_v4()))
+        })
+        .await;
+}
+
+/// Refresh the usage gauges from the database every minute, so a scrape of
+/// `/metrics` never waits on it.
+pub async fn start_refresh_usage_metrics(repo: Arc<dyn FileboxRepository>, metrics: Arc<Metrics>) {
+    refresh_usage_metrics(repo.as_ref(), &metrics).await;
+    every(1)
+        .minutes()
+        .perform(|| refresh_usage_metrics(repo.as_ref(), &metrics))
+        .await;
+}
+
+pub async fn refresh_usage_metrics(repo: &dyn FileboxRepository, metrics: &Metrics) {
+    match metrics
+        .time_db("filebox_usage", repo.get_filebox_usage())
+        .await
+    {
+        Ok((active_fileboxes, storage_used_bytes)) => {
+            metrics.active_fileboxes.set(active_fileboxes);
+            metrics.storage_used_bytes.set(storage_used_bytes);
+        }
+        Err(err) => tracing::error!("refresh usage metrics failed: {err:?}"),
+    }
+}
+
+/// Roll the audit log into `daily_stats`, rebuilding the last `lookback_days`
+/// since their boxes may still be taken or expire.
+pub async fn start_aggregate_daily_stats(repo: Arc<dyn FileboxRepository>, lookback_days: i64) {
+    every(1)
+        .hours()
+        .in_timezone(&Utc)
+        .perform(|| {
+            async {
+                tracing::info!("start_aggregate_daily_stats event - start");
+                let since = Utc::now().date_naive() - Duration::days(lookback_days);
+                match repo.aggregate_daily_stats(since).await {
+                    Ok(days) => tracing::info!("start_aggregate_daily_stats event - {days} days"),
+                    Err(err) => {
+                        tracing::error!("start_aggregate_daily_stats event - failed {:?}", err)
+                    }
+                }
+                tracing::info!("start_aggregate_daily_stats event - end");
+            }
+            .instrument(tracing::info_span!("aggregate_daily_stats", run_id = %Uuid::new_v4()))
+        })
+        .await;
+}
+
+/// Evict the expired entries of the embedded ip limit store, then save it to
+/// `path` if set, so the limits of the day survive a restart of a server
+/// running without redis.
+pub async fn start_snapshot_ip_limits(store: Arc<MemoryIpLimitStore>, path: Option<PathBuf>) {
+    every(1)
+        .minutes()
+        .perform(|| async {
+            store.evict_expired();
+            let Some(path) = &path else {
+                return;
+            };
+            if let Err(err) = store.save_snapshot(path) {
+                tracing::error!("save ip limit snapshot to {path:?} failed: {err}");
             }
-            log::info!("start_clean_expired_filebox event - end");
         })
         .await;
 }
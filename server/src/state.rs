@@ -1,22 +1,134 @@
-use sqlx::postgres::PgPool;
+use arc_swap::ArcSwap;
+use ipnet::IpNet;
 use std::{cell::RefCell, sync::Arc};
 use tiny_id::ShortCodeGenerator;
 
-use crate::{api::RedisActorAddr, data::redis::IpAllower};
+use crate::{
+    api::{IpRuleKind, RedisActorAddr, UploadLimits},
+    config::{CliArgs, Config},
+    data::{
+        limiter::{IpLimiter, LookupGuard},
+        redis::{load_ip_rules, IpAllower, IpRules},
+        repository::FileboxRepository,
+    },
+    errors::Error,
+    metrics::Metrics,
+};
 
 #[derive(Debug)]
 pub struct AppState {
     pub health_check_response: String,
     pub visit_count: std::sync::Mutex<u64>,
     pub upload_path: String,
-    pub db: PgPool,
+    pub storage_min_free_bytes: u64,
+    pub repo: Arc<dyn FileboxRepository>,
 
     // 由于会 标准库中的 Mutex 在 .await中 会: this `MutexGuard` is held across an `await` point
     // 所以改用 tokio 的 Mutex
     pub code_gen: tokio::sync::Mutex<RefCell<ShortCodeGenerator<char>>>,
+
+    // 未配置时 /admin 下的接口一律返回 401
+    pub admin_token: Option<String>,
+
+    // 仅信任来自这些地址的 X-REAL-IP, 见 api::client_ip
+    pub trusted_proxies: Vec<IpNet>,
+
+    // 可热加载, 见 reload_state
+    pub upload_limits: ArcSwap<UploadLimits>,
+
+    pub metrics: Arc<Metrics>,
 }
 
 pub struct CacheState {
-    pub ip_allower: Arc<IpAllower>,
-    pub redis_actor: Arc<RedisActorAddr>,
+    pub ip_allower: ArcSwap<IpAllower>,
+    pub ip_rules: ArcSwap<IpRules>,
+    /// Held by the reload and the /admin rule changes from reading the
+    /// persisted rules until the swap, so none of them undoes another.
+    pub ip_rules_lock: tokio::sync::Mutex<()>,
+    pub ip_limiter: Arc<IpLimiter>,
+    pub lookup_guard: Arc<LookupGuard>,
+    /// Unset when redis is not configured, the ip limits then stay in the
+    /// process and the ip rules added through /admin are kept by the repo.
+    pub redis_actor: Option<Arc<RedisActorAddr>>,
+}
+
+impl From<&Config> for UploadLimits {
+    fn from(config: &Config) -> Self {
+        Self {
+            max_upload_size: config.max_upload_size,
+            min_duration_day: config.min_duration_day,
+            max_duration_day: config.max_duration_day,
+        }
+    }
+}
+
+impl From<&Config> for IpAllower {
+    fn from(config: &Config) -> Self {
+        IpAllower::new(
+            config.ip_visit_error_limit,
+            config.ip_upload_limit,
+            config.ip_visit_error_duration_day,
+        )
+    }
+}
+
+/// Merge the ip rules added through /admin into `ip_rules`, from redis when
+/// it is configured and from the repository otherwise.
+pub async fn load_added_ip_rules(
+    repo: &dyn FileboxRepository,
+    redis_actor: Option<&RedisActorAddr>,
+    ip_rules: &mut IpRules,
+) -> Result<(), Error> {
+    if let Some(redis_actor) = redis_actor {
+        return load_ip_rules(redis_actor, ip_rules).await;
+    }
+
+    for (kind, net) in repo.list_ip_rules().await? {
+        ip_rules.add(kind, net);
+    }
+    Ok(())
+}
+
+/// Load the configuration again and swap in the reloadable part: ip limits,
+/// allow/deny lists, max upload size and expiry bounds. Everything else needs
+/// a restart. On error the running state is left untouched.
+pub async fn reload_state(
+    cli: &CliArgs,
+    app_state: &AppState,
+    cache_state: &CacheState,
+) -> Result<(), Error> {
+    let config = Config::load(cli).map_err(|err| Error::InvalidConfig(err.to_string()))?;
+
+    let _guard = cache_state.ip_rules_lock.lock().await;
+    let mut ip_rules = IpRules::new(config.ip_allow_list.clone(), config.ip_deny_list.clone());
+    let loaded = load_added_ip_rules(
+        app_state.repo.as_ref(),
+        cache_state.redis_actor.as_deref(),
+        &mut ip_rules,
+    )
+    .await;
+    if let Err(err) = &loaded {
+        tracing::warn!("load the added ip rules failed, keep the running ones: {err}");
+    }
+    // 读取失败时保留运行中已添加的规则, 不让重新加载失败;
+    // 未配置 redis 时规则只由本进程修改, 运行中的就是最新的
+    if loaded.is_err() || cache_state.redis_actor.is_none() {
+        let running = cache_state.ip_rules.load();
+        for kind in IpRuleKind::ALL {
+            for net in running.list_added(kind) {
+                ip_rules.add(kind, net);
+            }
+        }
+    }
+
+    cache_state.ip_rules.store(Arc::new(ip_rules));
+    cache_state
+        .ip_allower
+        .store(Arc::new(IpAllower::from(&config)));
+    app_state
+        .upload_limits
+        .store(Arc::new(UploadLimits::from(&config)));
+
+    tracing::info!("configuration reloaded");
+    Ok(())
 }
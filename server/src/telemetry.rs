@@ -0,0 +1,47 @@
+use std::str::FromStr;
+
+use serde::Serialize;
+use tracing_subscriber::EnvFilter;
+
+/// How log lines are written to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One human readable line per event.
+    #[default]
+    Text,
+    /// One json object per event, with the fields of the enclosing spans.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("expected text or json but got {s}")),
+        }
+    }
+}
+
+/// Install the global tracing subscriber, the level is read from `RUST_LOG`
+/// and defaults to info. Records of the `log` crate, as written by actix and
+/// sqlx, are forwarded to it.
+pub fn init_tracing(format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_file(true)
+        .with_line_number(true);
+
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .init(),
+    }
+}
@@ -1,21 +1,45 @@
-use std::{cell::RefCell, path::Path};
+use std::{cell::RefCell, path::Path, sync::Arc, time::Duration};
 
 use actix_web::{
     dev::{Service, ServiceResponse},
-    test, web, App,
+    test, web, App, HttpServer,
 };
-use sqlx::PgPool;
+use actix_web_lab::middleware::from_fn;
+use arc_swap::ArcSwap;
+use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
 use sqlx_db_tester::TestPg;
 use tiny_id::ShortCodeGenerator;
 
 use crate::{
+    api::UploadLimits,
+    config::CliArgs,
+    data::{
+        limiter::{IpLimiter, LimiterFailurePolicy, LookupGuard},
+        redis::{parse_ip_net_list, IpAllower, IpRules},
+        repository::FileboxRepository,
+    },
+    errors::configure_extractors,
     handlers::{
-        filebox::{add_new_filebox, get_filebox_by_code, take_filebox_by_code},
-        general::health_check_handler,
+        admin::{add_ip_rule, delete_ip_rule_by_cidr, list_ip_rules, reload_config},
+        filebox::{
+            add_new_filebox, add_new_raw_filebox, add_new_text_filebox, get_filebox_by_code,
+            take_filebox_by_code,
+        },
+        general::{
+            health_check_handler, liveness_handler, metrics_handler, openapi_handler,
+            readiness_handler,
+        },
+    },
+    metrics::Metrics,
+    middlewares::{
+        admin_token_mw, ip_upload_limit_of_day_mw, ip_visit_error_limit_of_day_mw,
+        localize_error_mw, request_id_mw,
     },
-    state::AppState,
+    state::{AppState, CacheState},
 };
 
+pub const TEST_ADMIN_TOKEN: &str = "test-admin-token";
+
 // private none test functions
 pub fn get_tdb() -> TestPg {
     dotenvy::from_filename(".env.test").ok();
@@ -24,29 +48,71 @@ pub fn get_tdb() -> TestPg {
     TestPg::new(server_url, migrations)
 }
 
+/// A fresh in-memory SQLite database with the migrations applied. Every
+/// connection would open its own database, so the pool keeps a single one.
+pub async fn get_sqlite_pool() -> SqlitePool {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!("./migrations_sqlite")
+        .run(&pool)
+        .await
+        .unwrap();
+    pool
+}
+
 pub async fn create_test_app(
-    db_pool: &PgPool,
+    repo: Arc<dyn FileboxRepository>,
 ) -> impl Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error> {
-    let length: usize = 5;
+    create_test_app_with_upload_limits(repo, UploadLimits::default()).await
+}
 
-    let generator = ShortCodeGenerator::new_lowercase_alphanumeric(length);
+/// The test app as if `upload_limits` had been reloaded.
+pub async fn create_test_app_with_upload_limits(
+    repo: Arc<dyn FileboxRepository>,
+    upload_limits: UploadLimits,
+) -> impl Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error> {
+    let shared_data = create_test_state(repo);
+    shared_data.upload_limits.store(Arc::new(upload_limits));
+    test::init_service(
+        App::new()
+            .app_data(shared_data)
+            .wrap(from_fn(localize_error_mw))
+            .wrap(from_fn(request_id_mw))
+            .configure(test_routes),
+    )
+    .await
+}
 
-    let shared_data = web::Data::new(AppState {
-        health_check_response: "I'm OK.".to_string(),
-        visit_count: std::sync::Mutex::new(0),
-        upload_path: "./todo".to_string(),
-        db: db_pool.clone(),
-        code_gen: tokio::sync::Mutex::new(RefCell::new(generator)),
-    });
+/// The test app with the ip limit middlewares in front of `/v1/filebox`, as
+/// the server mounts them, counting in the process.
+pub async fn create_limited_test_app(
+    repo: Arc<dyn FileboxRepository>,
+    cache_state: web::Data<CacheState>,
+) -> impl Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error> {
+    let shared_data = create_test_state(repo);
     test::init_service(
         App::new()
-            .app_data(shared_data.clone())
-            .route("/health", web::get().to(health_check_handler))
+            .app_data(shared_data)
+            .app_data(cache_state)
+            .configure(configure_extractors)
+            .wrap(from_fn(localize_error_mw))
+            .wrap(from_fn(request_id_mw))
             .service(
-                web::scope("/v1")
-                    .route("/filebox", web::post().to(add_new_filebox))
+                web::scope("/v1/filebox")
+                    .wrap(from_fn(ip_visit_error_limit_of_day_mw))
+                    .route(
+                        "/text",
+                        web::post()
+                            .to(add_new_text_filebox)
+                            .wrap(from_fn(ip_upload_limit_of_day_mw)),
+                    )
                     .service(
-                        web::resource("/filebox/{code}")
+                        web::resource("/{code}")
                             .route(web::get().to(get_filebox_by_code))
                             .route(web::post().to(take_filebox_by_code)),
                     ),
@@ -54,3 +120,124 @@ pub async fn create_test_app(
     )
     .await
 }
+
+/// The test app with the `/admin` ip rule and reload routes, the reload
+/// reading the configuration of `cli`.
+pub async fn create_admin_test_app(
+    repo: Arc<dyn FileboxRepository>,
+    cache_state: web::Data<CacheState>,
+    cli: CliArgs,
+) -> impl Service<actix_http::Request, Response = ServiceResponse, Error = actix_web::Error> {
+    let shared_data = create_test_state(repo);
+    test::init_service(
+        App::new()
+            .app_data(shared_data)
+            .app_data(cache_state)
+            .app_data(web::Data::new(cli))
+            .configure(configure_extractors)
+            .wrap(from_fn(localize_error_mw))
+            .wrap(from_fn(request_id_mw))
+            .service(
+                web::scope("/admin")
+                    .wrap(from_fn(admin_token_mw))
+                    .service(
+                        web::resource("/ip-rules")
+                            .route(web::get().to(list_ip_rules))
+                            .route(web::post().to(add_ip_rule))
+                            .route(web::delete().to(delete_ip_rule_by_cidr)),
+                    )
+                    .route("/reload", web::post().to(reload_config)),
+            ),
+    )
+    .await
+}
+
+/// Ip limits without redis, `visit_error_limit` wrong codes and 5 uploads
+/// per ip and day.
+pub fn create_test_cache_state(ip_rules: IpRules, visit_error_limit: i32) -> web::Data<CacheState> {
+    let metrics = Arc::new(Metrics::new());
+    web::Data::new(CacheState {
+        ip_allower: ArcSwap::from_pointee(IpAllower::new(visit_error_limit, 5, 1)),
+        ip_rules: ArcSwap::from_pointee(ip_rules),
+        ip_rules_lock: tokio::sync::Mutex::new(()),
+        ip_limiter: Arc::new(IpLimiter::new(
+            None,
+            LimiterFailurePolicy::FailOpen,
+            metrics,
+        )),
+        lookup_guard: Arc::new(LookupGuard::new(
+            100,
+            Duration::from_millis(100),
+            Duration::from_secs(1),
+        )),
+        redis_actor: None,
+    })
+}
+
+/// Serve the test app on a random local port and return its base url, for
+/// tests of real http clients.
+pub fn spawn_test_server(repo: Arc<dyn FileboxRepository>) -> String {
+    let shared_data = create_test_state(repo);
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(shared_data.clone())
+            .wrap(from_fn(localize_error_mw))
+            .wrap(from_fn(request_id_mw))
+            .configure(test_routes)
+    })
+    .workers(1)
+    .bind("127.0.0.1:0")
+    .unwrap();
+    let addr = server.addrs()[0];
+    actix_rt::spawn(server.run());
+    format!("http://{addr}")
+}
+
+fn create_test_state(repo: Arc<dyn FileboxRepository>) -> web::Data<AppState> {
+    let length: usize = 5;
+
+    let generator = ShortCodeGenerator::new_lowercase_alphanumeric(length);
+
+    let upload_path = std::env::temp_dir().join("filebox-test-uploads");
+    std::fs::create_dir_all(&upload_path).unwrap();
+
+    web::Data::new(AppState {
+        health_check_response: "I'm OK.".to_string(),
+        visit_count: std::sync::Mutex::new(0),
+        upload_path: upload_path.to_string_lossy().to_string(),
+        storage_min_free_bytes: 0,
+        repo,
+        code_gen: tokio::sync::Mutex::new(RefCell::new(generator)),
+        admin_token: Some(TEST_ADMIN_TOKEN.to_string()),
+        trusted_proxies: parse_ip_net_list("127.0.0.1,::1").unwrap(),
+        upload_limits: ArcSwap::from_pointee(UploadLimits::default()),
+        metrics: Arc::new(Metrics::new()),
+    })
+}
+
+fn test_routes(cfg: &mut web::ServiceConfig) {
+    configure_extractors(cfg);
+    cfg.route("/health", web::get().to(health_check_handler))
+        .route("/health/live", web::get().to(liveness_handler))
+        .route("/health/ready", web::get().to(readiness_handler))
+        .service(
+            web::resource("/metrics")
+                .wrap(from_fn(admin_token_mw))
+                .route(web::get().to(metrics_handler)),
+        )
+        .route("/openapi.json", web::get().to(openapi_handler))
+        .service(
+            web::scope("/v1")
+                .route("/filebox", web::post().to(add_new_filebox))
+                .route("/filebox/text", web::post().to(add_new_text_filebox))
+                .route(
+                    "/filebox/raw/{filename}",
+                    web::put().to(add_new_raw_filebox),
+                )
+                .service(
+                    web::resource("/filebox/{code}")
+                        .route(web::get().to(get_filebox_by_code))
+                        .route(web::post().to(take_filebox_by_code)),
+                ),
+        );
+}
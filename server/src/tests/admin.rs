@@ -0,0 +1,102 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{http::header, test};
+
+    use crate::{
+        api::{ErrorResponse, IpRuleKind, IpRuleRequest, IpRulesResponse},
+        config::CliArgs,
+        data::{
+            memory::MemoryFileboxRepository,
+            redis::{parse_ip_net, parse_ip_net_list, IpRules},
+            repository::FileboxRepository,
+            sqlite::SqliteFileboxRepository,
+        },
+        test_utils::{
+            create_admin_test_app, create_test_cache_state, get_sqlite_pool, TEST_ADMIN_TOKEN,
+        },
+    };
+
+    fn config_file(content: &str) -> CliArgs {
+        let path = std::env::temp_dir().join(format!("filebox-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, content).unwrap();
+        CliArgs {
+            config_file: Some(path),
+            ..Default::default()
+        }
+    }
+
+    fn admin(req: test::TestRequest) -> actix_http::Request {
+        req.insert_header((header::AUTHORIZATION, format!("Bearer {TEST_ADMIN_TOKEN}")))
+            .to_request()
+    }
+
+    #[actix_web::test]
+    async fn test_added_ip_rule_survives_reload_without_redis() {
+        let repo: Arc<dyn FileboxRepository> =
+            Arc::new(SqliteFileboxRepository::new(get_sqlite_pool().await));
+        let allow = parse_ip_net_list("10.0.0.0/8").unwrap();
+        let cache_state = create_test_cache_state(IpRules::new(allow, vec![]), 5);
+        let cli = config_file(
+            r#"
+            database_url = "sqlite::memory:"
+            ip_allow_list = ["10.0.0.0/8"]
+            "#,
+        );
+        let app = create_admin_test_app(repo.clone(), cache_state.clone(), cli).await;
+
+        let rule = IpRuleRequest {
+            kind: IpRuleKind::Deny,
+            cidr: "192.0.2.0/24".to_string(),
+        };
+        let req = test::TestRequest::post()
+            .uri("/admin/ip-rules")
+            .set_json(&rule);
+        assert_eq!(test::call_service(&app, admin(req)).await.status(), 200);
+
+        let req = test::TestRequest::post().uri("/admin/reload");
+        assert_eq!(test::call_service(&app, admin(req)).await.status(), 204);
+
+        let req = test::TestRequest::get().uri("/admin/ip-rules");
+        let rules: IpRulesResponse = test::call_and_read_body_json(&app, admin(req)).await;
+        assert_eq!(rules.allow, vec!["10.0.0.0/8"]);
+        assert_eq!(rules.deny, vec!["192.0.2.0/24"]);
+        assert!(cache_state.ip_rules.load().is_denied("192.0.2.7"));
+
+        // 未配置 redis 时保存在数据库中, 重启后仍会加载
+        let net = parse_ip_net("192.0.2.0/24").unwrap();
+        assert_eq!(
+            repo.list_ip_rules().await.unwrap(),
+            vec![(IpRuleKind::Deny, net)]
+        );
+
+        let req = test::TestRequest::delete()
+            .uri("/admin/ip-rules")
+            .set_json(&rule);
+        assert_eq!(test::call_service(&app, admin(req)).await.status(), 200);
+        assert!(repo.list_ip_rules().await.unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_delete_configured_ip_rule_is_conflict() {
+        let repo: Arc<dyn FileboxRepository> = Arc::new(MemoryFileboxRepository::new());
+        let allow = parse_ip_net_list("10.0.0.0/8").unwrap();
+        let cache_state = create_test_cache_state(IpRules::new(allow, vec![]), 5);
+        let app = create_admin_test_app(repo, cache_state.clone(), CliArgs::default()).await;
+
+        let rule = IpRuleRequest {
+            kind: IpRuleKind::Allow,
+            cidr: "10.0.0.0/8".to_string(),
+        };
+        let req = test::TestRequest::delete()
+            .uri("/admin/ip-rules")
+            .set_json(&rule);
+        let resp = test::call_service(&app, admin(req)).await;
+        assert_eq!(resp.status(), 409);
+        let err: ErrorResponse = test::read_body_json(resp).await;
+        assert_eq!(err.error, "CONFIGURED_IP_RULE");
+        assert!(err.message.contains("ip_allow_list"), "{}", err.message);
+        assert!(cache_state.ip_rules.load().is_allowed("10.1.2.3"));
+    }
+}
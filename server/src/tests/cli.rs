@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod tests {
+
+    use std::sync::Arc;
+
+    use crate::{
+        api::{FileboxFileType, DATE_FORMAT, IP_UPLOAD_LIMIT_HEADER},
+        cli::{CliClient, ClientError},
+        data::memory::MemoryFileboxRepository,
+        test_utils::spawn_test_server,
+    };
+
+    use chrono::Local;
+    use filebox_client::ErrorKind;
+
+    #[actix_web::test]
+    async fn test_cli_send_info_get() {
+        let server = spawn_test_server(Arc::new(MemoryFileboxRepository::new()));
+
+        let dir = std::env::temp_dir().join(format!("filebox-cli-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("report 1.csv");
+        std::fs::write(&source, "a,b\n1,2\n").unwrap();
+        let client = CliClient::new(&server, Some(dir.join("limits.json")));
+
+        let created = client
+            .send(source.to_str().unwrap(), None, Some(3))
+            .await
+            .unwrap();
+        assert_eq!(created.name, "report 1.csv");
+
+        let info = client.info(&created.code).await.unwrap();
+        assert_eq!(info.name, "report 1.csv");
+        assert_eq!(info.file_type, FileboxFileType::File);
+        assert_eq!(info.expired_at - info.created_at, 3 * 24 * 60 * 60);
+
+        let output = dir.join("out.csv");
+        let written = client.get(&created.code, Some(&output)).await.unwrap();
+        assert_eq!(written.as_deref(), Some(output.as_path()));
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "a,b\n1,2\n");
+
+        // 已取走的口令按服务端的 ErrorResponse 报错
+        match client.get(&created.code, Some(&output)).await {
+            Err(ClientError::Client(filebox_client::Error::Api(err))) => {
+                assert_eq!(err.kind, ErrorKind::AlreadyTaken);
+                assert_eq!(err.response.code, 409);
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+
+        // 服务端今日标记过上传上限时, 客户端直接拒绝
+        let today = Local::now().format(DATE_FORMAT).to_string();
+        std::fs::write(
+            dir.join("limits.json"),
+            format!(r#"{{"{IP_UPLOAD_LIMIT_HEADER}": "{today}"}}"#),
+        )
+        .unwrap();
+        assert!(matches!(
+            client.send(source.to_str().unwrap(), None, None).await,
+            Err(ClientError::LimitReached("upload"))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,16 +1,27 @@
 #[cfg(test)]
 mod tests {
-    use std::ops::Add;
+    use std::{ops::Add, sync::Arc};
 
     use crate::{
-        api::GetFileboxResponse,
-        data::postgres::add_new_filebox_db,
-        models::filebox::{AddFilebox, FileType},
-        test_utils::{create_test_app, get_tdb},
+        api::{
+            rfc3339, CreateFileboxResponse, CreateTextFileboxRequest, ErrorResponse,
+            FileboxFileType, GetFileboxResponse, UploadLimits, DURATION_DAY_HEADER,
+        },
+        data::{
+            memory::MemoryFileboxRepository, postgres::PgFileboxRepository,
+            repository::FileboxRepository, sqlite::SqliteFileboxRepository,
+        },
+        models::{
+            filebox::{AddFilebox, FileType},
+            filebox_event::{FileboxEventFilter, FileboxEventType},
+        },
+        test_utils::{
+            create_test_app, create_test_app_with_upload_limits, get_sqlite_pool, get_tdb,
+        },
     };
 
-    use actix_web::test;
-    use chrono::{Duration, Local};
+    use actix_web::{http::header, test};
+    use chrono::{Duration, Utc};
 
     // #[derive(Debug, Serialize)]
     // struct CreateFileboxForm {
@@ -22,10 +33,24 @@ mod tests {
 
     #[actix_web::test]
     async fn test_filebox_lifecycle() {
+        filebox_lifecycle(Arc::new(MemoryFileboxRepository::new())).await;
+    }
+
+    #[actix_web::test]
+    async fn test_filebox_lifecycle_on_postgres() {
         let tdb = get_tdb();
         let db_pool = tdb.get_pool().await;
+        filebox_lifecycle(Arc::new(PgFileboxRepository::new(db_pool))).await;
+    }
 
-        let app = create_test_app(&db_pool.clone()).await;
+    #[actix_web::test]
+    async fn test_filebox_lifecycle_on_sqlite() {
+        let pool = get_sqlite_pool().await;
+        filebox_lifecycle(Arc::new(SqliteFileboxRepository::new(pool))).await;
+    }
+
+    async fn filebox_lifecycle(repo: Arc<dyn FileboxRepository>) {
+        let app = create_test_app(repo.clone()).await;
 
         // TODO: How can i send a multipart(file) to TestRequest? #2512: https://github.com/actix/actix-web/discussions/2512
         // let req = test::TestRequest::post()
@@ -42,7 +67,7 @@ mod tests {
 
         // 曲线救国 lol
         let code = "12345".to_string();
-        let now = Local::now().naive_local();
+        let now = Utc::now();
         let filebox = AddFilebox {
             code: code.clone(),
             name: "test".to_string(),
@@ -52,7 +77,7 @@ mod tests {
             expired_at: now.add(Duration::days(7)),
             ..Default::default()
         };
-        let new_filebox = add_new_filebox_db(&db_pool, filebox.clone()).await.unwrap();
+        let new_filebox = repo.add_filebox(filebox.clone()).await.unwrap();
         assert_eq!(filebox.code, new_filebox.code);
         assert_eq!(filebox.name, new_filebox.name);
         assert_eq!(filebox.file_type, new_filebox.file_type);
@@ -74,10 +99,325 @@ mod tests {
         assert_eq!(get_filebox.name, new_filebox.name);
         assert_eq!(get_filebox.created_at, new_filebox.created_at.timestamp());
         assert_eq!(get_filebox.expired_at, new_filebox.expired_at.timestamp());
+        assert_eq!(
+            get_filebox.expired_at_rfc3339,
+            rfc3339(&new_filebox.expired_at)
+        );
+        assert!(get_filebox.created_at_rfc3339.ends_with('Z'));
+        assert!(get_filebox.used_at_rfc3339.is_none());
+
+        let unknown_req = test::TestRequest::get()
+            .uri("/v1/filebox/zzzzz")
+            .insert_header(("X-REAL-IP", "10.0.0.9"))
+            .peer_addr("127.0.0.1:40000".parse().unwrap())
+            .to_request();
+        let resp = test::call_service(&app, unknown_req).await;
+        assert_eq!(resp.status(), 404);
+
+        // 过长的口令同样记录下来
+        let long_code = "z".repeat(200);
+        let long_req = test::TestRequest::get()
+            .uri(&format!("/v1/filebox/{long_code}"))
+            .peer_addr("127.0.0.1:40000".parse().unwrap())
+            .to_request();
+        let resp = test::call_service(&app, long_req).await;
+        assert_eq!(resp.status(), 404);
+
+        let (events, _) = repo
+            .list_filebox_events(&FileboxEventFilter::default(), 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].event_type, FileboxEventType::LookupFailed);
+        assert_eq!(events[0].code, long_code);
+        assert_eq!(events[1].event_type, FileboxEventType::LookupFailed);
+        assert_eq!(events[1].ip, "10.0.0.9");
+        assert_eq!(events[2].event_type, FileboxEventType::Viewed);
+        assert_eq!(events[2].filebox_id, Some(new_filebox.id));
 
         // let take_filebox_req = test::TestRequest::post().uri(uri).to_request();
         // let take_filebox: TakeTextResponse =
         //     test::call_and_read_body_json(&app, take_filebox_req).await;
         // assert!(take_filebox.used_at > 0);
     }
+    #[actix_web::test]
+    async fn test_take_filebox_outcomes() {
+        let repo: Arc<dyn FileboxRepository> = Arc::new(MemoryFileboxRepository::new());
+        let app = create_test_app(repo.clone()).await;
+
+        let now = Utc::now();
+        for (code, expired_at) in [("taken", now.add(Duration::days(1))), ("expd1", now)] {
+            repo.add_filebox(AddFilebox {
+                code: code.to_string(),
+                name: "note".to_string(),
+                text: "hi".to_string(),
+                created_at: now.add(Duration::days(-1)),
+                expired_at,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        }
+
+        let take = |code: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/v1/filebox/{code}"))
+                .to_request()
+        };
+        let resp = test::call_service(&app, take("taken")).await;
+        assert_eq!(resp.status(), 200);
+
+        // GET 与 POST 对同一个口令给出同样的错误
+        for (code, status, name) in [
+            ("taken", 409, "ALREADY_TAKEN"),
+            ("expd1", 410, "EXPIRED"),
+            ("zzzzz", 404, "NOT_FOUND"),
+        ] {
+            let get = test::TestRequest::get()
+                .uri(&format!("/v1/filebox/{code}"))
+                .to_request();
+            for req in [get, take(code)] {
+                let resp = test::call_service(&app, req).await;
+                assert_eq!(resp.status(), status);
+                let err: ErrorResponse = test::read_body_json(resp).await;
+                assert_eq!((err.code, err.error.as_str()), (status, name));
+            }
+        }
+
+        // a right code is not a guess, only the unknown one counts
+        let filter = FileboxEventFilter {
+            event_type: Some(FileboxEventType::LookupFailed),
+            ..Default::default()
+        };
+        let (events, _) = repo.list_filebox_events(&filter, 10, 0).await.unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|event| event.code == "zzzzz"));
+    }
+
+    #[actix_web::test]
+    async fn test_error_message_language() {
+        let app = create_test_app(Arc::new(MemoryFileboxRepository::new())).await;
+
+        for (uri, accept_language, message) in [
+            ("/v1/filebox/zzzzz", None, "not found"),
+            (
+                "/v1/filebox/zzzzz",
+                Some("zh-CN,zh;q=0.9,en;q=0.8"),
+                "文件不存在",
+            ),
+            ("/v1/filebox/zzzzz?lang=en", Some("zh-CN"), "not found"),
+            ("/v1/filebox/zzzzz?lang=zh-CN", None, "文件不存在"),
+        ] {
+            let mut req = test::TestRequest::get().uri(uri);
+            if let Some(accept_language) = accept_language {
+                req = req.insert_header((header::ACCEPT_LANGUAGE, accept_language));
+            }
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert_eq!(resp.status(), 404);
+            let err: ErrorResponse = test::read_body_json(resp).await;
+            assert_eq!(err.error, "NOT_FOUND");
+            assert_eq!(err.message, message);
+        }
+
+        // 提取器拒绝的请求同样翻译
+        let req = test::TestRequest::post()
+            .uri("/v1/filebox/text?lang=zh")
+            .insert_header(header::ContentType::json())
+            .set_payload("{not json")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        let err: ErrorResponse = test::read_body_json(resp).await;
+        assert_eq!(
+            (err.error.as_str(), err.message.as_str()),
+            ("VALIDATE_ARGS_ERROR", "输入参数有误")
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_rejected_input_is_error_response() {
+        let app = create_test_app(Arc::new(MemoryFileboxRepository::new())).await;
+
+        let bad_json = test::TestRequest::post()
+            .uri("/v1/filebox/text")
+            .insert_header(header::ContentType::json())
+            .set_payload("{not json")
+            .to_request();
+        let bad_form = test::TestRequest::post()
+            .uri("/v1/filebox")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=filebox",
+            ))
+            .set_payload("--filebox--\r\n")
+            .to_request();
+        for (req, name) in [
+            (bad_json, "VALIDATE_ARGS_ERROR"),
+            (bad_form, "MULTIPART_ERROR"),
+        ] {
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 400);
+            let err: ErrorResponse = test::read_body_json(resp).await;
+            assert_eq!((err.code, err.error.as_str()), (400, name));
+        }
+    }
+    fn file_form(size: usize) -> actix_http::Request {
+        let mut body = Vec::new();
+        for (name, value) in [("name", "a.bin"), ("duration_day", "1"), ("file_type", "1")] {
+            body.extend(
+                format!("--filebox\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n")
+                    .as_bytes(),
+            );
+        }
+        body.extend(b"--filebox\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\nContent-Type: application/octet-stream\r\n\r\n");
+        body.extend(vec![b'x'; size]);
+        body.extend(b"\r\n--filebox--\r\n");
+        test::TestRequest::post()
+            .uri("/v1/filebox")
+            .insert_header((
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=filebox",
+            ))
+            .set_payload(body)
+            .to_request()
+    }
+
+    #[actix_web::test]
+    async fn test_multipart_upload_follows_reloaded_max_upload_size() {
+        let limits = UploadLimits {
+            max_upload_size: 1024,
+            ..Default::default()
+        };
+        let app =
+            create_test_app_with_upload_limits(Arc::new(MemoryFileboxRepository::new()), limits)
+                .await;
+
+        let resp = test::call_service(&app, file_form(1024)).await;
+        assert_eq!(resp.status(), 200);
+
+        // 超出 max_upload_size 但仍在表单余量内, 读完后由 validate 拒绝
+        let resp = test::call_service(&app, file_form(2048)).await;
+        assert_eq!(resp.status(), 400);
+        let err: ErrorResponse = test::read_body_json(resp).await;
+        assert_eq!(err.error, "INPUT_VALIDATE_ERROR");
+
+        // 远超时读取中途即停止, 不会落盘到上限 MAX_UPLOAD_SIZE_CEILING
+        let resp = test::call_service(&app, file_form(1024 * 1024)).await;
+        let status = resp.status();
+        let err: ErrorResponse = test::read_body_json(resp).await;
+        assert_eq!(
+            (status.as_u16(), err.error.as_str()),
+            (400, "MULTIPART_ERROR")
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_create_text_filebox_by_json() {
+        let app = create_test_app(Arc::new(MemoryFileboxRepository::new())).await;
+
+        let body = CreateTextFileboxRequest {
+            name: "note".to_string(),
+            text: "hello from a script".to_string(),
+            duration_day: 1,
+        };
+        let req = test::TestRequest::post()
+            .uri("/v1/filebox/text")
+            .set_json(&body)
+            .to_request();
+        let created: CreateFileboxResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(created.name, "note");
+        assert_eq!(created.file_type, FileboxFileType::Text);
+
+        let uri = format!("/v1/filebox/{}", created.code);
+        let take_req = test::TestRequest::post().uri(&uri).to_request();
+        let taken = test::call_and_read_body(&app, take_req).await;
+        assert_eq!(taken, "hello from a script");
+
+        // the json body shares validation with the multipart form
+        let invalid = CreateTextFileboxRequest {
+            text: String::new(),
+            ..body
+        };
+        let req = test::TestRequest::post()
+            .uri("/v1/filebox/text")
+            .set_json(&invalid)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400);
+    }
+    #[actix_web::test]
+    async fn test_create_raw_filebox() {
+        let app = create_test_app(Arc::new(MemoryFileboxRepository::new())).await;
+
+        // curl 默认 Accept: */*, 只返回口令
+        let req = test::TestRequest::put()
+            .uri("/v1/filebox/raw/build.log")
+            .insert_header((DURATION_DAY_HEADER, "2"))
+            .set_payload("line 1\nline 2\n")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let body = test::read_body(resp).await;
+        let code = std::str::from_utf8(&body).unwrap().trim().to_string();
+
+        let uri = format!("/v1/filebox/{code}");
+        let get_req = test::TestRequest::get().uri(&uri).to_request();
+        let filebox: GetFileboxResponse = test::call_and_read_body_json(&app, get_req).await;
+        assert_eq!(filebox.name, "build.log");
+        assert_eq!(filebox.file_type, FileboxFileType::File);
+        assert_eq!(filebox.expired_at - filebox.created_at, 2 * 24 * 60 * 60);
+
+        let take_req = test::TestRequest::post().uri(&uri).to_request();
+        let taken = test::call_and_read_body(&app, take_req).await;
+        assert_eq!(taken, "line 1\nline 2\n");
+
+        let req = test::TestRequest::put()
+            .uri("/v1/filebox/raw/notes.txt?duration_day=1")
+            .insert_header((header::ACCEPT, "application/json"))
+            .set_payload("hi")
+            .to_request();
+        let created: CreateFileboxResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(created.name, "notes.txt");
+
+        for uri in [
+            "/v1/filebox/raw/empty.txt",
+            "/v1/filebox/raw/notes.txt?max_downloads=3",
+            "/v1/filebox/raw/notes.txt?duration_day=200",
+        ] {
+            let payload = if uri.contains("empty") { "" } else { "hi" };
+            let req = test::TestRequest::put()
+                .uri(uri)
+                .set_payload(payload)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 400, "{uri}");
+        }
+    }
+
+    async fn create_raw_filebox_with_long_name(repo: Arc<dyn FileboxRepository>) {
+        let app = create_test_app(repo).await;
+
+        // 接口允许 50 字节的名称, 数据库的列也要放得下
+        let name = format!("{}.log", "a".repeat(36));
+        assert_eq!(name.len(), 40);
+        let req = test::TestRequest::put()
+            .uri(&format!("/v1/filebox/raw/{name}?duration_day=1"))
+            .insert_header((header::ACCEPT, "application/json"))
+            .set_payload("hi")
+            .to_request();
+        let created: CreateFileboxResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(created.name, name);
+    }
+
+    #[actix_web::test]
+    async fn test_create_raw_filebox_with_long_name_on_postgres() {
+        let tdb = get_tdb();
+        let db_pool = tdb.get_pool().await;
+        create_raw_filebox_with_long_name(Arc::new(PgFileboxRepository::new(db_pool))).await;
+    }
+
+    #[actix_web::test]
+    async fn test_create_raw_filebox_with_long_name_on_sqlite() {
+        let pool = get_sqlite_pool().await;
+        create_raw_filebox_with_long_name(Arc::new(SqliteFileboxRepository::new(pool))).await;
+    }
 }
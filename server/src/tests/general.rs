@@ -1,18 +1,98 @@
 #[cfg(test)]
 mod tests {
 
-    use crate::test_utils::{create_test_app, get_tdb};
+    use std::sync::Arc;
 
-    use actix_web::test;
+    use crate::{
+        api::{ComponentStatus, ProbeResponse, REQUEST_ID_HEADER},
+        data::{memory::MemoryFileboxRepository, repository::FileboxRepository},
+        metrics::Metrics,
+        models::filebox::AddFilebox,
+        scheduler::refresh_usage_metrics,
+        test_utils::{create_test_app, TEST_ADMIN_TOKEN},
+    };
+
+    use actix_web::{http::header, test};
+    use chrono::{Duration, Utc};
 
     #[actix_web::test]
     async fn test_health() {
-        let tdb = get_tdb();
-        let db_pool = tdb.get_pool().await;
-        let app = create_test_app(&db_pool).await;
+        let app = create_test_app(Arc::new(MemoryFileboxRepository::new())).await;
 
         let req = test::TestRequest::get().uri("/health").to_request();
         let resp = test::call_service(&app, req).await;
         assert!(resp.status().is_success());
     }
+
+    #[actix_web::test]
+    async fn test_request_id() {
+        let app = create_test_app(Arc::new(MemoryFileboxRepository::new())).await;
+
+        let req = test::TestRequest::get()
+            .uri("/health")
+            .insert_header((REQUEST_ID_HEADER, "abc-123"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get(REQUEST_ID_HEADER).unwrap(), "abc-123");
+
+        let req = test::TestRequest::get().uri("/health").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get(REQUEST_ID_HEADER).unwrap().len(), 36);
+    }
+
+    #[actix_web::test]
+    async fn test_probes() {
+        let app = create_test_app(Arc::new(MemoryFileboxRepository::new())).await;
+
+        let req = test::TestRequest::get().uri("/health/live").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+
+        let req = test::TestRequest::get().uri("/health/ready").to_request();
+        let resp: ProbeResponse = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(resp.status, ComponentStatus::Up);
+        assert_eq!(resp.components["memory"].status, ComponentStatus::Up);
+        assert_eq!(resp.components["storage"].status, ComponentStatus::Up);
+        // the test app runs without redis
+        assert!(!resp.components.contains_key("redis"));
+    }
+
+    #[actix_web::test]
+    async fn test_metrics() {
+        let app = create_test_app(Arc::new(MemoryFileboxRepository::new())).await;
+
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 401);
+
+        let req = test::TestRequest::get()
+            .uri("/metrics")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {TEST_ADMIN_TOKEN}")))
+            .to_request();
+        let body = test::call_and_read_body(&app, req).await;
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("filebox_active_fileboxes 0"));
+        assert!(body.contains("filebox_storage_used_bytes 0"));
+    }
+
+    #[actix_web::test]
+    async fn test_refresh_usage_metrics() {
+        let repo = MemoryFileboxRepository::new();
+        let now = Utc::now();
+        repo.add_filebox(AddFilebox {
+            code: "abcde".to_string(),
+            name: "note".to_string(),
+            text: "hello".to_string(),
+            created_at: now,
+            expired_at: now + Duration::days(1),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let metrics = Metrics::new();
+        refresh_usage_metrics(&repo, &metrics).await;
+        let text = metrics.encode();
+        assert!(text.contains("filebox_active_fileboxes 1"));
+    }
 }
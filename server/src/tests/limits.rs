@@ -0,0 +1,109 @@
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, sync::Arc};
+
+    use actix_web::{http::header, test};
+
+    use crate::{
+        api::{CreateTextFileboxRequest, IP_VISIT_ERROR_REMAINING_HEADER},
+        data::{
+            limiter::get_ip_visit_error_count,
+            memory::MemoryFileboxRepository,
+            redis::{parse_ip_net_list, IpRules},
+        },
+        test_utils::{create_limited_test_app, create_test_cache_state},
+    };
+
+    fn lookup(code: &str, peer: &str, real_ip: Option<&str>) -> actix_http::Request {
+        let mut req = test::TestRequest::get()
+            .uri(&format!("/v1/filebox/{code}"))
+            .peer_addr(peer.parse::<SocketAddr>().unwrap());
+        if let Some(real_ip) = real_ip {
+            req = req.insert_header(("X-REAL-IP", real_ip));
+        }
+        req.to_request()
+    }
+
+    #[actix_web::test]
+    async fn test_allowed_ip_bypasses_visit_error_limit() {
+        let allow = parse_ip_net_list("10.0.0.0/8").unwrap();
+        let cache_state = create_test_cache_state(IpRules::new(allow, vec![]), 1);
+        let app = create_limited_test_app(
+            Arc::new(MemoryFileboxRepository::new()),
+            cache_state.clone(),
+        )
+        .await;
+
+        // 经由可信代理的允许名单 ip 不计数, 也不会被限制
+        for _ in 0..3 {
+            let resp =
+                test::call_service(&app, lookup("zzzzz", "127.0.0.1:4000", Some("10.1.2.3"))).await;
+            assert_eq!(resp.status(), 404);
+        }
+        let limiter = cache_state.ip_limiter.as_ref();
+        assert_eq!(
+            get_ip_visit_error_count(limiter, "10.1.2.3").await.unwrap(),
+            0
+        );
+
+        // 直连的客户端自带 X-REAL-IP 不被信任, 按对端地址计数
+        let spoofed = lookup("zzzzz", "192.0.2.7:4000", Some("10.1.2.3"));
+        assert_eq!(test::call_service(&app, spoofed).await.status(), 404);
+        let spoofed = lookup("zzzzz", "192.0.2.7:4000", Some("10.1.2.3"));
+        assert_eq!(test::call_service(&app, spoofed).await.status(), 403);
+        assert_eq!(
+            get_ip_visit_error_count(limiter, "192.0.2.7")
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    fn remaining(resp: &actix_web::dev::ServiceResponse) -> &str {
+        resp.headers()
+            .get(IP_VISIT_ERROR_REMAINING_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_validation_error_keeps_visit_error_remaining() {
+        let cache_state = create_test_cache_state(IpRules::new(vec![], vec![]), 2);
+        let app =
+            create_limited_test_app(Arc::new(MemoryFileboxRepository::new()), cache_state).await;
+
+        // 输入校验失败不是口令错误, 不消耗次数
+        let invalid = CreateTextFileboxRequest {
+            name: "note".to_string(),
+            text: String::new(),
+            duration_day: 1,
+        };
+        for _ in 0..3 {
+            let req = test::TestRequest::post()
+                .uri("/v1/filebox/text")
+                .peer_addr("192.0.2.8:4000".parse().unwrap())
+                .set_json(&invalid)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 400);
+            assert_eq!(remaining(&resp), "2");
+        }
+        let bad_json = test::TestRequest::post()
+            .uri("/v1/filebox/text")
+            .peer_addr("192.0.2.8:4000".parse().unwrap())
+            .insert_header(header::ContentType::json())
+            .set_payload("{not json")
+            .to_request();
+        let resp = test::call_service(&app, bad_json).await;
+        assert_eq!(resp.status(), 400);
+        assert_eq!(remaining(&resp), "2");
+
+        let resp = test::call_service(&app, lookup("zzzzz", "192.0.2.8:4000", None)).await;
+        assert_eq!((resp.status().as_u16(), remaining(&resp)), (404, "1"));
+        let resp = test::call_service(&app, lookup("zzzzz", "192.0.2.8:4000", None)).await;
+        assert_eq!((resp.status().as_u16(), remaining(&resp)), (404, "0"));
+        let resp = test::call_service(&app, lookup("zzzzz", "192.0.2.8:4000", None)).await;
+        assert_eq!((resp.status().as_u16(), remaining(&resp)), (403, "0"));
+    }
+}
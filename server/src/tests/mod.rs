@@ -1,2 +1,6 @@
+mod admin;
+mod cli;
 mod filebox;
 mod general;
+mod limits;
+mod openapi;
@@ -0,0 +1,36 @@
+#[cfg(test)]
+mod tests {
+
+    use std::sync::Arc;
+
+    use crate::{data::memory::MemoryFileboxRepository, test_utils::create_test_app};
+
+    use actix_web::test;
+
+    const SNAPSHOT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+
+    /// Fails when the served spec drifts from the committed `openapi.json`,
+    /// run with `UPDATE_OPENAPI_SNAPSHOT=1` to rewrite the snapshot.
+    #[actix_web::test]
+    async fn test_openapi_snapshot() {
+        let app = create_test_app(Arc::new(MemoryFileboxRepository::new())).await;
+
+        let req = test::TestRequest::get().uri("/openapi.json").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert!(resp.status().is_success());
+        let served: serde_json::Value = test::read_body_json(resp).await;
+
+        if std::env::var("UPDATE_OPENAPI_SNAPSHOT").as_deref() == Ok("1") {
+            let mut text = serde_json::to_string_pretty(&served).unwrap();
+            text.push('\n');
+            std::fs::write(SNAPSHOT_PATH, text).unwrap();
+        }
+
+        let snapshot: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(SNAPSHOT_PATH).unwrap()).unwrap();
+        assert_eq!(
+            served, snapshot,
+            "openapi.json is out of date, rerun with UPDATE_OPENAPI_SNAPSHOT=1"
+        );
+    }
+}